@@ -0,0 +1,317 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    result,
+};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    lib::{installs::Installs, task, version},
+    utils::{
+        self,
+        fs::{mkdtemp, set_executable},
+        platform::Platform,
+    },
+};
+
+const MANIFEST: &str = "manifest.toml";
+
+pub type Result = result::Result<(), task::Error>;
+
+// Bundle collects every tool for a target platform,
+// so that `install --from-bundle` works without network access
+pub struct Bundle {
+    dir: PathBuf,
+    manifest: Manifest,
+    pub platform: Platform,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub version: String,
+    pub kind: EntryKind,
+    pub path: String, // within the bundle
+    pub dest: String, // within $HOME, which for Versioned is the link to the active version
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Executable,
+    File,
+    GitRepo,
+    // Versioned is a directory that we install alongside other versions of `name`,
+    // as if for Installs
+    Versioned,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    pub platform: String,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+impl Bundle {
+    pub fn new(platform: Platform) -> io::Result<Bundle> {
+        Ok(Bundle {
+            dir: mkdtemp()?,
+            manifest: Manifest {
+                platform: platform.to_string(),
+                entries: Vec::new(),
+            },
+            platform,
+        })
+    }
+
+    pub fn add(&mut self, entry: Entry) {
+        println!("bundle: {}: {}", &entry.name, &entry.version);
+        self.manifest.entries.push(entry);
+    }
+
+    // add_git() bundles a shallow clone, including its history,
+    // so that installing it leaves a repository that we can update later
+    pub fn add_git<S>(&mut self, name: S, url: S, dest: S) -> Result
+    where
+        S: AsRef<str>,
+    {
+        let path = format!("{}/repo", name.as_ref());
+        let clone_path = self.path(&path)?;
+        utils::git::shallow_clone(url.as_ref(), &clone_path.to_string_lossy())?;
+        if !utils::git::path_is_git_repository(&clone_path) {
+            return Err(task::Error::Io(
+                format!("unable to clone {}", url.as_ref()),
+                io::Error::from(io::ErrorKind::NotFound),
+            ));
+        }
+        let version = utils::git::head_commit(&clone_path)?;
+        self.add(Entry {
+            name: String::from(name.as_ref()),
+            version,
+            kind: EntryKind::GitRepo,
+            path,
+            dest: String::from(dest.as_ref()),
+        });
+        Ok(())
+    }
+
+    // path() is where to put `relative` in the bundle,
+    // with parent directories already created
+    pub fn path<S>(&self, relative: S) -> io::Result<PathBuf>
+    where
+        S: AsRef<str>,
+    {
+        let p = self.dir.join(relative.as_ref());
+        if let Some(parent) = p.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(p)
+    }
+
+    pub fn write<P>(self, target: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let manifest = toml::to_string(&self.manifest).map_err(io::Error::other)?;
+        fs::write(self.dir.join(MANIFEST), manifest)?;
+
+        let file = File::create(target.as_ref())?;
+        let encoder = libflate::gzip::Encoder::new(BufWriter::new(file))?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        builder.append_dir_all(".", &self.dir)?;
+        builder.into_inner()?.finish().into_result()?;
+
+        utils::fs::delete_if_exists(&self.dir);
+        Ok(())
+    }
+}
+
+// install() applies a bundle from `source`, without any network access
+pub fn install<P>(source: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let staging = staging_dir();
+    utils::fs::delete_if_exists(&staging);
+    fs::create_dir_all(&staging)?;
+
+    utils::archive::extract(source.as_ref(), &staging, MANIFEST)?;
+
+    let manifest = read_manifest(&staging)?;
+    let current = Platform::current();
+    match manifest.platform.parse::<Platform>() {
+        Ok(p) if is_compatible(&p, current) => {}
+        _ => {
+            utils::fs::delete_if_exists(&staging);
+            return Err(io::Error::other(format!(
+                "bundle is for {}, but this is {}",
+                &manifest.platform, current
+            )));
+        }
+    }
+
+    let home = utils::env::home_dir();
+    for entry in &manifest.entries {
+        let path = staging.join(&entry.path);
+        let dest = home.join(&entry.dest);
+        match install_entry(entry, &path, &dest) {
+            Ok(()) => println!("{}: {} -> {}", &entry.name, &entry.version, dest.display()),
+            Err(error) => println!("{}: install error: {:?}", &entry.name, error),
+        }
+    }
+
+    utils::fs::delete_if_exists(&staging);
+    Ok(())
+}
+
+fn install_entry(entry: &Entry, path: &Path, dest: &Path) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match entry.kind {
+        EntryKind::Executable | EntryKind::File => {
+            utils::fs::delete_if_exists(dest);
+            fs::copy(path, dest)?;
+            if entry.kind == EntryKind::Executable {
+                set_executable(dest)?;
+            }
+        }
+        // keep local changes and untracked files, as `git pull` would
+        EntryKind::GitRepo if utils::git::path_is_git_repository(dest) => {
+            utils::git::fetch_from(dest, path)?;
+        }
+        EntryKind::GitRepo => {
+            utils::fs::delete_if_exists(dest);
+            fs::rename(path, dest)?;
+        }
+        EntryKind::Versioned => {
+            let installs = Installs::new(&entry.name, dest, |s, v| version::matches(s, v));
+            install_versioned(&installs, &entry.version, path)?;
+        }
+    }
+    Ok(())
+}
+
+// install_versioned() moves `path` into place for `version`, and makes it the active version,
+// leaving other versions for a later sync to prune
+fn install_versioned(installs: &Installs, version: &str, path: &Path) -> io::Result<()> {
+    installs.install(version, |dir| {
+        fs::remove_dir(dir)?;
+        fs::rename(path, dir)
+    })?;
+    installs.activate(version)
+}
+
+// is_compatible() ignores the glibc version,
+// as we only use it to pick between otherwise equal assets
+fn is_compatible(bundle: &Platform, current: &Platform) -> bool {
+    bundle.os == current.os && bundle.arch == current.arch && bundle.libc == current.libc
+}
+
+fn read_manifest(dir: &Path) -> io::Result<Manifest> {
+    let contents = fs::read_to_string(dir.join(MANIFEST))?;
+    toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// staging_dir() is within $HOME,
+// so that installing a tree is just a rename
+fn staging_dir() -> PathBuf {
+    utils::env::home_dir().join(".local/share/jokeyrhyme-dotfiles/bundle")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::platform::{Arch, Libc, Os};
+
+    use super::*;
+
+    #[test]
+    fn manifest_round_trips_as_toml() {
+        let manifest = Manifest {
+            platform: String::from("linux-aarch64-musl"),
+            entries: vec![Entry {
+                name: String::from("jq"),
+                version: String::from("jq-1.6"),
+                kind: EntryKind::Executable,
+                path: String::from("bin/jq"),
+                dest: String::from(".local/bin/jq"),
+            }],
+        };
+        let s = toml::to_string(&manifest).expect("must serialise");
+        assert!(s.contains("kind = \"executable\""));
+        let parsed: Manifest = toml::from_str(&s).expect("must parse");
+        assert_eq!(parsed, manifest);
+    }
+
+    #[test]
+    fn write_then_read_manifest() {
+        let platform = "linux-armv7".parse::<Platform>().expect("must parse");
+        let mut bundle = Bundle::new(platform).expect("must create");
+        let plug = bundle.path("vim/plug.vim").expect("must create dirs");
+        fs::write(&plug, "\" vim-plug").expect("must write");
+        bundle.add(Entry {
+            name: String::from("vim"),
+            version: String::from("master"),
+            kind: EntryKind::File,
+            path: String::from("vim/plug.vim"),
+            dest: String::from(".vim/autoload/plug.vim"),
+        });
+
+        let target = utils::fs::mkftemp().expect("must create");
+        bundle.write(&target).expect("must write");
+
+        let extracted = mkdtemp().expect("must create");
+        let decoder = libflate::gzip::Decoder::new(File::open(&target).expect("must open"))
+            .expect("must decode");
+        tar::Archive::new(decoder)
+            .unpack(&extracted)
+            .expect("must unpack");
+        let manifest = read_manifest(&extracted).expect("must read");
+        assert_eq!(manifest.platform, "linux-armv7-glibc");
+        assert_eq!(manifest.entries.len(), 1);
+        assert!(extracted.join("vim/plug.vim").is_file());
+
+        utils::fs::delete_if_exists(&target);
+        utils::fs::delete_if_exists(&extracted);
+    }
+
+    #[test]
+    fn install_versioned_activates_bundled_version() {
+        let temp_path = mkdtemp().expect("must create");
+        let installs = Installs::in_dir(temp_path.join("share/golang"), temp_path.join("go"));
+        for version in &["go1.15.4", "go1.15.5"] {
+            let path = temp_path.join("bundle").join(version);
+            fs::create_dir_all(path.join("bin")).expect("must create dirs");
+            fs::write(path.join("VERSION"), version).expect("must write");
+            install_versioned(&installs, version, &path).expect("must install");
+        }
+        assert_eq!(installs.current(), Some(String::from("go1.15.5")));
+        assert_eq!(installs.versions(), vec!["go1.15.4", "go1.15.5"]);
+        assert_eq!(
+            fs::read_to_string(temp_path.join("go/VERSION")).expect("must read"),
+            "go1.15.5"
+        );
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn is_compatible_ignores_glibc_version() {
+        let linux = |arch, libc| Platform {
+            arch,
+            glibc_version: None,
+            libc,
+            os: Os::Linux,
+        };
+        let mut current = linux(Arch::X86_64, Libc::Glibc);
+        current.glibc_version = crate::lib::version::Version::parse("2.31");
+        assert!(is_compatible(&linux(Arch::X86_64, Libc::Glibc), &current));
+        assert!(!is_compatible(&linux(Arch::X86_64, Libc::Musl), &current));
+        assert!(!is_compatible(&linux(Arch::Aarch64, Libc::Glibc), &current));
+    }
+}
@@ -42,7 +42,7 @@ impl From<&ResponseMetadata> for String {
     }
 }
 
-pub fn load_response_body(url: &Url) -> io::Result<impl Read> {
+pub fn load_response_body(url: &Url) -> io::Result<File> {
     File::open(url_body_path(url))
 }
 
@@ -72,6 +72,25 @@ pub fn store_response(url: &Url, mut res: Response) -> io::Result<()> {
     fs::write(fp, String::from(&rm))
 }
 
+// partial_path() is where we keep an incomplete download,
+// so that we can resume it later, even after restarting
+pub fn partial_path(url: &Url) -> PathBuf {
+    url_body_path(url).with_extension("partial")
+}
+
+// store_partial() promotes a completed download from partial_path()
+pub fn store_partial(url: &Url) -> io::Result<()> {
+    let body_path = url_body_path(url);
+    fs::rename(partial_path(url), &body_path)?;
+
+    let rm = ResponseMetadata {
+        content_length: fs::metadata(&body_path)?.len(),
+        date: Utc::now(),
+        headers: Vec::<String>::new(),
+    };
+    fs::write(url_metadata_path(url), String::from(&rm))
+}
+
 const URL_TOO_LONG: usize = 100;
 
 fn url_filename(url: &Url) -> String {
@@ -131,6 +150,15 @@ mod tests {
         assert_eq!(got, "https___supercalifragilisticexpialidocious_example_com_supercalifragilisticexpialidocious_supercalif_fd5902017a34d8026b288be08eeda03224dfc6550f0837343e80bf92dc127071");
     }
 
+    #[test]
+    fn partial_path_is_beside_body() {
+        let url =
+            Url::parse("https://example.com/go1.15.2.linux-amd64.tar.gz").expect("must parse");
+        let got = partial_path(&url);
+        assert_eq!(got.parent(), url_body_path(&url).parent());
+        assert_eq!(got.extension().unwrap_or_default(), "partial");
+    }
+
     #[test]
     fn url_metadata_path_works() {
         let url = Url::parse("https://example.com/").expect("must parse");
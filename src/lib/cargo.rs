@@ -1,16 +1,54 @@
-use std::{collections::HashMap, env::consts::OS, io, path::PathBuf, str};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env::consts::{EXE_SUFFIX, OS},
+    fs, io,
+    path::{Path, PathBuf},
+    str,
+};
 
-use serde_derive::Deserialize;
+use semver::{Version, VersionReq};
+use serde_derive::{Deserialize, Serialize};
 
 use crate::{
-    lib::{favourites::Favourites, rust::bin_dir},
-    utils::process::{command_output, command_spawn_wait},
+    lib::{
+        favourites::Favourites,
+        ghratask::{Destination, GHRATask},
+        rust::{bin_dir, cargo_home},
+        version,
+    },
+    utils::{
+        self, cratesio,
+        env::home_dir,
+        forge::Forge,
+        github::{GitHubError, Release},
+        process::{command_output, command_spawn_wait},
+    },
 };
 
-#[derive(Debug, Deserialize)]
+// PREBUILT records which crates we installed from GitHub Releases,
+// alongside the .crates2.json that `cargo install` keeps
+const PREBUILT: &str = ".crates-prebuilt.toml";
+
+#[derive(Debug, Default, Deserialize)]
 pub struct CargoFavourites {
-    install: Vec<String>,
-    uninstall: Vec<String>,
+    #[serde(default)]
+    install: Vec<Krate>,
+    // locked is `cargo install --locked` for every crate that does not say otherwise
+    #[serde(default)]
+    locked: bool,
+    #[serde(default)]
+    uninstall: Vec<Krate>,
+}
+
+impl CargoFavourites {
+    // find() is the `install` entry for the crate with `name`
+    pub fn find(&self, name: &str) -> Option<&Krate> {
+        self.install.iter().find(|k| k.name() == name)
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
 }
 
 impl Favourites for CargoFavourites {
@@ -20,37 +58,420 @@ impl Favourites for CargoFavourites {
             return Ok(());
         }
 
+        let mut prebuilt = read_prebuilt();
+        for name in &surplus {
+            if let Some(p) = prebuilt.remove(name) {
+                p.delete_files();
+            }
+        }
+        write_prebuilt(&prebuilt)?;
+
+        let installed = found_versions();
         let mut args = vec![String::from("uninstall")];
-        args.extend(surplus);
-        cargo(&args)
+        args.extend(surplus.into_iter().filter(|s| installed.contains_key(s)));
+        if args.len() > 1 {
+            cargo(&args)?;
+        }
+        Ok(())
     }
     fn fill(&mut self) -> io::Result<()> {
         let missing = self.missing();
-        if missing.is_empty() {
-            return Ok(());
-        }
+        let installed = found_versions();
+        let mut prebuilt = read_prebuilt();
+        for krate in &self.install {
+            let name = krate.name();
+            if !missing.contains(&name) {
+                continue;
+            }
 
-        let mut args = vec![String::from("install")];
-        args.extend(missing);
-        cargo(&args)
+            if let Some(p) = latest_prebuilt(krate) {
+                if installed.contains_key(&name) {
+                    cargo(&["uninstall", &name])?;
+                }
+                prebuilt.insert(name, p);
+                write_prebuilt(&prebuilt)?;
+                continue;
+            }
+
+            if let Some(p) = prebuilt.remove(&name) {
+                p.delete_files();
+                write_prebuilt(&prebuilt)?;
+            }
+            let mut args = krate.install_args(self.locked);
+            // installed, but not with the version, features, or source that we want
+            if installed.contains_key(&name) {
+                args.push(String::from("--force"));
+            }
+            cargo(&args)?;
+        }
+        Ok(())
     }
     fn found(&self) -> Vec<String> {
-        found_versions().keys().cloned().collect()
+        let mut found: Vec<String> = read_installed()
+            .into_iter()
+            .filter(|i| self.find(&i.name).is_none_or(|k| k.is_satisfied_by(i)))
+            .map(|i| i.name)
+            .collect();
+        for (name, p) in read_prebuilt() {
+            let wanted = self
+                .find(&name)
+                .is_none_or(|k| k.allows_prebuilt() && k.accepts_version(&p.version));
+            if wanted && p.has_bins() && !found.contains(&name) {
+                found.push(name);
+            }
+        }
+        found
     }
     fn wanted(&self) -> Vec<String> {
-        self.install.clone()
+        self.install.iter().map(|k| k.name()).collect()
     }
     fn unwanted(&self) -> Vec<String> {
-        self.uninstall.clone()
+        self.uninstall.iter().map(|k| k.name()).collect()
     }
 }
 
-pub fn found_versions() -> HashMap<String, String> {
-    if !has_cargo() {
-        return HashMap::<String, String>::new();
+// Krate is a crate name, e.g. "ripgrep",
+// or a table, e.g. `{ name = "ripgrep", version = "12", features = ["pcre2"] }`,
+// `{ git = "https://github.com/...", branch = "main" }`, or `{ path = "~/src/..." }`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Krate {
+    Name(String),
+    Spec(Box<KrateSpec>),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct KrateSpec {
+    pub all_features: bool,
+    // bins are the executables that a GitHub Release archive must have,
+    // and default to just the crate name
+    pub bins: Vec<String>,
+    pub branch: Option<String>,
+    pub features: Vec<String>,
+    pub git: Option<String>,
+    pub locked: Option<bool>,
+    // name defaults to the last part of `git` or `path`
+    pub name: Option<String>,
+    pub no_default_features: bool,
+    pub path: Option<String>,
+    // prebuilt = false always builds with `cargo install`,
+    // otherwise we prefer binaries from the crate's GitHub Releases
+    pub prebuilt: Option<bool>,
+    // repo is e.g. "BurntSushi/ripgrep" on GitHub, or a URL on another forge,
+    // e.g. "https://gitlab.com/owner/tool", when crates.io does not have it
+    pub repo: Option<String>,
+    pub rev: Option<String>,
+    pub tag: Option<String>,
+    pub target: Option<String>,
+    pub version: Option<String>,
+}
+
+impl Krate {
+    pub fn name(&self) -> String {
+        let spec = match self {
+            Krate::Name(name) => return name.clone(),
+            Krate::Spec(spec) => spec,
+        };
+        if let Some(name) = &spec.name {
+            return name.clone();
+        }
+        let source = spec.git.as_deref().or(spec.path.as_deref()).unwrap_or("");
+        let last = trim_git_url(source)
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default();
+        String::from(last)
+    }
+
+    // install_args() are for `cargo install`, e.g. ["install", "--locked", "ripgrep"]
+    pub fn install_args(&self, locked: bool) -> Vec<String> {
+        let mut args = vec![String::from("install")];
+        let spec = match self {
+            Krate::Name(name) => {
+                if locked {
+                    args.push(String::from("--locked"));
+                }
+                args.push(name.clone());
+                return args;
+            }
+            Krate::Spec(spec) => spec,
+        };
+
+        if spec.locked.unwrap_or(locked) {
+            args.push(String::from("--locked"));
+        }
+        let mut flag = |name: &str, value: &Option<String>| {
+            if let Some(v) = value {
+                args.push(format!("--{}", name));
+                args.push(v.clone());
+            }
+        };
+        flag("git", &spec.git);
+        flag("branch", &spec.branch);
+        flag("tag", &spec.tag);
+        flag("rev", &spec.rev);
+        flag("path", &spec.path.as_deref().map(expand_home));
+        flag("version", &spec.version);
+        flag("target", &spec.target);
+        if !spec.features.is_empty() {
+            args.push(String::from("--features"));
+            args.push(spec.features.join(","));
+        }
+        if spec.all_features {
+            args.push(String::from("--all-features"));
+        }
+        if spec.no_default_features {
+            args.push(String::from("--no-default-features"));
+        }
+
+        // a git repository or path with several crates needs the name
+        if spec.name.is_some() || (spec.git.is_none() && spec.path.is_none()) {
+            args.push(self.name());
+        }
+        args
+    }
+
+    // accepts_version() is whether `version` is within the pinned version, if any,
+    // with the same semver rules as `cargo install --version`
+    pub fn accepts_version(&self, version: &str) -> bool {
+        let pin = match self {
+            Krate::Name(_) => return true,
+            Krate::Spec(spec) => match &spec.version {
+                Some(v) => v,
+                None => return true,
+            },
+        };
+        match (self.version_req(), Version::parse(version)) {
+            (Some(req), Ok(v)) => req.matches(&v),
+            _ => version::matches(pin.as_str(), version),
+        }
+    }
+
+    // allows_prebuilt() is false for anything that `cargo install` would build differently
+    pub fn allows_prebuilt(&self) -> bool {
+        match self {
+            Krate::Name(_) => true,
+            Krate::Spec(spec) => {
+                spec.prebuilt.unwrap_or(true)
+                    && self.is_registry()
+                    && spec.features.is_empty()
+                    && !spec.all_features
+                    && !spec.no_default_features
+                    && spec.target.is_none()
+            }
+        }
+    }
+
+    pub fn bins(&self) -> Vec<String> {
+        match self {
+            Krate::Spec(spec) if !spec.bins.is_empty() => spec.bins.clone(),
+            _ => vec![self.name()],
+        }
+    }
+
+    pub fn is_registry(&self) -> bool {
+        match self {
+            Krate::Name(_) => true,
+            Krate::Spec(spec) => spec.git.is_none() && spec.path.is_none(),
+        }
+    }
+
+    // version_req() is the pinned version, e.g. "12" is "^12"
+    pub fn version_req(&self) -> Option<VersionReq> {
+        match self {
+            Krate::Name(_) => None,
+            Krate::Spec(spec) => VersionReq::parse(spec.version.as_deref()?).ok(),
+        }
+    }
+
+    // is_satisfied_by() compares with what .crates2.json says about an installed crate
+    pub fn is_satisfied_by(&self, installed: &Installed) -> bool {
+        let spec = match self {
+            Krate::Name(_) => return installed.is_registry(),
+            Krate::Spec(spec) => spec,
+        };
+
+        let source = if let Some(git) = &spec.git {
+            installed.source.starts_with("git+")
+                && trim_git_url(git) == trim_git_url(installed.git_url())
+                && [
+                    ("branch", &spec.branch),
+                    ("tag", &spec.tag),
+                    ("rev", &spec.rev),
+                ]
+                .iter()
+                .all(|(key, value)| {
+                    value
+                        .as_ref()
+                        .is_none_or(|v| installed.source.contains(&format!("{}={}", key, v)))
+                })
+        } else if let Some(path) = &spec.path {
+            let want = PathBuf::from(expand_home(path));
+            let want = fs::canonicalize(&want).unwrap_or(want);
+            installed.source.strip_prefix("path+file://").map(Path::new) == Some(want.as_path())
+        } else {
+            installed.is_registry()
+        };
+
+        source
+            && self.accepts_version(&installed.version)
+            && spec.features.iter().all(|f| installed.features.contains(f))
+            && spec.all_features == installed.all_features
+            && spec.no_default_features == installed.no_default_features
+            && spec
+                .target
+                .as_ref()
+                .is_none_or(|t| installed.target.as_ref() == Some(t))
+    }
+}
+
+// Installed is an entry in ~/.cargo/.crates2.json
+#[derive(Clone, Debug, PartialEq)]
+pub struct Installed {
+    pub all_features: bool,
+    pub features: Vec<String>,
+    pub name: String,
+    pub no_default_features: bool,
+    // source is e.g. "registry+https://github.com/rust-lang/crates.io-index",
+    // "git+https://github.com/...?branch=main#<commit>", or "path+file:///..."
+    pub source: String,
+    pub target: Option<String>,
+    pub version: String,
+}
+
+impl Installed {
+    // git_url() is the repository in a "git+" `source`, without the branch or commit
+    pub fn git_url(&self) -> &str {
+        let url = self.source.trim_start_matches("git+");
+        url.split(['?', '#']).next().unwrap_or(url)
+    }
+
+    pub fn is_registry(&self) -> bool {
+        self.source.starts_with("registry+") || self.source.starts_with("sparse+")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Crates2 {
+    installs: HashMap<String, Crates2Install>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Crates2Install {
+    #[serde(default)]
+    all_features: bool,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    no_default_features: bool,
+    target: Option<String>,
+}
+
+// Prebuilt is a crate that we installed from a GitHub Release instead of building it,
+// so that `update` installs it the same way again
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Prebuilt {
+    pub bins: Vec<String>,
+    pub repo: String, // e.g. "BurntSushi/ripgrep"
+    pub version: String,
+}
+
+impl Prebuilt {
+    // delete_files() deletes the executables, and any man pages and completions
+    pub fn delete_files(&self) {
+        let home = home_dir();
+        for (file, destination) in prebuilt_files(&self.bins) {
+            utils::fs::delete_if_exists(home.join(destination.dir()).join(file));
+        }
+    }
+
+    fn has_bins(&self) -> bool {
+        self.bins.iter().all(|b| prebuilt_bin(b).is_file())
+    }
+}
+
+// install_prebuilt() installs `version` of `krate` from its GitHub (or GitLab, or Gitea) Release,
+// and is None when there is no such release or no binary for this platform
+pub fn install_prebuilt(krate: &Krate, version: &Version) -> Option<Prebuilt> {
+    if !krate.allows_prebuilt() {
+        return None;
+    }
+    let name = krate.name();
+    let repo = match krate {
+        Krate::Spec(spec) if spec.repo.is_some() => spec.repo.clone()?,
+        _ => cratesio::repository(&name).ok()??,
+    };
+    let (forge, owner, repo) = Forge::from_repository(&repo).or_else(|| {
+        let (owner, repo) = repo.split_once('/')?;
+        Some((Forge::GitHub, owner, repo))
+    })?;
+
+    let releases = forge.fetch_releases(owner, repo).ok()?;
+    let release = release_for_version(releases, &name, &version.to_string())?;
+
+    let bins = krate.bins();
+    let globs = prebuilt_files(&bins);
+    let files: Vec<(&str, Destination)> = globs.iter().map(|(g, d)| (g.as_str(), *d)).collect();
+    let task = GHRATask {
+        asset_filter: None,
+        command: &bins[0],
+        files: &files,
+        forge,
+        repo: (owner, repo),
+        trim_version: |s| s,
+        version_arg: "--version",
+    };
+    match task.install_release(&release) {
+        Ok(()) => Some(Prebuilt {
+            bins,
+            repo: format!("{}/{}", owner, repo),
+            version: version.to_string(),
+        }),
+        Err(GitHubError::AmbiguousAssets(_)) | Err(GitHubError::CompatibleAssetNotFound) => None,
+        Err(error) => {
+            println!("rust: {}: building instead of prebuilt: {:?}", &name, error);
+            None
+        }
+    }
+}
+
+// latest_prebuilt() is install_prebuilt() for the newest version within any pin
+pub fn latest_prebuilt(krate: &Krate) -> Option<Prebuilt> {
+    if !krate.allows_prebuilt() {
+        return None;
+    }
+    let req = krate.version_req();
+    let latest = cratesio::latest_version(&krate.name(), req.as_ref()).ok()??;
+    install_prebuilt(krate, &latest)
+}
+
+pub fn read_prebuilt() -> BTreeMap<String, Prebuilt> {
+    let contents = match fs::read_to_string(cargo_home().join(PREBUILT)) {
+        Ok(s) => s,
+        Err(_error) => return BTreeMap::new(),
     };
-    let stdout = cargo_output(&["install", "--list"]).unwrap_or_default();
-    parse_installed(stdout)
+    toml::from_str(&contents).unwrap_or_default()
+}
+
+pub fn write_prebuilt(prebuilt: &BTreeMap<String, Prebuilt>) -> io::Result<()> {
+    let path = cargo_home().join(PREBUILT);
+    if prebuilt.is_empty() {
+        utils::fs::delete_if_exists(&path);
+        return Ok(());
+    }
+    let contents = toml::to_string(prebuilt).map_err(io::Error::other)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)
+}
+
+pub fn found_versions() -> HashMap<String, String> {
+    read_installed()
+        .into_iter()
+        .map(|i| (i.name, i.version))
+        .collect()
 }
 
 pub fn has_cargo() -> bool {
@@ -64,16 +485,27 @@ where
     command_spawn_wait(cargo_exe(), args).map(|_| ())
 }
 
-pub fn cargo_output<S>(args: &[S]) -> io::Result<String>
-where
-    S: AsRef<str>,
-{
-    let output = command_output(cargo_exe(), args)?;
-    Ok(format!(
-        "{}\n{}",
-        String::from_utf8_lossy(&output.stdout).trim(),
-        String::from_utf8_lossy(&output.stderr).trim(),
-    ))
+// read_installed() trusts .crates2.json, which `cargo install` has written since Rust 1.41
+pub fn read_installed() -> Vec<Installed> {
+    let json_path = cargo_home().join(".crates2.json");
+    let contents = match fs::read_to_string(&json_path) {
+        Ok(s) => s,
+        Err(_error) => {
+            // nothing installed yet
+            return Vec::new();
+        }
+    };
+    match parse_crates2(&contents) {
+        Ok(i) => i,
+        Err(error) => {
+            println!(
+                "warning: rust: unable to parse {}, {}",
+                &json_path.display(),
+                error
+            );
+            Vec::new()
+        }
+    }
 }
 
 fn cargo_exe() -> PathBuf {
@@ -84,47 +516,226 @@ fn cargo_exe() -> PathBuf {
     })
 }
 
-fn parse_installed<S>(stdout: S) -> HashMap<String, String>
-where
-    S: AsRef<str>,
-{
-    let re = regex::Regex::new(r"^(?P<name>\S+)\sv(?P<version>\S+):").unwrap();
-    let s = stdout.as_ref();
-    let mut krates: HashMap<String, String> = HashMap::new();
-
-    for line in s.lines() {
-        if let Some(caps) = re.captures(line) {
-            let krate = caps.get(1).unwrap().as_str();
-            let version = caps.get(2).unwrap().as_str();
-            krates.insert(String::from(krate), String::from(version));
-        }
+fn expand_home(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => home_dir().join(rest).to_string_lossy().into_owned(),
+        None => String::from(path),
     }
-    krates
+}
+
+// e.g. "ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)"
+fn parse_crates2(contents: &str) -> serde_json::Result<Vec<Installed>> {
+    let crates2: Crates2 = serde_json::from_str(contents)?;
+    let mut installed: Vec<Installed> = crates2
+        .installs
+        .into_iter()
+        .filter_map(|(key, install)| {
+            let mut parts = key.splitn(3, ' ');
+            let name = parts.next()?;
+            let version = parts.next()?;
+            let source = parts.next()?.trim_start_matches('(').trim_end_matches(')');
+            Some(Installed {
+                all_features: install.all_features,
+                features: install.features,
+                name: String::from(name),
+                no_default_features: install.no_default_features,
+                source: String::from(source),
+                target: install.target,
+                version: String::from(version),
+            })
+        })
+        .collect();
+    installed.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(installed)
+}
+
+// prebuilt_files() are the file names that we install from a release archive for `bins`,
+// including the man pages and completions that e.g. ripgrep and fd ship alongside
+fn prebuilt_files(bins: &[String]) -> Vec<(String, Destination)> {
+    bins.iter()
+        .flat_map(|b| {
+            vec![
+                (format!("{}{}", b, EXE_SUFFIX), Destination::Bin),
+                (format!("{}.1", b), Destination::Man(1)),
+                (format!("{}.bash", b), Destination::BashCompletion),
+                (format!("{}.fish", b), Destination::FishCompletion),
+                (format!("_{}", b), Destination::ZshCompletion),
+            ]
+        })
+        .collect()
+}
+
+fn prebuilt_bin(bin: &str) -> PathBuf {
+    home_dir()
+        .join(".local")
+        .join("bin")
+        .join(format!("{}{}", bin, EXE_SUFFIX))
+}
+
+// release_for_version() finds tags like "12.1.1", "v12.1.1", or "ripgrep-v12.1.1"
+fn release_for_version(releases: Vec<Release>, name: &str, version: &str) -> Option<Release> {
+    releases.into_iter().find(|r| {
+        !r.draft
+            && r.tag_name
+                .trim_start_matches(name)
+                .trim_start_matches(['-', '_', '/', '@'])
+                .trim_start_matches('v')
+                == version
+    })
+}
+
+fn trim_git_url(url: &str) -> &str {
+    url.trim_end_matches('/').trim_end_matches(".git")
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::utils::github;
+
     use super::*;
 
+    const CRATES2: &str = r#"{
+        "installs": {
+            "ripgrep 12.1.1 (registry+https://github.com/rust-lang/crates.io-index)": {
+                "version_req": null,
+                "bins": ["rg"],
+                "features": ["pcre2"],
+                "all_features": false,
+                "no_default_features": false,
+                "profile": "release",
+                "target": "x86_64-unknown-linux-gnu",
+                "rustc": "rustc 1.48.0"
+            },
+            "tool 0.1.0 (git+https://github.com/example/tool?branch=main#0123abcd)": {
+                "bins": ["tool"],
+                "features": [],
+                "all_features": false,
+                "no_default_features": false,
+                "target": "x86_64-unknown-linux-gnu"
+            }
+        }
+    }"#;
+
     #[test]
-    fn test_parse_installed() {
-        let input = "
-racer v2.0.12:
-    racer
-rustfmt v0.10.0:
-    cargo-fmt
-    rustfmt
-rustsym v0.3.2:
-    rustsym
-";
-        let want: HashMap<String, String> = [
-            (String::from("racer"), String::from("2.0.12")),
-            (String::from("rustfmt"), String::from("0.10.0")),
-            (String::from("rustsym"), String::from("0.3.2")),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-        assert_eq!(want, parse_installed(input));
+    fn parse_crates2_reads_versions_features_and_sources() {
+        let installed = parse_crates2(CRATES2).expect("must parse");
+        assert_eq!(installed.len(), 2);
+        assert_eq!(installed[0].name, "ripgrep");
+        assert_eq!(installed[0].version, "12.1.1");
+        assert_eq!(installed[0].features, vec!["pcre2"]);
+        assert!(installed[0].is_registry());
+        assert_eq!(installed[1].name, "tool");
+        assert_eq!(installed[1].git_url(), "https://github.com/example/tool");
+    }
+
+    #[test]
+    fn release_for_version_matches_common_tags() {
+        let releases = github::parse_releases_json(
+            r#"[
+                {"assets":[],"name":null,"tag_name":"ripgrep-v13.0.0","draft":true},
+                {"assets":[],"name":null,"tag_name":"v12.1.1"},
+                {"assets":[],"name":null,"tag_name":"12.1.0"}
+            ]"#,
+        )
+        .expect("must parse");
+        let find =
+            |v: &str| release_for_version(releases.clone(), "ripgrep", v).map(|r| r.tag_name);
+        assert_eq!(find("12.1.1"), Some(String::from("v12.1.1")));
+        assert_eq!(find("12.1.0"), Some(String::from("12.1.0")));
+        assert_eq!(find("13.0.0"), None);
+        assert_eq!(find("12.1"), None);
+    }
+
+    #[test]
+    fn prebuilt_only_without_build_options() {
+        let rg = Krate::Spec(Box::new(KrateSpec {
+            bins: vec![String::from("rg")],
+            name: Some(String::from("ripgrep")),
+            ..Default::default()
+        }));
+        assert!(rg.allows_prebuilt());
+        assert_eq!(rg.bins(), vec!["rg"]);
+        assert_eq!(Krate::Name(String::from("bat")).bins(), vec!["bat"]);
+
+        let pcre2 = Krate::Spec(Box::new(KrateSpec {
+            features: vec![String::from("pcre2")],
+            name: Some(String::from("ripgrep")),
+            ..Default::default()
+        }));
+        assert!(!pcre2.allows_prebuilt());
+        let opted_out = Krate::Spec(Box::new(KrateSpec {
+            name: Some(String::from("ripgrep")),
+            prebuilt: Some(false),
+            ..Default::default()
+        }));
+        assert!(!opted_out.allows_prebuilt());
+
+        let files = prebuilt_files(&rg.bins());
+        assert!(files.contains(&(format!("rg{}", EXE_SUFFIX), Destination::Bin)));
+        assert!(files.contains(&(String::from("rg.1"), Destination::Man(1))));
+        assert!(files.contains(&(String::from("_rg"), Destination::ZshCompletion)));
+    }
+
+    #[test]
+    fn parse_rust_toml() {
+        let input = r#"
+            locked = true
+            install = [
+                "bat",
+                { name = "ripgrep", version = "12", features = ["pcre2"], locked = false },
+                { git = "https://github.com/example/tool.git", branch = "main" },
+            ]
+            uninstall = ["racer"]
+            "#;
+        let favs: CargoFavourites = toml::from_str(input).expect("must parse");
+        assert_eq!(favs.wanted(), vec!["bat", "ripgrep", "tool"]);
+        assert_eq!(favs.unwanted(), vec!["racer"]);
+
+        let args: Vec<Vec<String>> = favs
+            .install
+            .iter()
+            .map(|k| k.install_args(favs.locked))
+            .collect();
+        assert_eq!(args[0], vec!["install", "--locked", "bat"]);
+        assert_eq!(
+            args[1],
+            vec![
+                "install",
+                "--version",
+                "12",
+                "--features",
+                "pcre2",
+                "ripgrep"
+            ]
+        );
+        assert_eq!(
+            args[2],
+            vec![
+                "install",
+                "--locked",
+                "--git",
+                "https://github.com/example/tool.git",
+                "--branch",
+                "main"
+            ]
+        );
+
+        let installed = parse_crates2(CRATES2).expect("must parse");
+        assert!(favs.install[1].is_satisfied_by(&installed[0]));
+        assert!(favs.install[2].is_satisfied_by(&installed[1]));
+        assert!(!favs.install[2].is_satisfied_by(&installed[0]));
+
+        let newer = Krate::Spec(Box::new(KrateSpec {
+            name: Some(String::from("ripgrep")),
+            version: Some(String::from("13")),
+            ..Default::default()
+        }));
+        assert!(!newer.is_satisfied_by(&installed[0]));
+        let tag = Krate::Spec(Box::new(KrateSpec {
+            git: Some(String::from("https://github.com/example/tool")),
+            tag: Some(String::from("v1")),
+            ..Default::default()
+        }));
+        assert!(!tag.is_satisfied_by(&installed[1]));
     }
 }
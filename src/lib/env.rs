@@ -19,6 +19,7 @@ const PATH: &str = "PATH";
 #[derive(Debug)]
 pub struct Exports {
     pub editor: PathBuf,
+    pub fpath: Vec<PathBuf>, // zsh only
     pub gopath: Option<PathBuf>,
     pub goroot: Option<PathBuf>,
     pub info_path: Vec<PathBuf>,
@@ -30,6 +31,7 @@ impl Exports {
     pub fn new() -> Exports {
         Exports {
             editor: PathBuf::new(),
+            fpath: Vec::<PathBuf>::new(),
             gopath: None,
             goroot: None,
             info_path: Vec::<PathBuf>::new(),
@@ -87,6 +89,19 @@ impl Exports {
             ));
         }
 
+        // zsh only reads FPATH from the environment when it starts,
+        // so we prepend to the `fpath` array instead
+        if let Shell::Zsh = shell {
+            if !self.fpath.is_empty() {
+                let dirs: Vec<String> = self
+                    .fpath
+                    .iter()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect();
+                lines.push(format!("fpath=({} $fpath)", dirs.join(" ")));
+            }
+        }
+
         lines.join("\n")
     }
 }
@@ -214,6 +229,17 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn to_zsh_fpath() {
+        let exports = Exports {
+            fpath: vec![PathBuf::from("/home/me/.local/share/zsh/site-functions")],
+            ..Exports::new()
+        };
+        let got = exports.to_shell(Shell::Zsh);
+        assert!(got.ends_with("\nfpath=(/home/me/.local/share/zsh/site-functions $fpath)"));
+        assert!(!exports.to_shell(Shell::Bash).contains("fpath"));
+    }
+
     #[test]
     fn to_zsh() {
         let exports = Exports {
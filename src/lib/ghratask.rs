@@ -1,25 +1,71 @@
-use std::{env::consts::EXE_SUFFIX, fs};
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
 
 use crate::lib::{
+    bundle::{self, Bundle, Entry, EntryKind},
     ghrtask::GHRTask,
     task::{self, Status},
 };
 use crate::utils::{
     self,
-    archive::{extract_tar_gz, extract_zip},
-    fs::{mkdtemp, mkftemp, set_executable},
-    github::{self, Asset, Release},
+    archive::{self, Format},
+    checksum::{self, HashReader},
+    forge::{self, Forge},
+    fs::{mkdtemp, set_executable},
+    github::{self, Asset, AssetKind, Release},
+    platform::Platform,
 };
 
-// GHRATask simplifies tasks that install from GitHub Release archives.
+// GHRATask simplifies tasks that install from GitHub Release archives,
+// or from release archives on another `forge`.
 pub struct GHRATask<'a> {
-    pub asset_filter: fn(&Asset) -> bool,
+    // asset_filter defaults to github::default_asset() when None
+    pub asset_filter: Option<fn(&Asset, &Platform) -> bool>,
     pub command: &'a str,
+    // files maps globs within the archive to where we install matching files,
+    // and defaults to just the `command` executable when empty
+    pub files: &'a [(&'a str, Destination)],
+    pub forge: Forge<'a>,
     pub repo: (&'a str, &'a str),
     pub trim_version: fn(String) -> String,
     pub version_arg: &'a str,
 }
 
+// Destination is a directory within $HOME for files from an archive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    BashCompletion,
+    Bin,
+    FishCompletion,
+    Man(u8), // section, e.g. 1 for commands
+    ZshCompletion,
+}
+
+impl Destination {
+    pub fn dir(self) -> String {
+        String::from(match self {
+            Destination::BashCompletion => ".local/share/bash-completion/completions",
+            Destination::Bin => ".local/bin",
+            Destination::FishCompletion => ".config/fish/completions",
+            Destination::Man(section) => return format!(".local/share/man/man{}", section),
+            Destination::ZshCompletion => ".local/share/zsh/site-functions",
+        })
+    }
+}
+
+// Extracted is a file from an archive that we want to install
+#[derive(Debug, PartialEq)]
+struct Extracted {
+    dest: String, // within $HOME
+    destination: Destination,
+    path: PathBuf,
+}
+
 impl<'a> GHRATask<'a> {
     pub fn sync(&mut self) -> task::Result {
         if self.exists() {
@@ -39,17 +85,62 @@ impl<'a> GHRATask<'a> {
         Ok(Status::Changed(String::from("absent"), release.tag_name))
     }
 
+    pub fn bundle(&self, bundle: &mut Bundle) -> bundle::Result {
+        let release = self.latest_release()?;
+        let extract_path = mkdtemp()?;
+        let result = self
+            .extract_release(&release, &bundle.platform, &extract_path)
+            .map_err(task::Error::from)
+            .and_then(|files| {
+                for file in files {
+                    let path = format!("{}/{}", &self.command, &file.dest);
+                    fs::copy(&file.path, bundle.path(&path)?)?;
+                    bundle.add(Entry {
+                        name: String::from(self.command),
+                        version: release.tag_name.clone(),
+                        kind: if file.destination == Destination::Bin {
+                            EntryKind::Executable
+                        } else {
+                            EntryKind::File
+                        },
+                        path,
+                        dest: file.dest,
+                    });
+                }
+                Ok(())
+            });
+        utils::fs::delete_if_exists(&extract_path);
+        result
+    }
+
+    // update() is like GHRTask::update(),
+    // but installs from the archive rather than a bare executable
     pub fn update(&mut self, sync: Status) -> task::Result {
-        match sync {
-            Status::Changed(_, _) => Ok(Status::Skipped),
-            _ => self.as_ghrtask().update(sync),
+        if let Status::Changed(_, _) = sync {
+            return Ok(Status::Skipped);
+        }
+        if utils::http::is_offline() {
+            return Ok(Status::SkippedBecause(String::from("offline")));
         }
+        if !self.exists() {
+            return Ok(Status::Skipped);
+        }
+
+        let current = self.as_ghrtask().current_version();
+        if let Some(r) =
+            forge::release_versus_current(current.as_ref(), self.forge, self.repo.0, self.repo.1)
+        {
+            self.install_release(&r)?;
+            return Ok(Status::Changed(current, r.tag_name));
+        };
+        Ok(Status::NoChange(current))
     }
 
     fn as_ghrtask(&self) -> GHRTask<'a> {
         GHRTask {
             asset_filter: self.asset_filter,
             command: self.command,
+            forge: self.forge,
             repo: self.repo,
             trim_version: self.trim_version,
             version_arg: self.version_arg,
@@ -60,38 +151,319 @@ impl<'a> GHRATask<'a> {
         self.as_ghrtask().exists()
     }
 
-    fn install_release(&self, release: &Release) -> github::Result<()> {
-        let asset = github::compatible_asset(&release, &self.asset_filter)?;
+    // extract_release() downloads the archive for `platform` into `extract_path`,
+    // and returns the files that we should install
+    fn extract_release(
+        &self,
+        release: &Release,
+        platform: &Platform,
+        extract_path: &Path,
+    ) -> github::Result<Vec<Extracted>> {
+        let asset = match self.asset_filter {
+            Some(filter) => github::compatible_asset(release, &|a| filter(a, platform))?,
+            None => github::default_asset(release, platform, self.command, AssetKind::Archive)?,
+        };
 
-        let bin_path = utils::env::home_dir()
-            .join(".local")
-            .join("bin")
-            .join(format!("{}{}", &self.command, EXE_SUFFIX));
+        let want = self.published_checksum(release, &asset)?;
+        let mut hashed = HashReader::new(self.forge.stream(&asset.browser_download_url)?);
+        let (format, reader) = archive::peek(&mut hashed)?;
+        if format == Format::Unknown {
+            return Err(github::GitHubError::WrongAssetType {});
+        }
 
-        let archive_path = mkftemp()?;
-        github::download(asset.browser_download_url.clone(), &archive_path)?;
+        // a bare compressed executable extracts as just `command`
+        let exe_name = format!("{}{}", &self.command, platform.exe_suffix());
+        archive::extract_stream(reader, extract_path, &exe_name, 0)?;
+        if let Some(w) = want {
+            checksum::verify(asset.name.as_str(), &hashed.finish()?, &w)?;
+        }
 
-        let extract_path = mkdtemp()?;
-        if asset.name.ends_with(".tar.gz") {
-            extract_tar_gz(&archive_path, &extract_path)?;
-        } else if asset.name.ends_with(".zip") {
-            extract_zip(&archive_path, &extract_path)?;
+        let default_files = [(exe_name.as_str(), Destination::Bin)];
+        let files = if self.files.is_empty() {
+            &default_files[..]
         } else {
-            return Err(github::GitHubError::WrongAssetType {});
-        }
+            self.files
+        };
+        let extracted = match_files(extract_path, files)?;
 
-        fs::remove_file(&archive_path)?;
-        fs::copy(
-            &extract_path.join(format!("{}{}", &self.command, EXE_SUFFIX)),
-            &bin_path,
-        )?;
-        set_executable(&bin_path)?;
-        fs::remove_dir_all(&extract_path)?;
+        let exe_dest = format!("{}/{}", Destination::Bin.dir(), &exe_name);
+        if !extracted.iter().any(|e| e.dest == exe_dest) {
+            return Err(github::GitHubError::from(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} in {}", &exe_name, &asset.name),
+            )));
+        }
+        Ok(extracted)
+    }
 
-        Ok(())
+    pub fn install_release(&self, release: &Release) -> github::Result<()> {
+        let home = utils::env::home_dir();
+        let extract_path = mkdtemp()?;
+        let result = self
+            .extract_release(release, Platform::current(), &extract_path)
+            .and_then(|files| {
+                for file in files {
+                    let target = home.join(&file.dest);
+                    if let Some(parent) = target.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    utils::fs::delete_if_exists(&target);
+                    fs::copy(&file.path, &target)?;
+                    if file.destination == Destination::Bin {
+                        set_executable(&target)?;
+                    }
+                }
+                Ok(())
+            });
+        utils::fs::delete_if_exists(&extract_path);
+        result
     }
 
     fn latest_release(&self) -> Result<Release, github::GitHubError> {
         self.as_ghrtask().latest_release()
     }
+
+    // published_checksum() is the SHA-256 in e.g. "<asset>.sha256" when the release has one
+    fn published_checksum(
+        &self,
+        release: &Release,
+        asset: &Asset,
+    ) -> github::Result<Option<String>> {
+        let sums = release.assets.iter().find(|a| {
+            [".sha256", ".sha256sum"]
+                .iter()
+                .any(|suffix| a.name == format!("{}{}", &asset.name, suffix))
+        });
+        let sums = match sums {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        let mut text = String::new();
+        self.forge
+            .stream(&sums.browser_download_url)?
+            .read_to_string(&mut text)?;
+        Ok(text.split_whitespace().next().map(String::from))
+    }
+}
+
+// glob_matches() supports "*" and "?" within a directory, and "**" across them,
+// and matches a pattern without any "/" against just the file name
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let subject = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+
+    match Regex::new(&re) {
+        Ok(r) => r.is_match(subject),
+        Err(_error) => false,
+    }
+}
+
+// match_files() walks `dir` in a stable order, and the first matching glob wins,
+// as does the first file for each destination
+fn match_files(dir: &Path, files: &[(&str, Destination)]) -> io::Result<Vec<Extracted>> {
+    let mut paths = Vec::<PathBuf>::new();
+    walk_files(dir, &mut paths)?;
+    paths.sort();
+
+    let mut extracted = Vec::<Extracted>::new();
+    for path in paths {
+        let relative = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let file_name = match path.file_name() {
+            Some(f) => f.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if let Some((_, destination)) = files.iter().find(|(g, _)| glob_matches(g, &relative)) {
+            let dest = format!("{}/{}", destination.dir(), &file_name);
+            if !extracted.iter().any(|e| e.dest == dest) {
+                extracted.push(Extracted {
+                    dest,
+                    destination: *destination,
+                    path,
+                });
+            }
+        }
+    }
+    Ok(extracted)
+}
+
+// walk_files() includes symlinks to files, but does not follow symlinks to directories
+fn walk_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if fs::symlink_metadata(&path)?.is_dir() {
+            walk_files(&path, paths)?;
+        } else if path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_paths_and_file_names() {
+        assert!(glob_matches("*/bin/*", "tool-1.0/bin/tool"));
+        assert!(!glob_matches("*/bin/*", "bin/tool"));
+        assert!(!glob_matches("*/bin/*", "a/b/bin/tool"));
+        assert!(glob_matches("**/bin/*", "a/b/bin/tool"));
+        assert!(glob_matches("*.1", "tool-1.0/doc/tool.1"));
+        assert!(!glob_matches("*.1", "tool-1.0/doc/tool.1.gz"));
+        assert!(glob_matches("completions/_*", "completions/_tool"));
+        assert!(glob_matches("tool.?", "tool.1"));
+        assert!(glob_matches("tool", "tool-1.0/tool"));
+    }
+
+    #[test]
+    fn match_files_maps_globs_to_destinations() {
+        let dir = mkdtemp().unwrap();
+        for f in &[
+            "tool-1.0/bin/tool",
+            "tool-1.0/bin/tool-helper",
+            "tool-1.0/doc/tool.1",
+            "tool-1.0/README.md",
+            "completions/_tool",
+            "completions/tool.bash",
+        ] {
+            let p = dir.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, f).unwrap();
+        }
+
+        let got = match_files(
+            &dir,
+            &[
+                ("*/bin/*", Destination::Bin),
+                ("*.1", Destination::Man(1)),
+                ("completions/_*", Destination::ZshCompletion),
+                ("completions/*.bash", Destination::BashCompletion),
+            ],
+        )
+        .expect("must walk");
+        let dests: Vec<&str> = got.iter().map(|e| e.dest.as_str()).collect();
+        assert_eq!(
+            dests,
+            vec![
+                ".local/share/zsh/site-functions/_tool",
+                ".local/share/bash-completion/completions/tool.bash",
+                ".local/bin/tool",
+                ".local/bin/tool-helper",
+                ".local/share/man/man1/tool.1",
+            ]
+        );
+        assert_eq!(got[2].path, dir.join("tool-1.0/bin/tool"));
+
+        utils::fs::delete_if_exists(&dir);
+    }
+
+    // write_gitlab_fixture() is a GitLab API and release archive in `dir`, for file:// URLs,
+    // with a .sha256 that is correct unless `sha256` overrides it
+    fn write_gitlab_fixture(dir: &Path, sha256: Option<&str>) {
+        let mut builder = tar::Builder::new(libflate::gzip::Encoder::new(Vec::new()).unwrap());
+        let contents = b"#!/bin/sh\necho tool 1.0.0\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        builder
+            .append_data(&mut header, "tool-1.0.0/bin/tool", &contents[..])
+            .unwrap();
+        let archive = builder
+            .into_inner()
+            .unwrap()
+            .finish()
+            .into_result()
+            .unwrap();
+
+        let mut hashed = HashReader::new(&archive[..]);
+        io::copy(&mut hashed, &mut io::sink()).unwrap();
+        let sum = hashed.finish().unwrap();
+
+        let downloads = dir.join("downloads");
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(downloads.join("tool-1.0.0.tar.gz"), &archive).unwrap();
+        fs::write(
+            downloads.join("tool-1.0.0.tar.gz.sha256"),
+            format!("{}  tool-1.0.0.tar.gz\n", sha256.unwrap_or(&sum)),
+        )
+        .unwrap();
+
+        let link = |name: &str| {
+            format!(
+                r#"{{"name":"{}","url":"file://{}/{}"}}"#,
+                name,
+                downloads.display(),
+                name
+            )
+        };
+        let releases = format!(
+            r#"[{{"name":"1.0.0","tag_name":"v1.0.0","released_at":"2020-11-01T00:00:00Z",
+                "assets":{{"links":[{},{}],"sources":[]}}}}]"#,
+            link("tool-1.0.0.tar.gz"),
+            link("tool-1.0.0.tar.gz.sha256")
+        );
+        // "group/tool" is "group%2Ftool" in the URL, which is "group/tool" on disk
+        let api = dir.join("api/v4/projects/group/tool");
+        fs::create_dir_all(&api).unwrap();
+        fs::write(api.join("releases"), releases).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_release_from_gitlab() {
+        let dir = mkdtemp().unwrap();
+        let base = format!("file://{}", dir.display());
+        let task = GHRATask {
+            asset_filter: Some(|a, _| a.name.ends_with(".tar.gz")),
+            command: "tool",
+            files: &[],
+            forge: Forge::GitLab(&base),
+            repo: ("group", "tool"),
+            trim_version: |s| s,
+            version_arg: "--version",
+        };
+
+        write_gitlab_fixture(&dir, None);
+        let release = task.latest_release().expect("must find release");
+        assert_eq!(release.tag_name, "v1.0.0");
+        let extract_path = dir.join("extracted");
+        let got = task
+            .extract_release(&release, Platform::current(), &extract_path)
+            .expect("must extract");
+        let dests: Vec<&str> = got.iter().map(|e| e.dest.as_str()).collect();
+        assert_eq!(dests, vec![".local/bin/tool"]);
+
+        write_gitlab_fixture(&dir, Some("0000"));
+        let extract_path = dir.join("mismatched");
+        assert!(task
+            .extract_release(&release, Platform::current(), &extract_path)
+            .is_err());
+
+        utils::fs::delete_if_exists(&dir);
+    }
 }
@@ -1,15 +1,23 @@
-use std::env::consts::EXE_SUFFIX;
+use std::{env::consts::EXE_SUFFIX, path::Path};
 
-use crate::lib::task::{self, Status};
+use crate::lib::{
+    bundle::{self, Bundle, Entry, EntryKind},
+    task::{self, Status},
+};
 use crate::utils::{
     self,
-    github::{self, Asset, Release},
+    forge::{self, Forge},
+    github::{self, Asset, AssetKind, Release},
+    platform::Platform,
 };
 
-// GHRTask simplifies tasks that install from GitHub Releases.
+// GHRTask simplifies tasks that install from GitHub Releases,
+// or from releases on another `forge`.
 pub struct GHRTask<'a> {
-    pub asset_filter: fn(&Asset) -> bool,
+    // asset_filter defaults to github::default_asset() when None
+    pub asset_filter: Option<fn(&Asset, &Platform) -> bool>,
     pub command: &'a str,
+    pub forge: Forge<'a>,
     pub repo: (&'a str, &'a str),
     pub trim_version: fn(String) -> String,
     pub version_arg: &'a str,
@@ -43,7 +51,7 @@ impl<'a> GHRTask<'a> {
     }
 
     pub fn latest_release(&self) -> Result<Release, github::GitHubError> {
-        github::latest_release(String::from(self.repo.0), String::from(self.repo.1))
+        self.forge.latest_release(self.repo.0, self.repo.1)
     }
 
     pub fn sync(&self) -> task::Result {
@@ -68,12 +76,16 @@ impl<'a> GHRTask<'a> {
         if let Status::Changed(_, _) = sync {
             return Ok(Status::Skipped);
         }
+        if utils::http::is_offline() {
+            return Ok(Status::SkippedBecause(String::from("offline")));
+        }
         if !self.exists() {
             return Ok(Status::Skipped);
         }
 
         let current = self.current_version();
-        if let Some(r) = github::release_versus_current(current.as_ref(), self.repo.0, self.repo.1)
+        if let Some(r) =
+            forge::release_versus_current(current.as_ref(), self.forge, self.repo.0, self.repo.1)
         {
             self.install_release(&r)?;
             return Ok(Status::Changed(current, r.tag_name));
@@ -81,14 +93,41 @@ impl<'a> GHRTask<'a> {
         Ok(Status::NoChange(current))
     }
 
-    fn install_release(&self, release: &Release) -> github::Result<()> {
-        let asset = github::compatible_asset(&release, &self.asset_filter)?;
+    pub fn bundle(&self, bundle: &mut Bundle) -> bundle::Result {
+        let release = self.latest_release()?;
+        let file_name = format!("{}{}", &self.command, bundle.platform.exe_suffix());
+        let path = format!("bin/{}", &file_name);
+        self.download_release(&release, &bundle.platform, &bundle.path(&path)?)?;
+        bundle.add(Entry {
+            name: String::from(self.command),
+            version: release.tag_name,
+            kind: EntryKind::Executable,
+            path,
+            dest: format!(".local/bin/{}", &file_name),
+        });
+        Ok(())
+    }
 
+    fn download_release(
+        &self,
+        release: &Release,
+        platform: &Platform,
+        target: &Path,
+    ) -> github::Result<()> {
+        let asset = match self.asset_filter {
+            Some(filter) => github::compatible_asset(release, &|a| filter(a, platform))?,
+            None => github::default_asset(release, platform, self.command, AssetKind::Binary)?,
+        };
+        self.forge.download(&asset.browser_download_url, target)
+    }
+
+    fn install_release(&self, release: &Release) -> github::Result<()> {
         let bin_path = utils::env::home_dir()
             .join(".local")
             .join("bin")
             .join(format!("{}{}", &self.command, EXE_SUFFIX));
-        github::download_release_asset(&asset, &bin_path)?;
+        self.download_release(release, Platform::current(), &bin_path)?;
+        utils::fs::set_executable(&bin_path)?;
 
         Ok(())
     }
@@ -1,97 +1,176 @@
-use std::{env::consts::EXE_SUFFIX, io, path::PathBuf};
+use std::{fs, io, path::PathBuf};
 
 use serde_derive::Deserialize;
 
 use crate::{
-    lib::favourites::Favourites,
-    utils::{fs::delete_if_exists, golang::gopath, process::command_spawn_wait},
+    lib::{favourites::Favourites, version::Version},
+    utils::{
+        fs::{delete_empty_ancestors, delete_if_exists},
+        golang::{go_exe, gopath},
+        process::{command_output, command_spawn_wait},
+    },
 };
 
-#[derive(Debug, Deserialize)]
+// GoGetFavourites are packages for `go install`, e.g. "golang.org/x/tools/cmd/goimports",
+// optionally pinned to a version with "@", e.g. "mvdan.cc/gofumpt@v0.1.0"
+#[derive(Debug, Default, Deserialize)]
 pub struct GoGetFavourites {
+    #[serde(default)]
     install: Vec<String>,
+    #[serde(default)]
     uninstall: Vec<String>,
 }
 
-impl Default for GoGetFavourites {
-    fn default() -> GoGetFavourites {
-        GoGetFavourites {
-            install: Vec::<String>::new(),
-            uninstall: Vec::<String>::new(),
-        }
+impl GoGetFavourites {
+    // pins() are the packages in `install`, with their pinned versions (if any)
+    pub fn pins(&self) -> Vec<(String, Option<String>)> {
+        self.install
+            .iter()
+            .map(|i| {
+                let (pkg, pin) = split_pin(i);
+                (String::from(pkg), pin.map(String::from))
+            })
+            .collect()
     }
 }
 
 impl Favourites for GoGetFavourites {
     fn cull(&mut self) -> io::Result<()> {
-        for pkg in self.surplus() {
-            let p = PathBuf::from(pkg);
-            let src = gopath().join("src").join(&p);
-            delete_if_exists(src);
-            // TODO: cleanup empty ancestor directories, too
-
-            if let Some(f) = p.file_name() {
-                let bin =
-                    gopath()
-                        .join("bin")
-                        .join(format!("{}{}", f.to_string_lossy(), EXE_SUFFIX));
-                delete_if_exists(bin);
+        let surplus = self.surplus();
+        let src = gopath().join("src");
+        for binary in installed_binaries() {
+            if surplus.contains(&binary.path) {
+                delete_if_exists(&binary.file);
+
+                // from before modules, when `go get` also cloned into $GOPATH/src
+                let pkg_src = src.join(&binary.path);
+                if pkg_src.is_dir() {
+                    delete_if_exists(&pkg_src);
+                    delete_empty_ancestors(&pkg_src, &src);
+                }
             }
         }
         Ok(())
     }
     fn fill(&mut self) -> io::Result<()> {
-        for pkg in self.missing() {
-            command_spawn_wait("go", &["get", "-u", "-v", &pkg])?;
+        let missing = self.missing();
+        for (pkg, pin) in self.pins() {
+            if missing.contains(&pkg) {
+                go_install(&pkg, pin.as_deref().unwrap_or("latest"))?;
+            }
         }
         Ok(())
     }
     fn found(&self) -> Vec<String> {
-        // not really feasible to do this accurately,
-        // only for things specifically listed in install/uninstall
-        let mut results = Vec::<String>::new();
-        for pkg in self.wanted() {
-            if pkg_found(pkg.as_str()) {
-                results.push(pkg);
-            }
-        }
-        for pkg in self.unwanted() {
-            if pkg_found(pkg.as_str()) {
-                results.push(pkg);
-            }
-        }
-        results
+        installed_binaries().into_iter().map(|b| b.path).collect()
     }
     fn wanted(&self) -> Vec<String> {
-        self.install.clone()
+        self.pins().into_iter().map(|(pkg, _)| pkg).collect()
     }
     fn unwanted(&self) -> Vec<String> {
-        self.uninstall.clone()
+        self.uninstall
+            .iter()
+            .map(|u| String::from(split_pin(u).0))
+            .collect()
+    }
+}
+
+// Binary is what `go version -m` reports for an executable in $GOPATH/bin
+#[derive(Debug, PartialEq)]
+pub struct Binary {
+    pub file: PathBuf,
+    pub module: String,
+    // modules are each "module@version" in the build, including dependencies
+    pub modules: Vec<String>,
+    pub path: String,    // the package, e.g. "golang.org/x/tools/cmd/goimports"
+    pub version: String, // of the module, or "(devel)" for a local build
+}
+
+pub fn go_install(pkg: &str, version: &str) -> io::Result<()> {
+    command_spawn_wait("go", &["install", &format!("{}@{}", pkg, version)]).map(|_| ())
+}
+
+// installed_binaries() is read_binaries(), or nothing if we cannot read them
+pub fn installed_binaries() -> Vec<Binary> {
+    read_binaries().unwrap_or_default()
+}
+
+// read_binaries() reads the build info embedded in each executable,
+// skipping those that are not from Go modules
+pub fn read_binaries() -> io::Result<Vec<Binary>> {
+    let bin = gopath().join("bin");
+    if fs::read_dir(&bin).is_err() {
+        return Ok(Vec::new());
+    }
+    let output = command_output(go_exe(), &["version", "-m", &bin.to_string_lossy()])?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`go version -m` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
     }
+    Ok(parse_build_info(&String::from_utf8_lossy(&output.stdout)))
 }
 
-fn pkg_found<S>(pkg: S) -> bool
-where
-    S: AsRef<str>,
-{
-    let p = PathBuf::from(pkg.as_ref());
-    match p.file_name() {
-        Some(f) => {
-            let bin = gopath()
-                .join("bin")
-                .join(format!("{}{}", f.to_string_lossy(), EXE_SUFFIX));
-
-            let src = gopath().join("src").join(&p);
-            bin.is_file() && src.is_dir()
+// e.g.
+// /home/user/go/bin/goimports: go1.15.5
+//         path    golang.org/x/tools/cmd/goimports
+//         mod     golang.org/x/tools      v0.0.0-20201124005743-911501bfb504      h1:...
+//         dep     golang.org/x/mod        v0.3.0  h1:...
+fn parse_build_info(stdout: &str) -> Vec<Binary> {
+    let mut binaries = Vec::<Binary>::new();
+    let mut file: Option<PathBuf> = None;
+    let mut path: Option<String> = None;
+    let mut in_binary = false;
+    for line in stdout.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            file = line.rsplit_once(": ").map(|(f, _)| PathBuf::from(f));
+            path = None;
+            in_binary = false;
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["path", p, ..] => path = Some(String::from(*p)),
+            ["mod", module, version, ..] => {
+                if let (Some(f), Some(p)) = (file.take(), path.take()) {
+                    binaries.push(Binary {
+                        file: f,
+                        module: String::from(*module),
+                        modules: vec![format!("{}@{}", module, version)],
+                        path: p,
+                        version: String::from(*version),
+                    });
+                    in_binary = true;
+                }
+            }
+            // "=>" is a replacement for the module in the line before
+            ["dep", module, version, ..] | ["=>", module, version, ..] => {
+                if let Some(b) = binaries.last_mut().filter(|_| in_binary) {
+                    b.modules.push(format!("{}@{}", module, version));
+                }
+            }
+            _ => {}
         }
-        None => false,
+    }
+    binaries
+}
+
+// is_version_pin() is whether `pin` is a module version, e.g. "v0.1.0",
+// rather than a query like "latest" or a branch like "master"
+pub fn is_version_pin(pin: &str) -> bool {
+    pin.starts_with('v') && Version::parse(pin).is_some()
+}
+
+fn split_pin(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once('@') {
+        Some((pkg, version)) => (pkg, Some(version)),
+        None => (spec, None),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env::consts::OS;
-
     use super::*;
 
     #[test]
@@ -105,23 +184,68 @@ mod tests {
     }
 
     #[test]
-    fn found_finds_goimports() {
-        let goimports_pkg = String::from("golang.org/x/tools/cmd/goimports");
+    fn parse_build_info_finds_module_binaries() {
+        let input = "\
+/home/user/go/bin/goimports: go1.15.5
+\tpath\tgolang.org/x/tools/cmd/goimports
+\tmod\tgolang.org/x/tools\tv0.0.0-20201124005743-911501bfb504\th1:abc=
+\tdep\tgolang.org/x/mod\tv0.3.0\th1:def=
+/home/user/go/bin/devel: go1.15.5
+	path	example.com/devel
+	dep	golang.org/x/sys	v0.0.0-20201119102817-f84b799fce68	h1:jkl=
+/home/user/go/bin/gofumpt: go1.15.5
+\tpath\tmvdan.cc/gofumpt
+\tmod\tmvdan.cc/gofumpt\tv0.1.0\th1:ghi=
+";
+        assert_eq!(
+            parse_build_info(input),
+            vec![
+                Binary {
+                    file: PathBuf::from("/home/user/go/bin/goimports"),
+                    module: String::from("golang.org/x/tools"),
+                    modules: vec![
+                        String::from("golang.org/x/tools@v0.0.0-20201124005743-911501bfb504"),
+                        String::from("golang.org/x/mod@v0.3.0"),
+                    ],
+                    path: String::from("golang.org/x/tools/cmd/goimports"),
+                    version: String::from("v0.0.0-20201124005743-911501bfb504"),
+                },
+                Binary {
+                    file: PathBuf::from("/home/user/go/bin/gofumpt"),
+                    module: String::from("mvdan.cc/gofumpt"),
+                    modules: vec![String::from("mvdan.cc/gofumpt@v0.1.0")],
+                    path: String::from("mvdan.cc/gofumpt"),
+                    version: String::from("v0.1.0"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pins_split_versions_from_packages() {
         let favs = GoGetFavourites {
-            install: vec![goimports_pkg.clone()],
-            uninstall: vec![],
+            install: vec![
+                String::from("golang.org/x/tools/cmd/goimports"),
+                String::from("mvdan.cc/gofumpt@v0.1.0"),
+            ],
+            uninstall: vec![String::from("github.com/golang/dep/cmd/dep@v0.5.4")],
         };
-
-        let bin = gopath().join("bin").join(if OS == "windows" {
-            "goimports.exe"
-        } else {
-            "goimports"
-        });
-
-        let src = gopath().join("src").join(goimports_pkg.clone());
         assert_eq!(
-            bin.is_file() && src.is_dir(),
-            favs.found().contains(&goimports_pkg)
+            favs.pins(),
+            vec![
+                (String::from("golang.org/x/tools/cmd/goimports"), None),
+                (
+                    String::from("mvdan.cc/gofumpt"),
+                    Some(String::from("v0.1.0"))
+                ),
+            ]
         );
+        assert_eq!(favs.unwanted(), vec!["github.com/golang/dep/cmd/dep"]);
+
+        assert!(is_version_pin("v0.1.0"));
+        assert!(is_version_pin("v0.0.0-20201124005743-911501bfb504"));
+        assert!(!is_version_pin("latest"));
+        assert!(!is_version_pin("master"));
+        assert!(!is_version_pin("911501bfb504"));
     }
 }
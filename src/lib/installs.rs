@@ -0,0 +1,525 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_derive::Deserialize;
+
+use crate::{
+    lib::{
+        task::{self, Status},
+        version::{self, Version},
+    },
+    utils::{self, fs::symbolic_link},
+};
+
+// KEEP_PREVIOUS is how many versions we keep for `rollback`,
+// in addition to the active version and those in Runtimes
+const KEEP_PREVIOUS: usize = 2;
+
+// Installs keeps each version of a tool in its own directory,
+// e.g. ~/.local/share/golang/go1.15.5, with `link` pointing at the active one
+pub struct Installs {
+    dir: PathBuf,
+    link: PathBuf,
+    // matches() is whether an installed version is within a spec,
+    // which is usually version::matches(), but may know about release channels
+    matches: fn(&str, &str) -> bool,
+}
+
+impl Installs {
+    pub fn new<P>(tool: &str, link: P, matches: fn(&str, &str) -> bool) -> Installs
+    where
+        P: AsRef<Path>,
+    {
+        Installs {
+            dir: utils::env::home_dir()
+                .join(".local")
+                .join("share")
+                .join(tool),
+            link: link.as_ref().to_path_buf(),
+            matches,
+        }
+    }
+
+    // in_dir() keeps versions in `dir` rather than ~/.local/share
+    #[cfg(test)]
+    pub fn in_dir<P>(dir: P, link: P) -> Installs
+    where
+        P: AsRef<Path>,
+    {
+        Installs {
+            dir: dir.as_ref().to_path_buf(),
+            link: link.as_ref().to_path_buf(),
+            matches: |s, v| version::matches(s, v),
+        }
+    }
+
+    // activate() replaces `link` with a rename,
+    // so there is never a moment without a working `link`
+    pub fn activate(&self, version: &str) -> io::Result<()> {
+        let target = self.path(version);
+        let file_name = self.link.file_name().unwrap_or_default().to_string_lossy();
+        let temp_link = self.link.with_file_name(format!(".{}.new", file_name));
+        if let Some(parent) = self.link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        utils::fs::delete_if_exists(&temp_link);
+        symbolic_link(&target, &temp_link)?;
+
+        // an unversioned install is a real directory, which rename cannot replace,
+        // and Windows cannot rename over an existing symlink either
+        match fs::symlink_metadata(&self.link) {
+            Ok(attr) if cfg!(windows) || !attr.file_type().is_symlink() => {
+                utils::fs::delete_if_exists(&self.link);
+            }
+            _ => {}
+        }
+        fs::rename(&temp_link, &self.link)
+    }
+
+    pub fn current(&self) -> Option<String> {
+        let target = fs::read_link(&self.link).ok()?;
+        if target.parent() != Some(self.dir.as_path()) {
+            return None;
+        }
+        Some(target.file_name()?.to_string_lossy().into_owned())
+    }
+
+    // find() is the newest installed version within `spec`,
+    // or the newest of all when there is no `spec`
+    pub fn find(&self, spec: Option<&str>) -> Option<String> {
+        self.versions()
+            .into_iter()
+            .rfind(|v| spec.is_none_or(|s| (self.matches)(s, v)))
+    }
+
+    // install() has `f` populate an empty directory for `version`,
+    // and only moves it into place once `f` succeeds
+    pub fn install<F>(&self, version: &str, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&Path) -> io::Result<()>,
+    {
+        let staging = self.dir.join(format!(".{}.partial", version));
+        utils::fs::delete_if_exists(&staging);
+        fs::create_dir_all(&staging)?;
+
+        if let Err(error) = f(&staging) {
+            utils::fs::delete_if_exists(&staging);
+            return Err(error);
+        }
+
+        let target = self.path(version);
+        utils::fs::delete_if_exists(&target);
+        fs::rename(&staging, &target)
+    }
+
+    pub fn path(&self, version: &str) -> PathBuf {
+        self.dir.join(version)
+    }
+
+    // rollback() activates the newest version older than the active one,
+    // returning the versions we switched from and to
+    pub fn rollback(&self) -> io::Result<(String, String)> {
+        let current = match self.current() {
+            Some(c) => c,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not a versioned install", self.link.display()),
+                ));
+            }
+        };
+        let previous = match self
+            .versions()
+            .into_iter()
+            .rfind(|v| sort_key(v) < sort_key(&current))
+        {
+            Some(p) => p,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no version older than {}", &current),
+                ));
+            }
+        };
+        self.activate(&previous)?;
+        Ok((current, previous))
+    }
+
+    // versions() are oldest first
+    pub fn versions(&self) -> Vec<String> {
+        let mut versions: Vec<String> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.'))
+                .collect(),
+            Err(_error) => Vec::new(), // nothing installed yet
+        };
+        versions.sort_by_key(|v| sort_key(v));
+        versions
+    }
+
+    // prune() deletes old versions, except for the active version and `keep`
+    pub fn prune(&self, keep: &[String]) {
+        let current = self.current();
+        let mut others: Vec<String> = self
+            .versions()
+            .into_iter()
+            .filter(|v| Some(v) != current.as_ref() && !keep.contains(v))
+            .collect();
+        let excess = others.len().saturating_sub(KEEP_PREVIOUS);
+        for version in others.drain(..excess) {
+            utils::fs::delete_if_exists(self.path(&version));
+        }
+    }
+}
+
+// Runtimes are the `versions` and `default` in golang.toml or nodejs.toml,
+// e.g. `versions = ["12", "14"]` to install the latest 12.x and 14.x
+#[derive(Debug, Default, Deserialize)]
+pub struct Runtimes {
+    // channel is what we follow without any `versions`,
+    // e.g. `channel = "lts"` for Node.js
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub versions: Vec<String>,
+    pub default: Option<String>,
+}
+
+impl Runtimes {
+    pub fn read(file_name: &str) -> Runtimes {
+        let cfg_path = utils::env::home_dir()
+            .join(".dotfiles/config")
+            .join(file_name);
+
+        let contents = match fs::read_to_string(&cfg_path) {
+            Ok(s) => s,
+            Err(_error) => {
+                // versions are optional, and we default to just the latest
+                return Default::default();
+            }
+        };
+
+        match toml::from_str(&contents) {
+            Ok(c) => c,
+            Err(error) => {
+                println!(
+                    "warning: unable to parse {}, {}",
+                    &cfg_path.display(),
+                    error
+                );
+                Default::default()
+            }
+        }
+    }
+
+    // default_spec() is `default`, or else the last of `versions`, or else `channel`
+    pub fn default_spec(&self) -> Option<&str> {
+        self.default
+            .as_deref()
+            .or_else(|| self.versions.last().map(|v| v.as_str()))
+            .or(self.channel.as_deref())
+    }
+
+    // specs() is `versions`, or just the latest on `channel` when there are none
+    pub fn specs(&self) -> Vec<Option<&str>> {
+        if self.versions.is_empty() {
+            vec![self.channel.as_deref()]
+        } else {
+            self.versions.iter().map(|v| Some(v.as_str())).collect()
+        }
+    }
+}
+
+// sync_versions() installs the latest version for each of `runtimes`,
+// but during sync (`upgrade` is false) only those without any version yet;
+// `current` is the version on PATH, which may predate Installs
+pub fn sync_versions(
+    installs: &Installs,
+    runtimes: &Runtimes,
+    current: Option<String>,
+    upgrade: bool,
+    latest: &dyn Fn(Option<&str>) -> Result<String, task::Error>,
+    install: &dyn Fn(&str) -> io::Result<()>,
+) -> task::Result {
+    let before = installs
+        .current()
+        .or_else(|| current.clone())
+        .unwrap_or_else(|| String::from("absent"));
+
+    let mut added = Vec::<String>::new();
+    for spec in runtimes.specs() {
+        let installed = installs.find(spec).or_else(|| {
+            current
+                .clone()
+                .filter(|c| spec.is_none_or(|s| (installs.matches)(s, c)))
+        });
+        if installed.is_some() && !upgrade {
+            continue;
+        }
+        let latest = latest(spec)?;
+        if installed.is_none_or(|i| version::is_newer(&latest, &i)) {
+            install(&latest)?;
+            added.push(latest);
+        }
+    }
+
+    // `use` may have chosen a configured version other than the default,
+    // in which case we follow upgrades to that version instead,
+    // and sync leaves the active version alone (e.g. after `rollback`)
+    // unless no configured spec covers it anymore
+    let active_spec = installs.current().and_then(|c| {
+        runtimes
+            .specs()
+            .into_iter()
+            .find(|spec| spec.is_none_or(|s| (installs.matches)(s, &c)))
+    });
+    let spec = match active_spec {
+        Some(_) if !upgrade => None,
+        Some(s) => Some(s),
+        None => Some(runtimes.default_spec()),
+    };
+    if let Some(v) = spec.and_then(|s| installs.find(s)) {
+        if installs.current().as_ref() != Some(&v) {
+            installs.activate(&v)?;
+        }
+    }
+
+    let keep: Vec<String> = runtimes
+        .specs()
+        .into_iter()
+        .filter_map(|spec| installs.find(spec))
+        .collect();
+    installs.prune(&keep);
+
+    let after = installs.current().unwrap_or_else(|| before.clone());
+    let others: Vec<String> = added.into_iter().filter(|a| a != &after).collect();
+    if others.is_empty() && after == before {
+        Ok(Status::NoChange(before))
+    } else if others.is_empty() {
+        Ok(Status::Changed(before, after))
+    } else {
+        Ok(Status::Changed(
+            before,
+            format!("{} (also added {})", after, others.join(", ")),
+        ))
+    }
+}
+
+// verify_command() checks that a freshly-installed executable at least runs
+pub fn verify_command(exe: &Path, arg: &str) -> io::Result<()> {
+    let output = utils::process::command_output(exe, &[arg])?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{} {}` failed: {}",
+            exe.display(),
+            arg,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+fn sort_key(version: &str) -> (Option<Version>, String) {
+    (Version::parse(version), String::from(version))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::fs::mkdtemp;
+
+    use super::*;
+
+    fn installs_in(temp_path: &Path) -> Installs {
+        Installs::in_dir(temp_path.join("share/golang"), temp_path.join("go"))
+    }
+
+    fn install_version(installs: &Installs, version: &str) -> io::Result<()> {
+        installs.install(version, |dir| fs::write(dir.join("VERSION"), version))?;
+        installs.activate(version)?;
+        installs.prune(&[]);
+        Ok(())
+    }
+
+    fn latest_of<'a>(
+        available: &'a [&'a str],
+    ) -> impl Fn(Option<&str>) -> Result<String, task::Error> + 'a {
+        move |spec| match available
+            .iter()
+            .rfind(|v| spec.is_none_or(|s| version::matches(s, v)))
+        {
+            Some(v) => Ok(String::from(*v)),
+            None => Err(task::Error::from(io::Error::from(io::ErrorKind::NotFound))),
+        }
+    }
+
+    #[test]
+    fn install_switches_link_and_keeps_previous_versions() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+
+        // an unversioned install from before
+        fs::create_dir_all(temp_path.join("go")).unwrap();
+
+        for v in &["go1.9.7", "go1.14.12", "go1.15.4", "go1.15.5"] {
+            install_version(&installs, v).expect("must install");
+        }
+        assert_eq!(installs.current(), Some(String::from("go1.15.5")));
+        assert_eq!(
+            fs::read_to_string(temp_path.join("go/VERSION")).unwrap(),
+            "go1.15.5"
+        );
+        assert_eq!(
+            installs.versions(),
+            vec!["go1.14.12", "go1.15.4", "go1.15.5"]
+        );
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn failed_install_keeps_current_version() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+
+        install_version(&installs, "go1.15.4").expect("must install");
+        let result = installs.install("go1.15.5", |dir| {
+            fs::write(dir.join("VERSION"), "go1.15.5")?;
+            Err(io::Error::other("checksum mismatch"))
+        });
+        assert!(result.is_err());
+        assert_eq!(installs.current(), Some(String::from("go1.15.4")));
+        assert_eq!(installs.versions(), vec!["go1.15.4"]);
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn rollback_activates_previous_version() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+
+        install_version(&installs, "go1.15.4").expect("must install");
+        install_version(&installs, "go1.15.5").expect("must install");
+
+        let (from, to) = installs.rollback().expect("must roll back");
+        assert_eq!((from.as_str(), to.as_str()), ("go1.15.5", "go1.15.4"));
+        assert_eq!(installs.current(), Some(String::from("go1.15.4")));
+        assert!(installs.rollback().is_err());
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn sync_versions_installs_each_runtime_and_follows_use() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+        let runtimes = Runtimes {
+            channel: None,
+            versions: vec![String::from("1.14"), String::from("1.15")],
+            default: Some(String::from("1.14")),
+        };
+        let install = |v: &str| installs.install(v, |dir| fs::write(dir.join("VERSION"), v));
+
+        let available = ["go1.14.12", "go1.15.5"];
+        let status = sync_versions(
+            &installs,
+            &runtimes,
+            None,
+            false,
+            &latest_of(&available),
+            &install,
+        )
+        .expect("must sync");
+        assert_eq!(
+            status,
+            Status::Changed(
+                String::from("absent"),
+                String::from("go1.14.12 (also added go1.15.5)")
+            )
+        );
+        assert_eq!(installs.current(), Some(String::from("go1.14.12")));
+
+        // `use 1.15`, then a new 1.15.x arrives
+        installs.activate("go1.15.5").expect("must activate");
+        let available = ["go1.14.12", "go1.15.5", "go1.15.6"];
+        sync_versions(
+            &installs,
+            &runtimes,
+            None,
+            true,
+            &latest_of(&available),
+            &install,
+        )
+        .expect("must update");
+        assert_eq!(installs.current(), Some(String::from("go1.15.6")));
+        assert_eq!(
+            installs.versions(),
+            vec!["go1.14.12", "go1.15.5", "go1.15.6"]
+        );
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn sync_versions_keeps_rolled_back_version() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+        let runtimes = Runtimes::default();
+        let install = |v: &str| installs.install(v, |dir| fs::write(dir.join("VERSION"), v));
+
+        install_version(&installs, "go1.15.4").expect("must install");
+        install_version(&installs, "go1.15.5").expect("must install");
+        installs.rollback().expect("must roll back");
+
+        let available = ["go1.15.4", "go1.15.5"];
+        let status = sync_versions(
+            &installs,
+            &runtimes,
+            None,
+            false,
+            &latest_of(&available),
+            &install,
+        )
+        .expect("must sync");
+        assert_eq!(status, Status::NoChange(String::from("go1.15.4")));
+        assert_eq!(installs.current(), Some(String::from("go1.15.4")));
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn runtimes_follow_channel_without_versions() {
+        let runtimes: Runtimes = toml::from_str(r#"channel = "lts""#).expect("must parse");
+        assert_eq!(runtimes.specs(), vec![Some("lts")]);
+        assert_eq!(runtimes.default_spec(), Some("lts"));
+
+        let runtimes: Runtimes =
+            toml::from_str("channel = \"lts\"\nversions = [\"12\", \"14\"]").expect("must parse");
+        assert_eq!(runtimes.specs(), vec![Some("12"), Some("14")]);
+        assert_eq!(runtimes.default_spec(), Some("14"));
+    }
+
+    #[test]
+    fn sync_versions_keeps_unversioned_install() {
+        let temp_path = mkdtemp().unwrap();
+        let installs = installs_in(&temp_path);
+        let available = ["go1.15.5"];
+        let status = sync_versions(
+            &installs,
+            &Default::default(),
+            Some(String::from("go1.15.5")),
+            true,
+            &latest_of(&available),
+            &|_| panic!("must not install"),
+        )
+        .expect("must sync");
+        assert_eq!(status, Status::NoChange(String::from("go1.15.5")));
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+}
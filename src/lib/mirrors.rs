@@ -0,0 +1,211 @@
+use std::{env::var, fs};
+
+use serde_derive::Deserialize;
+
+use crate::utils::{self, platform::Platform};
+
+const CRATES_INDEX: &str = "https://index.crates.io";
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+const GITHUB_API: &str = "https://api.github.com";
+const GITHUB_RAW: &str = "https://raw.githubusercontent.com";
+const GOLANG_DL: &str = "https://dl.google.com/go";
+const GOLANG_INDEX: &str = "https://go.dev/dl/?mode=json&include=all";
+const NODEJS_DIST: &str = "https://nodejs.org/dist";
+// nodejs.org does not publish musl builds
+const NODEJS_DIST_MUSL: &str = "https://unofficial-builds.nodejs.org/download/release";
+const PYPI: &str = "https://pypi.org/pypi";
+const RUSTUP_DIST: &str = "https://static.rust-lang.org/rustup";
+
+// Mirrors overrides the base URLs of our download sources,
+// e.g. to point at an internal artifact mirror, or a `file://` directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Mirrors {
+    crates_index: Option<String>,
+    crates_io_api: Option<String>,
+    github_api: Option<String>,
+    github_raw: Option<String>,
+    golang_dl: Option<String>,
+    golang_index: Option<String>,
+    nodejs_dist: Option<String>,
+    pypi: Option<String>,
+    rustup_dist: Option<String>,
+}
+
+pub fn crates_index() -> String {
+    pick(
+        var("DOTFILES_CRATES_INDEX_URL").ok(),
+        read_config().crates_index,
+        CRATES_INDEX,
+    )
+}
+
+// crates_io_api() has each crate's metadata, e.g. "crates/ripgrep" for its repository URL
+pub fn crates_io_api() -> String {
+    pick(
+        var("DOTFILES_CRATES_IO_API_URL").ok(),
+        read_config().crates_io_api,
+        CRATES_IO_API,
+    )
+}
+
+pub fn github_api() -> String {
+    pick(
+        var("DOTFILES_GITHUB_API_URL").ok(),
+        read_config().github_api,
+        GITHUB_API,
+    )
+}
+
+pub fn github_raw() -> String {
+    pick(
+        var("DOTFILES_GITHUB_RAW_URL").ok(),
+        read_config().github_raw,
+        GITHUB_RAW,
+    )
+}
+
+pub fn golang_dl() -> String {
+    pick(
+        var("DOTFILES_GOLANG_DL_URL").ok(),
+        read_config().golang_dl,
+        GOLANG_DL,
+    )
+}
+
+// golang_index() is the JSON list of Go releases, with their files and SHA-256 hashes
+pub fn golang_index() -> String {
+    pick(
+        var("DOTFILES_GOLANG_INDEX_URL").ok(),
+        read_config().golang_index,
+        GOLANG_INDEX,
+    )
+}
+
+// nodejs_dist() has the releases for `platform`, which for musl are unofficial
+pub fn nodejs_dist(platform: &Platform) -> String {
+    pick(
+        var("DOTFILES_NODEJS_DIST_URL").ok(),
+        read_config().nodejs_dist,
+        nodejs_default(platform),
+    )
+}
+
+// pypi() has the JSON API for each project, e.g. "black/json"
+pub fn pypi() -> String {
+    pick(var("DOTFILES_PYPI_URL").ok(), read_config().pypi, PYPI)
+}
+
+// rustup_dist() has rustup-init for each target, e.g. "dist/x86_64-unknown-linux-gnu/rustup-init"
+pub fn rustup_dist() -> String {
+    pick(
+        var("DOTFILES_RUSTUP_DIST_URL").ok(),
+        read_config().rustup_dist,
+        RUSTUP_DIST,
+    )
+}
+
+fn nodejs_default(platform: &Platform) -> &'static str {
+    if platform.is_musl() {
+        NODEJS_DIST_MUSL
+    } else {
+        NODEJS_DIST
+    }
+}
+
+// pick() prefers environment variables, then config, then our default
+fn pick<S>(env: Option<String>, config: Option<String>, default: S) -> String
+where
+    S: AsRef<str>,
+{
+    let url = env
+        .into_iter()
+        .chain(config)
+        .find(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| String::from(default.as_ref()));
+    String::from(url.trim().trim_end_matches('/'))
+}
+
+fn read_config() -> Mirrors {
+    let cfg_path = utils::env::home_dir().join(".dotfiles/config/mirrors.toml");
+
+    let contents = match fs::read_to_string(&cfg_path) {
+        Ok(s) => s,
+        Err(_error) => {
+            // mirrors are optional, so a missing file is expected
+            return Default::default();
+        }
+    };
+
+    match toml::from_str(&contents) {
+        Ok(c) => c,
+        Err(error) => {
+            println!(
+                "warning: mirrors: unable to parse {}, {}",
+                &cfg_path.display(),
+                error
+            );
+            Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_prefers_env_then_config_then_default() {
+        assert_eq!(
+            pick(
+                Some(String::from("file:///srv/env/")),
+                Some(String::from("https://config.example.com")),
+                GOLANG_DL,
+            ),
+            "file:///srv/env"
+        );
+        assert_eq!(
+            pick(
+                Some(String::from(" ")),
+                Some(String::from("https://config.example.com/go/")),
+                GOLANG_DL,
+            ),
+            "https://config.example.com/go"
+        );
+        assert_eq!(pick(None, None, GOLANG_DL), GOLANG_DL);
+    }
+
+    #[test]
+    fn nodejs_default_is_unofficial_for_musl() {
+        let musl = "linux-x86_64-musl".parse::<Platform>().expect("must parse");
+        let gnu = "linux-x86_64".parse::<Platform>().expect("must parse");
+        assert_eq!(nodejs_default(&musl), NODEJS_DIST_MUSL);
+        assert_eq!(nodejs_default(&gnu), NODEJS_DIST);
+        assert_eq!(
+            pick(
+                None,
+                Some(String::from("file:///srv/mirror/node")),
+                nodejs_default(&musl)
+            ),
+            "file:///srv/mirror/node"
+        );
+    }
+
+    #[test]
+    fn parse_mirrors_toml() {
+        let input = r#"
+            golang_dl = "https://artifacts.example.com/go"
+            nodejs_dist = "file:///srv/mirror/node"
+            "#;
+        let mirrors: Mirrors = toml::from_str(input).expect("must parse");
+        assert_eq!(mirrors.github_api, None);
+        assert_eq!(
+            mirrors.golang_dl,
+            Some(String::from("https://artifacts.example.com/go"))
+        );
+        assert_eq!(
+            mirrors.nodejs_dist,
+            Some(String::from("file:///srv/mirror/node"))
+        );
+    }
+}
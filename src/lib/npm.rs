@@ -0,0 +1,458 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use semver::VersionReq;
+use serde_derive::Deserialize;
+
+use crate::{
+    lib::{favourites::Favourites, version},
+    utils::{
+        self,
+        process::{command_output, command_spawn_wait},
+    },
+};
+
+// NpmFavourites are global packages, e.g. "typescript", "typescript@4", or "@vue/cli@^4.5",
+// installed with npm, pnpm, or yarn (per `manager` in nodejs.toml)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NpmFavourites {
+    install: Vec<String>,
+    manager: Manager,
+    uninstall: Vec<String>,
+}
+
+impl NpmFavourites {
+    pub fn manager(&self) -> Manager {
+        self.manager
+    }
+
+    // found_versions() are e.g. "typescript@4.0.5" for each installed favourite
+    pub fn found_versions(&self) -> Vec<String> {
+        let wanted = self.wanted();
+        let mut versions: Vec<String> = self
+            .manager
+            .installed_versions()
+            .into_iter()
+            .filter(|(pkg, _)| wanted.contains(pkg))
+            .map(|(pkg, version)| format!("{}@{}", pkg, version))
+            .collect();
+        versions.sort();
+        versions
+    }
+
+    // installed_specs() are the `install` entries that are already installed,
+    // e.g. to install again for the latest version within their range
+    pub fn installed_specs(&self) -> Vec<String> {
+        let found = self.found();
+        self.install
+            .iter()
+            .filter(|i| found.iter().any(|f| f == split_spec(i).0))
+            .cloned()
+            .collect()
+    }
+
+    // specs() are the packages in `install`, with their version ranges (if any)
+    pub fn specs(&self) -> Vec<(String, Option<String>)> {
+        self.install
+            .iter()
+            .map(|i| {
+                let (pkg, range) = split_spec(i);
+                (String::from(pkg), range.map(String::from))
+            })
+            .collect()
+    }
+}
+
+impl Favourites for NpmFavourites {
+    fn cull(&mut self) -> io::Result<()> {
+        let surplus = self.surplus();
+        if surplus.is_empty() {
+            return Ok(());
+        }
+
+        let mut args = self.manager.uninstall_args();
+        args.extend(surplus);
+        self.manager.run(&args)
+    }
+    fn fill(&mut self) -> io::Result<()> {
+        let missing = self.missing();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut args = self.manager.install_args();
+        args.extend(
+            self.install
+                .iter()
+                .filter(|i| missing.iter().any(|m| m == split_spec(i).0))
+                .cloned(),
+        );
+        self.manager.run(&args)
+    }
+    // found() leaves out packages without their executables, or outside their version range,
+    // so that fill() reinstalls them
+    fn found(&self) -> Vec<String> {
+        let specs = self.specs();
+        // pnpm and yarn spawn a process for each of these, so we only ask once
+        let root = self.manager.global_root();
+        let bin_dir = self.manager.global_bin();
+        let mut found: Vec<String> = self
+            .manager
+            .installed_versions()
+            .into_iter()
+            .filter(|(pkg, version)| {
+                specs
+                    .iter()
+                    .find(|(p, _)| p == pkg)
+                    .is_none_or(|(_, range)| accepts_version(range.as_deref(), version))
+            })
+            .filter(|(pkg, _)| {
+                root.as_deref()
+                    .is_some_and(|r| is_bin_linked(pkg, r, bin_dir.as_deref()))
+            })
+            .map(|(pkg, _)| pkg)
+            .collect();
+        found.sort();
+        found
+    }
+    fn wanted(&self) -> Vec<String> {
+        self.specs().into_iter().map(|(pkg, _)| pkg).collect()
+    }
+    fn unwanted(&self) -> Vec<String> {
+        self.uninstall
+            .iter()
+            .map(|u| String::from(split_spec(u).0))
+            .collect()
+    }
+}
+
+// Manager is what installs global packages
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    #[default]
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+impl Manager {
+    pub fn exe(&self) -> &'static str {
+        match self {
+            Manager::Npm => "npm",
+            Manager::Pnpm => "pnpm",
+            Manager::Yarn => "yarn",
+        }
+    }
+
+    pub fn has(&self) -> bool {
+        command_output(self.exe(), &["--version"]).is_ok_and(|o| o.status.success())
+    }
+
+    // installed_versions() are the top-level global packages
+    pub fn installed_versions(&self) -> HashMap<String, String> {
+        match self {
+            Manager::Npm | Manager::Pnpm => {
+                match command_output(self.exe(), &["ls", "--global", "--depth=0", "--json"]) {
+                    Ok(output) => parse_ls_json(&String::from_utf8_lossy(&output.stdout)),
+                    Err(_error) => HashMap::new(),
+                }
+            }
+            // yarn has no JSON for its global packages, so we read them from disk
+            Manager::Yarn => {
+                let dir = match self.stdout(&["global", "dir"]) {
+                    Some(d) => PathBuf::from(d),
+                    None => return HashMap::new(),
+                };
+                let manifest: Manifest = match read_json(&dir.join("package.json")) {
+                    Ok(m) => m,
+                    Err(_error) => return HashMap::new(),
+                };
+                manifest
+                    .dependencies
+                    .into_keys()
+                    .filter_map(|pkg| {
+                        let path = dir.join("node_modules").join(&pkg).join("package.json");
+                        let installed: Manifest = read_json(&path).ok()?;
+                        Some((pkg, installed.version?))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    pub fn install_args(&self) -> Vec<String> {
+        self.args(match self {
+            Manager::Npm => &["install", "--global"],
+            Manager::Pnpm => &["add", "--global"],
+            Manager::Yarn => &["global", "add"],
+        })
+    }
+
+    pub fn run<S>(&self, args: &[S]) -> io::Result<()>
+    where
+        S: AsRef<str>,
+    {
+        command_spawn_wait(self.exe(), args).map(|_| ())
+    }
+
+    // version() is of the manager itself, e.g. "6.14.8"
+    pub fn version(&self) -> Option<String> {
+        self.stdout(&["--version"])
+    }
+
+    pub fn uninstall_args(&self) -> Vec<String> {
+        self.args(match self {
+            Manager::Npm => &["uninstall", "--global"],
+            Manager::Pnpm => &["remove", "--global"],
+            Manager::Yarn => &["global", "remove"],
+        })
+    }
+
+    fn args(&self, args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| String::from(*a)).collect()
+    }
+
+    fn global_bin(&self) -> Option<PathBuf> {
+        match self {
+            Manager::Npm => Some(utils::nodejs::bin_dir()),
+            Manager::Pnpm => self.stdout(&["bin", "--global"]).map(PathBuf::from),
+            Manager::Yarn => self.stdout(&["global", "bin"]).map(PathBuf::from),
+        }
+    }
+
+    // global_root() is the node_modules directory with the global packages
+    fn global_root(&self) -> Option<PathBuf> {
+        match self {
+            Manager::Npm => Some(utils::nodejs::lib_dir().join("node_modules")),
+            Manager::Pnpm => self.stdout(&["root", "--global"]).map(PathBuf::from),
+            Manager::Yarn => self
+                .stdout(&["global", "dir"])
+                .map(|d| PathBuf::from(d).join("node_modules")),
+        }
+    }
+
+    fn stdout(&self, args: &[&str]) -> Option<String> {
+        let output = command_output(self.exe(), args).ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let line = stdout.lines().next()?.trim();
+        if line.is_empty() {
+            None
+        } else {
+            Some(String::from(line))
+        }
+    }
+}
+
+// Bin is either a path for an executable named after the package, or executables by name
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Bin {
+    Path(String),
+    Names(HashMap<String, String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Dependency {
+    version: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Globals {
+    #[serde(default)]
+    dependencies: HashMap<String, Dependency>,
+}
+
+// Manifest is a package.json
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Manifest {
+    bin: Option<Bin>,
+    dependencies: HashMap<String, String>,
+    version: Option<String>,
+}
+
+impl Manifest {
+    fn bins(&self, pkg: &str) -> Vec<String> {
+        match &self.bin {
+            // e.g. "@vue/cli" would be "cli"
+            Some(Bin::Path(path)) if !path.is_empty() => {
+                vec![String::from(pkg.rsplit('/').next().unwrap_or(pkg))]
+            }
+            Some(Bin::Names(names)) => names.keys().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// accepts_version() is true when `version` is within a semver `range`,
+// or when `range` is a dist-tag like "latest" that we cannot check offline
+fn accepts_version(range: Option<&str>, version: &str) -> bool {
+    let range = match range {
+        Some(r) => r.trim(),
+        None => return true,
+    };
+    let version = match semver::Version::parse(version) {
+        Ok(v) => v,
+        Err(_error) => return version::matches(range, version),
+    };
+    // e.g. ">=4 <5 || ^6", where npm separates comparators with spaces and not commas
+    let mut parsed_any = false;
+    for alternative in range.split("||") {
+        let comparators: Vec<&str> = alternative.split_whitespace().collect();
+        if let Ok(req) = VersionReq::parse(&comparators.join(", ")) {
+            parsed_any = true;
+            if req.matches(&version) {
+                return true;
+            }
+        }
+    }
+    if parsed_any {
+        false
+    } else {
+        !range.starts_with(|c: char| c.is_ascii_digit())
+            || version::matches(range, &version.to_string())
+    }
+}
+
+// is_bin_linked() is whether all of the package's executables are where we can run them,
+// given the manager's global_root() and global_bin()
+fn is_bin_linked(pkg: &str, root: &Path, bin_dir: Option<&Path>) -> bool {
+    let manifest: Manifest = match read_json(&root.join(pkg).join("package.json")) {
+        Ok(m) => m,
+        Err(_error) => return false,
+    };
+    manifest
+        .bins(pkg)
+        .iter()
+        .all(|b| bin_dir.is_some_and(|d| d.join(b).exists()) || which::which(b).is_ok())
+}
+
+// parse_ls_json() reads `npm ls --json`, or `pnpm ls --json` which is a list of those
+fn parse_ls_json(stdout: &str) -> HashMap<String, String> {
+    let globals: Vec<Globals> = match serde_json::from_str::<Vec<Globals>>(stdout) {
+        Ok(g) => g,
+        Err(_error) => match serde_json::from_str::<Globals>(stdout) {
+            Ok(g) => vec![g],
+            Err(_error) => return HashMap::new(),
+        },
+    };
+    globals
+        .into_iter()
+        .flat_map(|g| g.dependencies)
+        .filter_map(|(pkg, dep)| Some((pkg, dep.version?)))
+        .collect()
+}
+
+fn read_json<T>(path: &Path) -> io::Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// split_spec() allows for scoped packages, e.g. "@vue/cli@4" is "@vue/cli" and "4"
+fn split_spec(spec: &str) -> (&str, Option<&str>) {
+    let at = spec.char_indices().skip(1).find(|(_, c)| *c == '@');
+    match at {
+        Some((i, _)) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_version_in_npm_ranges() {
+        assert!(accepts_version(None, "4.1.2"));
+        assert!(accepts_version(Some("4"), "4.1.2"));
+        assert!(!accepts_version(Some("4"), "3.9.7"));
+        assert!(accepts_version(Some("^4.1"), "4.1.2"));
+        assert!(accepts_version(Some("4.x"), "4.0.5"));
+        assert!(accepts_version(Some(">=4 <5"), "4.9.0"));
+        assert!(!accepts_version(Some(">=4 <5"), "5.0.0"));
+        assert!(accepts_version(Some("^3 || ^4"), "4.0.0"));
+        assert!(accepts_version(Some("latest"), "4.0.0"));
+    }
+
+    #[test]
+    fn parse_nodejs_toml() {
+        let input = r#"
+            channel = "lts"
+            manager = "pnpm"
+            install = ["typescript@4", "@vue/cli@^4.5", "prettier"]
+            uninstall = ["tslint@5"]
+            "#;
+        let favs: NpmFavourites = toml::from_str(input).expect("must parse");
+        assert_eq!(favs.manager(), Manager::Pnpm);
+        assert_eq!(favs.wanted(), vec!["typescript", "@vue/cli", "prettier"]);
+        assert_eq!(favs.unwanted(), vec!["tslint"]);
+        assert_eq!(favs.specs()[1].1, Some(String::from("^4.5")));
+
+        let favs: NpmFavourites = toml::from_str("").expect("must parse");
+        assert_eq!(favs.manager(), Manager::Npm);
+        assert_eq!(favs.manager().install_args(), vec!["install", "--global"]);
+        assert_eq!(Manager::Yarn.uninstall_args(), vec!["global", "remove"]);
+    }
+
+    #[test]
+    fn parse_npm_and_pnpm_ls_json() {
+        let npm =
+            r#"{"dependencies":{"npm":{"version":"6.14.8"},"typescript":{"version":"4.0.5"}}}"#;
+        let pnpm = r#"[{"path":"/home/user/.pnpm-global/4","dependencies":{"typescript":{"version":"4.0.5"}}}]"#;
+        assert_eq!(parse_ls_json(npm).len(), 2);
+        assert_eq!(
+            parse_ls_json(pnpm).get("typescript"),
+            Some(&String::from("4.0.5"))
+        );
+        assert!(parse_ls_json("npm ERR!").is_empty());
+    }
+
+    #[test]
+    fn manifest_bins_by_name_or_package() {
+        let named: Manifest =
+            serde_json::from_str(r#"{"bin":{"tsc":"./bin/tsc","tsserver":"./bin/tsserver"}}"#)
+                .expect("must parse");
+        let mut bins = named.bins("typescript");
+        bins.sort();
+        assert_eq!(bins, vec!["tsc", "tsserver"]);
+        let path: Manifest = serde_json::from_str(r#"{"bin":"./bin/cli.js"}"#).expect("must parse");
+        assert_eq!(path.bins("@vue/cli"), vec!["cli"]);
+    }
+
+    #[test]
+    fn is_bin_linked_checks_bin_dir() {
+        let temp_path = utils::fs::mkdtemp().expect("must create");
+        let root = temp_path.join("node_modules");
+        let bin_dir = temp_path.join("bin");
+        let pkg_dir = root.join("dotfiles-test-pkg");
+        fs::create_dir_all(&pkg_dir).expect("must create dirs");
+        fs::create_dir_all(&bin_dir).expect("must create dirs");
+        fs::write(
+            pkg_dir.join("package.json"),
+            r#"{"bin":{"dotfiles-test-bin":"./cli.js"}}"#,
+        )
+        .expect("must write");
+
+        assert!(!is_bin_linked("dotfiles-test-pkg", &root, Some(&bin_dir)));
+        fs::write(bin_dir.join("dotfiles-test-bin"), "").expect("must write");
+        assert!(is_bin_linked("dotfiles-test-pkg", &root, Some(&bin_dir)));
+        assert!(!is_bin_linked("dotfiles-missing", &root, Some(&bin_dir)));
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn split_spec_allows_scopes() {
+        assert_eq!(split_spec("typescript@4"), ("typescript", Some("4")));
+        assert_eq!(split_spec("@vue/cli@4"), ("@vue/cli", Some("4")));
+        assert_eq!(split_spec("@vue/cli"), ("@vue/cli", None));
+    }
+}
@@ -0,0 +1,381 @@
+use std::{cmp::Ordering, fmt};
+
+// Version is a Python package version, e.g. "20.8b1", "2.0.post1", or "1!2.0.dev3",
+// see https://www.python.org/dev/peps/pep-0440/
+#[derive(Clone, Debug)]
+pub struct Version {
+    dev: Option<u64>,
+    epoch: u64,
+    post: Option<u64>,
+    pre: Option<(Phase, u64)>,
+    release: Vec<u64>,
+    text: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Phase {
+    Alpha,
+    Beta,
+    Rc,
+}
+
+impl Version {
+    // parse() accepts the alternative spellings that PEP 440 normalises,
+    // e.g. "1.0-RC1", "v2.0", "1.0.alpha.1", and "1.0-1" (a post-release)
+    pub fn parse<S>(input: S) -> Option<Version>
+    where
+        S: AsRef<str>,
+    {
+        let text = input.as_ref().trim();
+        let lower = text.to_lowercase();
+        let s = lower.trim_start_matches('v');
+        let s = s.split('+').next()?; // we ignore local versions, e.g. "+ubuntu1"
+
+        let (epoch, s) = match s.split_once('!') {
+            Some((e, rest)) => (e.parse().ok()?, rest),
+            None => (0, s),
+        };
+
+        let mut cursor = Cursor(s);
+        let mut release = Vec::<u64>::new();
+        loop {
+            release.push(cursor.number()?);
+            if !(cursor.0.starts_with('.')
+                && cursor.0[1..].starts_with(|c: char| c.is_ascii_digit()))
+            {
+                break;
+            }
+            cursor.0 = &cursor.0[1..];
+        }
+
+        let mut pre = None;
+        let mark = cursor.0;
+        cursor.separator();
+        if let Some(phase) = cursor.pre_phase() {
+            cursor.separator();
+            pre = Some((phase, cursor.number().unwrap_or_default()));
+        } else {
+            cursor.0 = mark;
+        }
+
+        let mut post = None;
+        let mark = cursor.0;
+        if cursor.0.starts_with('-') && cursor.0[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            cursor.0 = &cursor.0[1..];
+            post = cursor.number();
+        } else {
+            cursor.separator();
+            if cursor.keyword(&["post", "rev", "r"]) {
+                cursor.separator();
+                post = Some(cursor.number().unwrap_or_default());
+            } else {
+                cursor.0 = mark;
+            }
+        }
+
+        let mut dev = None;
+        cursor.separator();
+        if cursor.keyword(&["dev"]) {
+            cursor.separator();
+            dev = Some(cursor.number().unwrap_or_default());
+        }
+
+        if !cursor.0.is_empty() {
+            return None;
+        }
+        Some(Version {
+            dev,
+            epoch,
+            post,
+            pre,
+            release,
+            text: String::from(text),
+        })
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    // key() orders e.g. 1.0.dev1 < 1.0a1.dev1 < 1.0a1 < 1.0b1 < 1.0rc1 < 1.0 < 1.0.post1
+    #[allow(clippy::type_complexity)]
+    fn key(&self) -> (u64, Vec<u64>, (u8, u64), Option<u64>, (u8, u64)) {
+        let pre = match (self.pre, self.post, self.dev) {
+            (None, None, Some(_)) => (0, 0),
+            (Some((phase, n)), _, _) => (phase as u8 + 1, n),
+            _ => (u8::MAX, 0),
+        };
+        let dev = match self.dev {
+            Some(n) => (0, n),
+            None => (1, 0),
+        };
+        (self.epoch, self.trimmed_release(), pre, self.post, dev)
+    }
+
+    // trimmed_release() drops trailing zeros, as 1.0 == 1.0.0
+    fn trimmed_release(&self) -> Vec<u64> {
+        let mut release = self.release.clone();
+        while release.len() > 1 && release.last() == Some(&0) {
+            release.pop();
+        }
+        release
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Eq for Version {}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Specifier is one clause of a version specifier, e.g. ">=2" or "==1.4.*"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Specifier {
+    op: String,
+    version: String,
+}
+
+impl Specifier {
+    pub fn contains(&self, version: &Version) -> bool {
+        if self.op == "===" {
+            return version.text == self.version;
+        }
+        if let Some(prefix) = self.version.strip_suffix(".*") {
+            let matched = Version::parse(prefix).is_some_and(|p| {
+                p.epoch == version.epoch && padded(&version.release, p.release.len()) == p.release
+            });
+            return match self.op.as_str() {
+                "==" => matched,
+                "!=" => !matched,
+                _ => false,
+            };
+        }
+
+        let spec = match Version::parse(&self.version) {
+            Some(v) => v,
+            None => return false,
+        };
+        match self.op.as_str() {
+            "==" => version == &spec,
+            "!=" => version != &spec,
+            "<=" => version <= &spec,
+            ">=" => version >= &spec,
+            // "<2" does not allow "2.0a1", and ">2" does not allow "2.post1"
+            "<" => {
+                version < &spec
+                    && !(version.is_prerelease()
+                        && !spec.is_prerelease()
+                        && version.trimmed_release() == spec.trimmed_release())
+            }
+            ">" => {
+                version > &spec
+                    && !(version.post.is_some()
+                        && spec.post.is_none()
+                        && version.trimmed_release() == spec.trimmed_release())
+            }
+            // "~=2.2" means ">=2.2, ==2.*"
+            "~=" if spec.release.len() > 1 => {
+                let prefix = &spec.release[..spec.release.len() - 1];
+                version >= &spec
+                    && version.epoch == spec.epoch
+                    && padded(&version.release, prefix.len()) == prefix
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Specifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.op, self.version)
+    }
+}
+
+// SpecifierSet is comma-separated specifiers that must all match, e.g. ">=2,<3"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpecifierSet(Vec<Specifier>);
+
+impl SpecifierSet {
+    pub fn parse<S>(input: S) -> Option<SpecifierSet>
+    where
+        S: AsRef<str>,
+    {
+        let mut specifiers = Vec::<Specifier>::new();
+        for clause in input.as_ref().split(',') {
+            let clause = clause.trim();
+            if clause.is_empty() {
+                continue;
+            }
+            let op_len = clause
+                .find(|c: char| !"=!<>~".contains(c))
+                .unwrap_or(clause.len());
+            let (op, version) = clause.split_at(op_len);
+            if !["~=", "==", "!=", "<=", ">=", "<", ">", "==="].contains(&op) {
+                return None;
+            }
+            specifiers.push(Specifier {
+                op: String::from(op),
+                version: String::from(version.trim()),
+            });
+        }
+        Some(SpecifierSet(specifiers))
+    }
+
+    // allows_prereleases() is when a specifier names one, e.g. "==20.8b1"
+    pub fn allows_prereleases(&self) -> bool {
+        self.0
+            .iter()
+            .any(|s| Version::parse(&s.version).is_some_and(|v| v.is_prerelease()))
+    }
+
+    pub fn contains(&self, version: &Version) -> bool {
+        self.0.iter().all(|s| s.contains(version))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for SpecifierSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let clauses: Vec<String> = self.0.iter().map(|s| s.to_string()).collect();
+        write!(f, "{}", clauses.join(","))
+    }
+}
+
+// Cursor consumes a version string from the front
+struct Cursor<'a>(&'a str);
+
+impl<'a> Cursor<'a> {
+    fn keyword(&mut self, words: &[&str]) -> bool {
+        for word in words {
+            if let Some(rest) = self.0.strip_prefix(word) {
+                self.0 = rest;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        let end = self
+            .0
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        let n = self.0[..end].parse().ok()?;
+        self.0 = &self.0[end..];
+        Some(n)
+    }
+
+    fn pre_phase(&mut self) -> Option<Phase> {
+        if self.keyword(&["alpha", "a"]) {
+            Some(Phase::Alpha)
+        } else if self.keyword(&["beta", "b"]) {
+            Some(Phase::Beta)
+        } else if self.keyword(&["preview", "pre", "rc", "c"]) {
+            Some(Phase::Rc)
+        } else {
+            None
+        }
+    }
+
+    fn separator(&mut self) {
+        self.0 = self.0.strip_prefix(['.', '-', '_']).unwrap_or(self.0);
+    }
+}
+
+fn padded(release: &[u64], len: usize) -> Vec<u64> {
+    let mut padded: Vec<u64> = release.iter().take(len).cloned().collect();
+    padded.resize(len, 0);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        Version::parse(s).expect("must parse")
+    }
+
+    #[test]
+    fn parse_alternative_spellings() {
+        assert_eq!(v("1.0-RC1"), v("1.0rc1"));
+        assert_eq!(v("v2.0"), v("2"));
+        assert_eq!(v("1.0.alpha.1"), v("1.0a1"));
+        assert_eq!(v("1.0-1"), v("1.0.post1"));
+        assert_eq!(v("1.0.dev"), v("1.0.dev0"));
+        assert_eq!(v("2.0+ubuntu1"), v("2.0"));
+        assert!(v("20.8b1").is_prerelease());
+        assert!(!v("2.0.post1").is_prerelease());
+        assert!(Version::parse("latest").is_none());
+        assert!(Version::parse("1.0foo").is_none());
+    }
+
+    #[test]
+    fn versions_order_per_pep_440() {
+        let ordered = [
+            "1.0.dev1",
+            "1.0a1.dev1",
+            "1.0a1",
+            "1.0b1",
+            "1.0rc1",
+            "1.0",
+            "1.0.post1.dev1",
+            "1.0.post1",
+            "1.1",
+            "1!0.1",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn specifiers_contain_versions() {
+        let contains = |spec: &str, version: &str| {
+            SpecifierSet::parse(spec)
+                .expect("must parse")
+                .contains(&v(version))
+        };
+        assert!(contains("==20.8b1", "20.8b1"));
+        assert!(!contains("==20.8b1", "20.8"));
+        assert!(contains(">=2", "2.0.1"));
+        assert!(contains(">=2,<3", "2.9"));
+        assert!(!contains(">=2,<3", "3.0"));
+        assert!(!contains("<3", "3.0a1"));
+        assert!(!contains(">2", "2.post1"));
+        assert!(contains("~=2.2", "2.9"));
+        assert!(!contains("~=2.2", "3.0"));
+        assert!(contains("~=1.4.5", "1.4.9"));
+        assert!(!contains("~=1.4.5", "1.5.0"));
+        assert!(contains("==1.4.*", "1.4.2"));
+        assert!(!contains("!=1.4.*", "1.4.2"));
+        assert!(contains("", "0.1"));
+        assert!(SpecifierSet::parse("=>2").is_none());
+        assert!(SpecifierSet::parse("==20.8b1")
+            .expect("must parse")
+            .allows_prereleases());
+    }
+}
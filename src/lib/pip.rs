@@ -1,15 +1,40 @@
-use std::{io, path::PathBuf, str};
+use std::{
+    collections::HashMap,
+    env::consts::{EXE_SUFFIX, OS},
+    fs, io,
+    path::PathBuf,
+    str,
+};
 
 use serde_derive::Deserialize;
 
 use crate::{
-    lib::favourites::Favourites,
-    utils::process::{command_output, command_spawn_wait},
+    lib::{
+        favourites::Favourites,
+        pep440::{SpecifierSet, Version},
+        python,
+    },
+    utils::{
+        env::home_dir,
+        fs::{delete_if_exists, symbolic_link},
+        process::{command_output, command_spawn_wait},
+        pypi::normalize_name,
+    },
 };
 
 const PIPS: &[&str] = &["pip", "pip3"];
 const VERSION_ARGS: &[&str] = &["--version"];
 
+// ENTRY_POINTS prints the scripts that a package installs,
+// so that we only link those and not e.g. "pip" or "python"
+const ENTRY_POINTS: &str = r#"
+import importlib.metadata, sys
+dist = importlib.metadata.distribution(sys.argv[1])
+for ep in dist.entry_points:
+    if ep.group in ("console_scripts", "gui_scripts"):
+        print(ep.name)
+"#;
+
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct PipPackage {
     pub name: String,
@@ -19,8 +44,50 @@ pub struct PipPackage {
 #[derive(Debug, Default, Deserialize)]
 #[serde(default)]
 pub struct PipFavourites {
-    install: Vec<String>,
-    uninstall: Vec<String>,
+    install: Vec<Package>,
+    // isolated gives every package without its own setting a virtualenv
+    isolated: bool,
+    uninstall: Vec<Package>,
+}
+
+impl PipFavourites {
+    // installed_versions() are the versions of installed packages by normalised name,
+    // from the user site for shared packages, and from their own virtualenv otherwise
+    pub fn installed_versions(&self) -> HashMap<String, String> {
+        let mut versions: HashMap<String, String> = user_site_versions()
+            .into_iter()
+            .filter(|(name, _)| !self.is_isolated(name))
+            .collect();
+        for name in found_venvs() {
+            // a venv for a shared package is stale, and one for an isolated package may be broken
+            let wanted_shared = self.find(&name).is_some() && !self.is_isolated(&name);
+            if wanted_shared || versions.contains_key(&name) {
+                continue;
+            }
+            if let Some(version) = venv_version(&name) {
+                versions.insert(name, version);
+            }
+        }
+        versions
+    }
+
+    // is_isolated() is whether `name` belongs in its own virtualenv
+    pub fn is_isolated(&self, name: &str) -> bool {
+        self.find(name)
+            .is_some_and(|p| p.is_isolated(self.isolated))
+    }
+
+    // requirements() are each `install` entry, and whether it is isolated
+    pub fn requirements(&self) -> Vec<(Requirement, bool)> {
+        self.install
+            .iter()
+            .map(|p| (p.requirement(), p.is_isolated(self.isolated)))
+            .collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&Package> {
+        self.install.iter().find(|p| p.name() == name)
+    }
 }
 
 impl Favourites for PipFavourites {
@@ -30,9 +97,18 @@ impl Favourites for PipFavourites {
             return Ok(());
         }
 
+        for name in &surplus {
+            if venv_dir(name).is_dir() {
+                delete_venv(name)?;
+            }
+        }
+        let shared = user_site_versions();
         let mut args = vec![String::from("uninstall"), String::from("--yes")];
-        args.extend(surplus);
-        pip(&args)
+        args.extend(surplus.into_iter().filter(|s| shared.contains_key(s)));
+        if args.len() > 2 {
+            pip(&args)?;
+        }
+        Ok(())
     }
     fn fill(&mut self) -> io::Result<()> {
         let missing = self.missing();
@@ -40,27 +116,277 @@ impl Favourites for PipFavourites {
             return Ok(());
         }
 
+        let (isolated, shared): (Vec<String>, Vec<String>) =
+            missing.into_iter().partition(|m| self.is_isolated(m));
+        let requirement = |name: &str| match self.find(name) {
+            Some(p) => p.requirement().text,
+            None => String::from(name),
+        };
+
+        // a package's scripts would clash in ~/.local/bin if it were in both places
+        let user_site = user_site_versions();
+        let moving: Vec<&String> = isolated
+            .iter()
+            .filter(|i| user_site.contains_key(*i))
+            .collect();
+        if !moving.is_empty() {
+            let mut args = vec!["uninstall", "--yes"];
+            args.extend(moving.iter().map(|m| m.as_str()));
+            pip(&args)?;
+        }
+        for name in &isolated {
+            install_venv(name, &requirement(name))?;
+        }
+
+        if shared.is_empty() {
+            return Ok(());
+        }
+        for name in shared.iter().filter(|s| venv_dir(s).is_dir()) {
+            delete_venv(name)?;
+        }
         let mut args = vec![String::from("install"), String::from("--user")];
-        args.extend(missing);
+        args.extend(shared.iter().map(|s| requirement(s)));
         pip(&args)
     }
+    // found() leaves out packages with versions that their `install` entry does not allow,
+    // so that fill() reinstalls them
     fn found(&self) -> Vec<String> {
-        found_versions().iter().map(|k| k.name.clone()).collect()
+        let mut found: Vec<String> = self
+            .installed_versions()
+            .into_iter()
+            .filter(|(name, version)| {
+                self.find(name)
+                    .is_none_or(|p| p.requirement().accepts_version(version))
+            })
+            .map(|(name, _)| name)
+            .collect();
+        found.sort();
+        found
     }
     fn wanted(&self) -> Vec<String> {
-        self.install.clone()
+        self.install.iter().map(|p| p.name()).collect()
     }
     fn unwanted(&self) -> Vec<String> {
-        self.uninstall.clone()
+        self.uninstall.iter().map(|p| p.name()).collect()
     }
 }
 
+// Package is a requirement, e.g. "black" or "black==20.8b1",
+// or a table, e.g. `{ name = "httpie>=2", isolated = true }`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Package {
+    Name(String),
+    Spec {
+        name: String,
+        isolated: Option<bool>,
+    },
+}
+
+impl Package {
+    pub fn is_isolated(&self, default: bool) -> bool {
+        match self {
+            Package::Name(_) => default,
+            Package::Spec { isolated, .. } => isolated.unwrap_or(default),
+        }
+    }
+
+    // name() is normalised, e.g. "pyyaml" for "PyYAML>=5"
+    pub fn name(&self) -> String {
+        normalize_name(&self.requirement().name)
+    }
+
+    pub fn requirement(&self) -> Requirement {
+        let text = match self {
+            Package::Name(name) => name,
+            Package::Spec { name, .. } => name,
+        };
+        Requirement::parse(text).unwrap_or_else(|| Requirement {
+            name: text.clone(),
+            specifiers: SpecifierSet::default(),
+            text: text.clone(),
+        })
+    }
+}
+
+// Requirement is e.g. "black==20.8b1", "httpie>=2", or "requests[socks]~=2.24",
+// see https://www.python.org/dev/peps/pep-0508/ (but without URLs)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Requirement {
+    pub name: String,
+    pub specifiers: SpecifierSet,
+    pub text: String, // for `pip install`
+}
+
+impl Requirement {
+    pub fn parse(input: &str) -> Option<Requirement> {
+        // we install regardless of environment markers, e.g. `; python_version < "3.8"`
+        let text = input.split(';').next()?.trim();
+        let end = text
+            .find(|c: char| !c.is_ascii_alphanumeric() && !"-_.".contains(c))
+            .unwrap_or(text.len());
+        let (name, mut rest) = text.split_at(end);
+        if name.is_empty() {
+            return None;
+        }
+        rest = rest.trim_start();
+        if rest.starts_with('[') {
+            rest = &rest[rest.find(']')? + 1..];
+        }
+        let rest = rest.trim().trim_start_matches('(').trim_end_matches(')');
+        Some(Requirement {
+            name: String::from(name),
+            specifiers: SpecifierSet::parse(rest)?,
+            text: String::from(text),
+        })
+    }
+
+    // accepts_version() is lenient with versions that are not PEP 440,
+    // which can only satisfy a requirement without specifiers
+    pub fn accepts_version(&self, version: &str) -> bool {
+        match Version::parse(version) {
+            Some(v) => self.specifiers.contains(&v),
+            None => self.specifiers.is_empty(),
+        }
+    }
+}
+
+// found_venvs() are the packages with a directory in ~/.local/share/venvs,
+// whether or not they still work
+pub fn found_venvs() -> Vec<String> {
+    let entries = match fs::read_dir(venvs_dir()) {
+        Ok(e) => e,
+        Err(_error) => return Vec::new(),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+// install_venv() (re)creates the virtualenv for `name` with `requirement`,
+// and links its scripts into ~/.local/bin
+pub fn install_venv(name: &str, requirement: &str) -> io::Result<()> {
+    let python = match python::which_v3() {
+        Some(p) => p,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "python >=3.x")),
+    };
+    let venv = venv_dir(name);
+    if venv.exists() {
+        delete_venv(name)?;
+    }
+    fs::create_dir_all(venvs_dir())?;
+    command_spawn_wait(&python, &["-m", "venv", &venv.to_string_lossy()])?;
+    command_spawn_wait(venv_exe(name, "pip"), &["install", requirement])?;
+    link_venv_scripts(name)
+}
+
+// upgrade_venv() upgrades the package (and its dependencies) within its virtualenv,
+// and links any new scripts
+pub fn upgrade_venv(name: &str, requirement: &str) -> io::Result<()> {
+    if venv_version(name).is_none() {
+        return install_venv(name, requirement);
+    }
+    command_spawn_wait(
+        venv_exe(name, "pip"),
+        &[
+            "install",
+            "--upgrade",
+            "--upgrade-strategy",
+            "eager",
+            requirement,
+        ],
+    )?;
+    link_venv_scripts(name)
+}
+
+// venv_version() is None when the virtualenv no longer runs or no longer has its package,
+// e.g. after a Python upgrade removes the interpreter that it was created with
+pub fn venv_version(name: &str) -> Option<String> {
+    let output = command_output(
+        venv_exe(name, "python"),
+        &[
+            "-c",
+            "import importlib.metadata, sys; print(importlib.metadata.version(sys.argv[1]))",
+            name,
+        ],
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(String::from(stdout.trim()))
+}
+
+fn delete_venv(name: &str) -> io::Result<()> {
+    unlink_venv_scripts(name)?;
+    delete_if_exists(venv_dir(name));
+    Ok(())
+}
+
+fn link_venv_scripts(name: &str) -> io::Result<()> {
+    let output = command_output(venv_exe(name, "python"), &["-c", ENTRY_POINTS, name])?;
+    let bin = local_bin_dir();
+    fs::create_dir_all(&bin)?;
+    for script in String::from_utf8_lossy(&output.stdout).lines() {
+        let file_name = format!("{}{}", script.trim(), EXE_SUFFIX);
+        let dest = bin.join(&file_name);
+        delete_if_exists(&dest);
+        symbolic_link(&venv_bin_dir(name).join(&file_name), &dest)?;
+    }
+    Ok(())
+}
+
+fn local_bin_dir() -> PathBuf {
+    home_dir().join(".local").join("bin")
+}
+
+// unlink_venv_scripts() finds links into the virtualenv,
+// as a broken one cannot tell us its scripts
+fn unlink_venv_scripts(name: &str) -> io::Result<()> {
+    let entries = match fs::read_dir(local_bin_dir()) {
+        Ok(e) => e,
+        Err(_error) => return Ok(()),
+    };
+    let venv = venv_dir(name);
+    for entry in entries.filter_map(|e| e.ok()) {
+        if fs::read_link(entry.path()).is_ok_and(|t| t.starts_with(&venv)) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn venv_bin_dir(name: &str) -> PathBuf {
+    venv_dir(name).join(if OS == "windows" { "Scripts" } else { "bin" })
+}
+
+fn venv_dir(name: &str) -> PathBuf {
+    venvs_dir().join(name)
+}
+
+fn venv_exe(name: &str, exe: &str) -> PathBuf {
+    venv_bin_dir(name).join(format!("{}{}", exe, EXE_SUFFIX))
+}
+
+fn venvs_dir() -> PathBuf {
+    home_dir().join(".local").join("share").join("venvs")
+}
+
 pub fn found_versions() -> Vec<PipPackage> {
-    if !has() {
-        return Vec::new();
+    let exe = match pip_exe() {
+        Ok(e) => e,
+        Err(_error) => return Vec::new(),
     };
-    let stdout = exe_output(&["list", "--format=json", "--user"]).unwrap_or_default();
-    parse_pippackages(stdout)
+    // only stdout, as pip warns on stderr, e.g. "WARNING: You are using pip version ..."
+    match command_output(exe, &["list", "--format=json", "--user"]) {
+        Ok(output) => parse_pippackages(String::from_utf8_lossy(&output.stdout)),
+        Err(_error) => Vec::new(),
+    }
 }
 
 pub fn has() -> bool {
@@ -96,6 +422,14 @@ where
     }
 }
 
+// user_site_versions() are from found_versions(), by normalised name
+fn user_site_versions() -> HashMap<String, String> {
+    found_versions()
+        .into_iter()
+        .map(|p| (normalize_name(&p.name), p.version))
+        .collect()
+}
+
 fn pip_exe() -> io::Result<PathBuf> {
     match which_v3() {
         Some(p) => Ok(p),
@@ -143,4 +477,45 @@ mod tests {
         ];
         assert_eq!(want, parse_pippackages(input));
     }
+
+    #[test]
+    fn parse_requirements() {
+        let req = Requirement::parse("black==20.8b1").expect("must parse");
+        assert_eq!(req.name, "black");
+        assert!(req.accepts_version("20.8b1"));
+        assert!(!req.accepts_version("19.10b0"));
+
+        let req = Requirement::parse("requests[socks] >=2.24, <3 ; python_version >= '3'")
+            .expect("must parse");
+        assert_eq!(req.name, "requests");
+        assert_eq!(req.text, "requests[socks] >=2.24, <3");
+        assert!(req.accepts_version("2.25.1"));
+        assert!(!req.accepts_version("3.0"));
+
+        let req = Requirement::parse("PyYAML").expect("must parse");
+        assert!(req.accepts_version("5.3.1"));
+        assert!(req.accepts_version("not-a-version"));
+        assert!(Requirement::parse("httpie=>2").is_none());
+        assert!(Requirement::parse(">=2").is_none());
+    }
+
+    #[test]
+    fn parse_pip_toml_with_isolated_packages() {
+        let input = r#"
+            isolated = true
+            install = ["black==20.8b1", { name = "PyLint", isolated = false }]
+            uninstall = ["httpie"]
+            "#;
+        let favs: PipFavourites = toml::from_str(input).expect("must parse");
+        assert_eq!(favs.wanted(), vec!["black", "pylint"]);
+        assert_eq!(favs.unwanted(), vec!["httpie"]);
+        assert!(favs.is_isolated("black"));
+        assert!(!favs.is_isolated("pylint"));
+        assert!(!favs.is_isolated("httpie"));
+
+        let shared: PipFavourites =
+            toml::from_str(r#"install = ["Py_YAML>=5"]"#).expect("must parse");
+        assert_eq!(shared.wanted(), vec!["py-yaml"]);
+        assert!(!shared.is_isolated("py-yaml"));
+    }
 }
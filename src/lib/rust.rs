@@ -1,35 +1,167 @@
-use std::{env::consts::OS, io, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    env::consts::OS,
+    fs::{self, File},
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use serde_derive::Deserialize;
 
-use crate::utils::{
-    env::home_dir,
-    process::{command_output, command_spawn_wait},
+use crate::{
+    lib::mirrors,
+    utils::{
+        self,
+        checksum::{self, HashReader},
+        env::home_dir,
+        fs::{mkdtemp, set_executable},
+        platform::Platform,
+        process::{command_output, command_spawn_wait},
+    },
 };
 
+// RustupConfig is the rustup part of rust.toml, e.g.
+//
+// default_toolchain = "stable"
+// [toolchains.stable]
+// components = ["clippy", "rust-src", "rust-analyzer"]
+// targets = ["wasm32-unknown-unknown"]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RustupConfig {
+    default_toolchain: Option<String>,
+    toolchains: BTreeMap<String, Toolchain>,
+}
+
+impl RustupConfig {
+    // default_toolchain() is "stable" unless rust.toml says otherwise,
+    // or it only has other toolchains
+    pub fn default_toolchain(&self) -> String {
+        if let Some(d) = &self.default_toolchain {
+            return d.clone();
+        }
+        let toolchains = self.toolchains();
+        if toolchains.contains_key("stable") {
+            return String::from("stable");
+        }
+        toolchains
+            .into_keys()
+            .next()
+            .unwrap_or_else(|| String::from("stable"))
+    }
+
+    // toolchains() always has at least stable with clippy and rustfmt
+    pub fn toolchains(&self) -> BTreeMap<String, Toolchain> {
+        let mut toolchains = self.toolchains.clone();
+        if toolchains.is_empty() {
+            toolchains.insert(
+                String::from("stable"),
+                Toolchain {
+                    components: vec![String::from("clippy"), String::from("rustfmt")],
+                    targets: Vec::new(),
+                },
+            );
+        }
+        if let Some(d) = &self.default_toolchain {
+            toolchains.entry(d.clone()).or_default();
+        }
+        toolchains
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Toolchain {
+    pub components: Vec<String>,
+    pub targets: Vec<String>, // for cross-compilation
+}
+
 pub fn bin_dir() -> PathBuf {
-    home_dir().join(".cargo").join("bin")
+    cargo_home().join("bin")
+}
+
+pub fn cargo_home() -> PathBuf {
+    home_dir().join(".cargo")
+}
+
+// default_toolchain() is the full name, e.g. "stable-x86_64-unknown-linux-gnu"
+pub fn default_toolchain() -> Option<String> {
+    let stdout = rustup_stdout(&["default"]).ok()?;
+    stdout.split_whitespace().next().map(String::from)
+}
+
+// has_component() allows for rustup listing most components with the host's triple,
+// e.g. "clippy-x86_64-unknown-linux-gnu", but some without, e.g. "rust-src"
+pub fn has_component(installed: &[String], component: &str) -> bool {
+    let with_host = format!("{}-{}", component, Platform::current().rust_target());
+    installed.iter().any(|i| i == component || i == &with_host)
 }
 
 pub fn has_rustup() -> bool {
     command_output(rustup_exe(), &["--version"]).is_ok()
 }
 
-pub fn rustup<S>(args: &[S]) -> io::Result<()>
-where
-    S: AsRef<str>,
-{
-    command_spawn_wait(rustup_exe(), args).map(|_| ())
+// has_toolchain() matches e.g. "stable" or "nightly-2020-07-01" with installed full names
+pub fn has_toolchain(installed: &[String], toolchain: &str) -> bool {
+    installed
+        .iter()
+        .any(|i| i == toolchain || i.starts_with(&format!("{}-", toolchain)))
+}
+
+// install_rustup() runs rustup-init (after checking its SHA-256) without any toolchains,
+// which we then install per rust.toml
+pub fn install_rustup() -> io::Result<()> {
+    let platform = Platform::current();
+    let url = format!(
+        "{}/dist/{}/rustup-init{}",
+        mirrors::rustup_dist(),
+        platform.rust_target(),
+        platform.exe_suffix()
+    );
+
+    let mut want = String::new();
+    utils::http::stream(format!("{}.sha256", &url))?.read_to_string(&mut want)?;
+    let want = want.split_whitespace().next().unwrap_or_default();
+
+    let temp_path = mkdtemp()?;
+    let init = temp_path.join(format!("rustup-init{}", platform.exe_suffix()));
+    let result = (|| {
+        let mut hashed = HashReader::new(utils::http::stream(&url)?);
+        io::copy(&mut hashed, &mut File::create(&init)?)?;
+        checksum::verify("rustup-init", &hashed.finish()?, want)?;
+        set_executable(&init)?;
+        command_spawn_wait(
+            &init,
+            &["-y", "--no-modify-path", "--default-toolchain", "none"],
+        )
+        .map(|_| ())
+    })();
+    fs::remove_dir_all(&temp_path).ok();
+    result
 }
 
-pub fn rustup_output<S>(args: &[S]) -> io::Result<String>
+pub fn installed_components(toolchain: &str) -> io::Result<Vec<String>> {
+    let stdout = rustup_stdout(&["component", "list", "--installed", "--toolchain", toolchain])?;
+    Ok(stdout.lines().map(|l| String::from(l.trim())).collect())
+}
+
+pub fn installed_targets(toolchain: &str) -> io::Result<Vec<String>> {
+    let stdout = rustup_stdout(&["target", "list", "--installed", "--toolchain", toolchain])?;
+    Ok(stdout.lines().map(|l| String::from(l.trim())).collect())
+}
+
+pub fn installed_toolchains() -> io::Result<Vec<String>> {
+    Ok(parse_toolchain_list(&rustup_stdout(&[
+        "toolchain",
+        "list",
+    ])?))
+}
+
+pub fn rustup<S>(args: &[S]) -> io::Result<()>
 where
     S: AsRef<str>,
 {
-    let output = command_output(rustup_exe(), args)?;
-    Ok(format!(
-        "{}\n{}",
-        String::from_utf8_lossy(&output.stdout).trim(),
-        String::from_utf8_lossy(&output.stderr).trim(),
-    ))
+    command_spawn_wait(rustup_exe(), args).map(|_| ())
 }
 
 pub fn rustup_version() -> String {
@@ -39,6 +171,18 @@ pub fn rustup_version() -> String {
     }
 }
 
+// e.g.
+// stable-x86_64-unknown-linux-gnu (default)
+// nightly-2020-07-01-x86_64-unknown-linux-gnu
+fn parse_toolchain_list(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .filter(|t| *t != "no") // "no installed toolchains"
+        .map(String::from)
+        .collect()
+}
+
 fn rustup_exe() -> PathBuf {
     bin_dir().join(if OS == "windows" {
         "rustup.exe"
@@ -46,3 +190,62 @@ fn rustup_exe() -> PathBuf {
         "rustup"
     })
 }
+
+// rustup_stdout() is for parsing, where rustup's "info: ..." on stderr would get in the way
+fn rustup_stdout<S>(args: &[S]) -> io::Result<String>
+where
+    S: AsRef<str>,
+{
+    let output = command_output(rustup_exe(), args)?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rustup_config() {
+        let input = r#"
+            default_toolchain = "nightly"
+            install = ["bat"]
+
+            [toolchains.stable]
+            components = ["rust-src"]
+            targets = ["wasm32-unknown-unknown"]
+            "#;
+        let config: RustupConfig = toml::from_str(input).expect("must parse");
+        assert_eq!(config.default_toolchain(), "nightly");
+        let toolchains = config.toolchains();
+        assert_eq!(
+            toolchains.keys().collect::<Vec<_>>(),
+            vec!["nightly", "stable"]
+        );
+        assert_eq!(toolchains["nightly"], Toolchain::default());
+        assert_eq!(toolchains["stable"].targets, vec!["wasm32-unknown-unknown"]);
+
+        let empty: RustupConfig = toml::from_str("").expect("must parse");
+        assert_eq!(empty.default_toolchain(), "stable");
+        assert_eq!(
+            empty.toolchains()["stable"].components,
+            vec!["clippy", "rustfmt"]
+        );
+    }
+
+    #[test]
+    fn toolchains_match_full_names() {
+        let installed = parse_toolchain_list(
+            "stable-x86_64-unknown-linux-gnu (default)\nnightly-2020-07-01-x86_64-unknown-linux-gnu\n",
+        );
+        assert!(has_toolchain(&installed, "stable"));
+        assert!(has_toolchain(&installed, "nightly-2020-07-01"));
+        assert!(!has_toolchain(&installed, "beta"));
+        assert!(parse_toolchain_list("no installed toolchains\n").is_empty());
+
+        let host = Platform::current().rust_target();
+        let components = vec![format!("clippy-{}", host), String::from("rust-src")];
+        assert!(has_component(&components, "clippy"));
+        assert!(has_component(&components, "rust-src"));
+        assert!(!has_component(&components, "rustfmt"));
+    }
+}
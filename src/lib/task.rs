@@ -8,7 +8,6 @@ use crate::utils::github;
 pub enum Error {
     GitHub(String, github::GitHubError),
     Io(String, io::Error),
-    NoTags,
     Popen(String, subprocess::PopenError),
 }
 impl std::error::Error for Error {}
@@ -17,7 +16,6 @@ impl fmt::Display for Error {
         match &self {
             Error::GitHub(msg, cause) => write!(f, "{}", format!("{}: {:?}", msg, cause).red()),
             Error::Io(msg, cause) => write!(f, "{}", format!("{}: {:?}", msg, cause).red()),
-            Error::NoTags => write!(f, "{}", "NoTagsError".red()),
             Error::Popen(msg, cause) => write!(f, "{}", format!("{}: {:?}", msg, cause).red()),
         }
     }
@@ -48,6 +46,7 @@ pub enum Status {
 
     Changed(String, String), // finished, with details
     NoChange(String),        // finished, with details
+    SkippedBecause(String),  // never finishing, with reason
 
                              // TODO: support async with below statuses
                              // InProgress,              // started, not finished yet
@@ -64,6 +63,9 @@ impl fmt::Display for Status {
                 write!(f, "{}", format!("'{}' -> '{}'", old.red(), new.yellow()))
             }
             Status::NoChange(old) => write!(f, "{}", format!("'{}'", old).green()),
+            Status::SkippedBecause(reason) => {
+                write!(f, "{}", format!("skipped: {}", reason).blue())
+            }
         }
     }
 }
@@ -1,5 +1,92 @@
+use std::fmt;
+
 const UNSTABLE: &[&str] = &["alpha", "beta", "canary", "dev", "preview", "rc"];
 
+// Version is a semantic version that we parse leniently,
+// as tools report versions like "go1.15.2", "v14.3.0", and "jq-1.6"
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Version(semver::Version);
+
+impl Version {
+    pub fn parse<S>(input: S) -> Option<Version>
+    where
+        S: AsRef<str>,
+    {
+        let s = input.as_ref();
+        let start = s.find(|c: char| c.is_ascii_digit())?;
+        let rest = &s[start..];
+
+        let mut numbers = Vec::<u64>::new();
+        let mut end = 0;
+        for part in rest.split('.') {
+            let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+            if digits.is_empty() {
+                break;
+            }
+            numbers.push(digits.parse().ok()?);
+            end += digits.len();
+            if digits.len() < part.len() || numbers.len() == 3 {
+                break;
+            }
+            end += 1; // the "."
+        }
+        let end = end.min(rest.len());
+
+        let mut v = semver::Version::new(
+            numbers[0],
+            numbers.get(1).cloned().unwrap_or_default(),
+            numbers.get(2).cloned().unwrap_or_default(),
+        );
+
+        // e.g. "-rc.1" or "beta1", but not " linux/amd64" or "+build"
+        let pre: String = rest[end..]
+            .trim_start_matches('-')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-')
+            .collect();
+        if pre.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            if let Ok(p) = semver::Prerelease::new(pre.trim_end_matches(['.', '-'])) {
+                v.pre = p;
+            }
+        }
+
+        Some(Version(v))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// is_newer() is true when `candidate` is strictly newer than `current`,
+// or when `current` is not recognisable as a version at all
+pub fn is_newer<S>(candidate: S, current: S) -> bool
+where
+    S: AsRef<str>,
+{
+    match (Version::parse(&candidate), Version::parse(&current)) {
+        (Some(cand), Some(curr)) => cand > curr,
+        (Some(_), None) => true,
+        // e.g. a "nightly" tag, which we cannot know is newer, so we never risk a downgrade
+        (None, Some(_)) => false,
+        // neither can we compare, so fallback to a looser comparison
+        _ => trim_version(candidate.as_ref()) != trim_version(current.as_ref()),
+    }
+}
+
+// matches() is true when `version` is within `spec`,
+// e.g. "14" and "v14.15" both match "v14.15.0", but "1.1" does not match "go1.15.5"
+pub fn matches<S>(spec: S, version: S) -> bool
+where
+    S: AsRef<str>,
+{
+    let spec = trim_version(spec.as_ref());
+    let version = trim_version(version.as_ref());
+    version == spec || (version.starts_with(spec) && version[spec.len()..].starts_with('.'))
+}
+
 pub fn is_stable<S>(version: S) -> bool
 where
     S: AsRef<str>,
@@ -9,13 +96,44 @@ where
         UNSTABLE.join("|")
     ))
     .unwrap();
-    !re.is_match(&version.as_ref())
+    !re.is_match(version.as_ref())
+}
+
+fn trim_version(s: &str) -> &str {
+    s.trim_start_matches(|c: char| !c.is_ascii_digit()).trim()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn is_newer_only_when_strictly_newer() {
+        assert!(is_newer("v1.14.1", "minikube version: v1.14.0"));
+        assert!(is_newer("go1.15.3", "go1.15.2"));
+        assert!(is_newer("go1.15", "go1.15beta1"));
+        assert!(is_newer("v14.3.0", "unexpected"));
+
+        assert!(!is_newer("v14.3.0", "v14.3.0"));
+        assert!(!is_newer("14.3.0", "v14.3.0"));
+        assert!(!is_newer("jq-1.6", "jq-1.6.0"));
+        assert!(!is_newer("go1.15.2", "go1.16"));
+        assert!(!is_newer("nightly", "v14.3.0"));
+    }
+
+    #[test]
+    fn matches_version_prefixes() {
+        assert!(matches("14", "v14.15.0"));
+        assert!(matches("v14.15", "v14.15.0"));
+        assert!(matches("go1.15", "go1.15.5"));
+        assert!(matches("1.15", "go1.15.5"));
+        assert!(matches("1.15.5", "go1.15.5"));
+
+        assert!(!matches("1.1", "go1.15.5"));
+        assert!(!matches("12", "v14.15.0"));
+        assert!(!matches("1.15.5", "go1.15"));
+    }
+
     #[test]
     fn is_stable_versions() {
         assert!(is_stable("1.0.0"));
@@ -25,4 +143,36 @@ mod tests {
         assert!(!is_stable("1.0.0-beta.1"));
         assert!(!is_stable("go1.12beta1"));
     }
+
+    #[test]
+    fn parse_lenient_versions() {
+        let cases = vec![
+            ("1.2.3", "1.2.3"),
+            ("v14.3.0", "14.3.0"),
+            ("go1.15.2", "1.15.2"),
+            ("go1.15.2 linux/amd64", "1.15.2"),
+            ("go1.12beta1", "1.12.0-beta1"),
+            ("jq-1.6", "1.6.0"),
+            ("Build label: 3.7.0", "3.7.0"),
+            ("v1.0.0-rc.1+build.5", "1.0.0-rc.1"),
+            ("1.2.3.4", "1.2.3"),
+            ("20.8b1", "20.8.0-b1"),
+            ("v2", "2.0.0"),
+        ];
+        for (input, want) in cases {
+            let got = Version::parse(input).expect("must parse");
+            assert_eq!(got.to_string(), want, "input: {}", input);
+        }
+        assert_eq!(Version::parse("unexpected"), None);
+        assert_eq!(Version::parse(""), None);
+    }
+
+    #[test]
+    fn versions_order_semantically() {
+        let parse = |s| Version::parse(s).expect("must parse");
+        assert!(parse("1.10.0") > parse("1.9.0"));
+        assert!(parse("1.0.0") > parse("1.0.0-rc.1"));
+        assert!(parse("1.0.0-rc.2") > parse("1.0.0-rc.1"));
+        assert_eq!(parse("v1.6"), parse("jq-1.6.0"));
+    }
 }
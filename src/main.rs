@@ -6,6 +6,7 @@ use clap::{App, Arg, SubCommand};
 
 mod lib {
     pub mod brew;
+    pub mod bundle;
     pub mod cache;
     pub mod cargo;
     pub mod env;
@@ -13,6 +14,10 @@ mod lib {
     pub mod ghratask;
     pub mod ghrtask;
     pub mod goget;
+    pub mod installs;
+    pub mod mirrors;
+    pub mod npm;
+    pub mod pep440;
     pub mod pip;
     pub mod python;
     pub mod rust;
@@ -23,23 +28,37 @@ mod lib {
 mod tasks;
 mod utils {
     pub mod archive;
+    pub mod checksum;
+    pub mod cratesio;
     pub mod env;
+    pub mod forge;
     pub mod fs;
     pub mod git;
+    pub mod gitea;
     pub mod github;
+    pub mod gitlab;
     pub mod golang;
     pub mod http;
     pub mod nodejs;
+    pub mod platform;
     pub mod process;
+    pub mod progress;
+    pub mod pypi;
     pub mod ssh;
 }
 
-use crate::lib::env::Shell;
+use crate::{lib::env::Shell, utils::platform::Platform};
 
 fn main() {
     let matches = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .global(true)
+                .help("only use cached HTTP responses, never the network"),
+        )
         .subcommand(
             SubCommand::with_name("all")
                 .about("sync / update my settings and packages on this computer"),
@@ -50,8 +69,66 @@ fn main() {
                 .about("run specific comma-separated tasks"),
         )
         .subcommand(SubCommand::with_name("env").about("export generated environment variables"))
+        .subcommand(
+            SubCommand::with_name("bundle")
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .takes_value(true)
+                        .help("platform to bundle for, e.g. linux-aarch64-musl"),
+                )
+                .arg(Arg::with_name("output").help("path to write the bundle tarball"))
+                .about("download tools into a bundle for a computer without network access"),
+        )
+        .subcommand(
+            SubCommand::with_name("install")
+                .arg(
+                    Arg::with_name("from-bundle")
+                        .long("from-bundle")
+                        .takes_value(true)
+                        .help("path to a tarball from the bundle command"),
+                )
+                .about("install tools without network access"),
+        )
+        .subcommand(
+            SubCommand::with_name("gc")
+                .arg(Arg::with_name("tool").required(true).help("golang"))
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("also delete caches that other projects may need"),
+                )
+                .about("delete caches that a tool no longer needs"),
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .arg(
+                    Arg::with_name("tool")
+                        .required(true)
+                        .help("golang or nodejs"),
+                )
+                .about("switch a tool back to its previously-installed version"),
+        )
+        .subcommand(
+            SubCommand::with_name("use")
+                .arg(
+                    Arg::with_name("tool")
+                        .required(true)
+                        .help("golang or nodejs"),
+                )
+                .arg(
+                    Arg::with_name("version")
+                        .required(true)
+                        .help("an installed version, e.g. 14 or 1.15"),
+                )
+                .about("switch the default version of a tool"),
+        )
         .get_matches();
 
+    if matches.is_present("offline") {
+        utils::http::set_offline(true);
+    }
+
     if let Some(_matches) = matches.subcommand_matches("all") {
         tasks::all();
         return;
@@ -66,6 +143,52 @@ fn main() {
         return;
     }
 
+    if let Some(matches) = matches.subcommand_matches("bundle") {
+        let platform = match matches.value_of("target") {
+            Some(t) => match t.parse::<Platform>() {
+                Ok(p) => p,
+                Err(error) => return println!("Error: {}", error),
+            },
+            None => Platform::current().clone(),
+        };
+        let output = match matches.value_of("output") {
+            Some(o) => String::from(o),
+            None => format!("{}-{}.tar.gz", env!("CARGO_PKG_NAME"), platform),
+        };
+        tasks::bundle(platform, output);
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("install") {
+        match matches.value_of("from-bundle") {
+            Some(b) => {
+                if let Err(error) = lib::bundle::install(b) {
+                    println!("Error: {}", error);
+                }
+            }
+            None => println!("Error: --from-bundle is mandatory for 'install'"),
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("gc") {
+        tasks::gc(matches.value_of("tool").unwrap(), matches.is_present("all"));
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("rollback") {
+        tasks::rollback(matches.value_of("tool").unwrap());
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("use") {
+        tasks::use_version(
+            matches.value_of("tool").unwrap(),
+            matches.value_of("version").unwrap(),
+        );
+        return;
+    }
+
     if let Some(_matches) = matches.subcommand_matches("env") {
         let exports = tasks::env();
         let shell = var("SHELL").unwrap_or_default();
@@ -1,13 +1,15 @@
 use regex::Regex;
 
 use crate::lib::{
+    bundle::{self, Bundle},
     ghratask::GHRATask,
     task::{self, Status, Task},
 };
-use crate::utils::{
-    github::Asset,
-    golang::{arch, os},
-};
+use crate::utils::{forge::Forge, github::Asset, platform::Platform};
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHRA_TASK.bundle(bundle)
+}
 
 pub fn task() -> Task {
     Task {
@@ -18,15 +20,22 @@ pub fn task() -> Task {
 }
 
 const GHRA_TASK: GHRATask = GHRATask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "atlantis",
+    files: &[],
+    forge: Forge::GitHub,
     repo: ("runatlantis", "atlantis"),
     trim_version,
     version_arg: "version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let re = Regex::new(&format!(r"^atlantis_{}_{}\.zip$", os(), arch())).unwrap();
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let re = Regex::new(&format!(
+        r"^atlantis_{}_{}\.zip$",
+        platform.go_os(),
+        platform.go_arch()
+    ))
+    .unwrap();
 
     re.is_match(&asset.name)
 }
@@ -1,10 +1,22 @@
 use std::env::consts::OS;
 
 use crate::{
-    lib::task::{self, Status, Task},
-    utils,
+    lib::{
+        bundle::{self, Bundle},
+        task::{self, Status, Task},
+    },
+    utils::{self, platform::Os},
 };
 
+const BASH_IT_URL: &str = "https://github.com/Bash-it/bash-it.git";
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    if bundle.platform.os == Os::Windows {
+        return Ok(());
+    }
+    bundle.add_git("bash-it", BASH_IT_URL, ".bash_it")
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("bash"),
@@ -30,8 +42,7 @@ fn sync() -> task::Result {
     let it_path = utils::env::home_dir().join(".bash_it");
     if !utils::git::path_is_git_repository(&it_path) {
         utils::fs::delete_if_exists(&it_path);
-        let it_url = "https://github.com/Bash-it/bash-it.git";
-        match utils::git::shallow_clone(it_url, &it_path.to_string_lossy()) {
+        match utils::git::shallow_clone(BASH_IT_URL, &it_path.to_string_lossy()) {
             Ok(()) => {}
             Err(error) => println!("bash: unable to install bash-it: {}", error),
         }
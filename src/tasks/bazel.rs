@@ -1,13 +1,19 @@
-use std::env::consts::ARCH;
-
 use regex::Regex;
 
 use crate::lib::{
+    bundle::{self, Bundle},
     ghrtask::GHRTask,
     task::{self, Status, Task},
 };
-use crate::utils::github::Asset;
-use crate::utils::golang::os;
+use crate::utils::{
+    forge::Forge,
+    github::Asset,
+    platform::{Arch, Platform},
+};
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
 
 pub fn task() -> Task {
     Task {
@@ -18,15 +24,25 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "bazel",
+    forge: Forge::GitHub,
     repo: ("bazelbuild", "bazel"),
     trim_version,
     version_arg: "version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let re = Regex::new(&format!("^bazel-.*-{}-{}(\\.exe)?$", os(), ARCH)).unwrap();
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let arch = match platform.arch {
+        Arch::Aarch64 => "arm64",
+        _ => platform.arch.as_str(),
+    };
+    let re = Regex::new(&format!(
+        "^bazel-.*-{}-{}(\\.exe)?$",
+        platform.go_os(),
+        arch
+    ))
+    .unwrap();
 
     re.is_match(&asset.name)
 }
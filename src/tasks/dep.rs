@@ -1,11 +1,17 @@
-use std::env::consts::OS;
-
 use crate::lib::{
+    bundle::{self, Bundle},
     ghrtask::GHRTask,
     task::{self, Status, Task},
 };
-use crate::utils::github::Asset;
-use crate::utils::golang::{arch, os};
+use crate::utils::{
+    forge::Forge,
+    github::Asset,
+    platform::{Os, Platform},
+};
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
 
 pub fn task() -> Task {
     Task {
@@ -16,18 +22,19 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "dep",
+    forge: Forge::GitHub,
     repo: ("golang", "dep"),
     trim_version,
     version_arg: "version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let name = if OS == "windows" {
-        format!("dep-{}-{}.exe", os(), arch())
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let name = if platform.os == Os::Windows {
+        format!("dep-{}-{}.exe", platform.go_os(), platform.go_arch())
     } else {
-        format!("dep-{}-{}", os(), arch())
+        format!("dep-{}-{}", platform.go_os(), platform.go_arch())
     };
 
     asset.name == name
@@ -3,8 +3,9 @@ use std::fs;
 use crate::{
     lib::{
         favourites::Favourites,
-        goget::GoGetFavourites,
+        goget::{self, GoGetFavourites},
         task::{self, Status, Task},
+        version,
     },
     utils,
 };
@@ -53,20 +54,46 @@ fn sync() -> task::Result {
     Ok(Status::Done)
 }
 
+// update() reinstalls favourites that are behind their pin,
+// or behind the module proxy's latest version when they have no pin (or "@latest"),
+// leaving alone local builds and pins to branches or commits
 fn update(_: Status) -> task::Result {
+    if utils::http::is_offline() {
+        return Ok(Status::SkippedBecause(String::from("offline")));
+    }
     if !utils::golang::is_installed() {
         return Ok(Status::Skipped);
     }
 
     let favs = read_config();
+    let binaries = goget::installed_binaries();
+    let mut before = Vec::<String>::new();
+    let mut after = Vec::<String>::new();
+    for (pkg, pin) in favs.pins() {
+        let binary = match binaries.iter().find(|b| b.path == pkg) {
+            Some(b) if b.version != "(devel)" => b,
+            _ => continue,
+        };
+        let want = match pin.filter(|p| p != "latest") {
+            Some(p) if goget::is_version_pin(&p) && p != binary.version => p,
+            Some(_) => continue,
+            None => match utils::golang::proxy_latest(&binary.module) {
+                Ok(latest) if version::is_newer(&latest, &binary.version) => latest,
+                Ok(_) => continue,
+                Err(error) => {
+                    println!("warning: goget: unable to check {}: {}", &pkg, error);
+                    continue;
+                }
+            },
+        };
+        goget::go_install(&pkg, &want)?;
+        before.push(format!("{}@{}", &pkg, &binary.version));
+        after.push(format!("{}@{}", &pkg, &want));
+    }
 
-    let mut install_args = vec![String::from("get"), String::from("-u")];
-    install_args.extend(favs.found());
-
-    utils::process::command_spawn_wait("go", &install_args)?;
-
-    Ok(Status::Changed(
-        String::from("unknown"),
-        String::from("unknown"),
-    ))
+    if before.is_empty() {
+        Ok(Status::NoChange(String::new()))
+    } else {
+        Ok(Status::Changed(before.join(","), after.join(",")))
+    }
 }
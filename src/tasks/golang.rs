@@ -1,15 +1,23 @@
-use std::env::consts::OS;
+use std::{collections::HashSet, io, path::Path};
 
 use crate::lib::{
+    bundle::{self, Bundle, Entry, EntryKind},
     env::Exports,
+    goget,
+    installs::{sync_versions, verify_command, Installs, Runtimes},
+    mirrors,
     task::{self, Status, Task},
+    version,
 };
 use crate::utils::{
     self,
-    fs::mkftemp,
-    golang::{arch, bin_dir, gopath, goroot, os},
+    checksum::{self, HashReader},
+    golang::{bin_dir, gopath, goroot, File},
+    platform::Platform,
 };
 
+const CONFIG: &str = "golang.toml";
+
 pub fn env(mut exports: Exports) -> Exports {
     let gp = gopath();
     if gp.is_dir() {
@@ -32,6 +40,23 @@ pub fn env(mut exports: Exports) -> Exports {
     exports
 }
 
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    let file = utils::golang::latest_file(&bundle.platform, Runtimes::read(CONFIG).default_spec())?;
+
+    let tree_path = bundle.path("golang/go")?;
+    let extract_path = tree_path.parent().unwrap();
+    extract_golang(&file, extract_path, 0)?;
+
+    bundle.add(Entry {
+        name: String::from("golang"),
+        version: file.version,
+        kind: EntryKind::Versioned,
+        path: String::from("golang/go"),
+        dest: String::from(".local/go"),
+    });
+    Ok(())
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("golang"),
@@ -40,76 +65,90 @@ pub fn task() -> Task {
     }
 }
 
-fn install_golang<S>(version: S) -> task::Result
-where
-    S: AsRef<str>,
-{
-    let current = if utils::golang::is_installed() {
-        utils::golang::current_version()
-    } else {
-        String::from("absent")
-    };
-
-    let temp_path = mkftemp()?;
-
-    let v = version.as_ref();
-    let remote_url = format!(
-        "https://dl.google.com/go/{}.{}-{}.{}",
-        &v,
-        os(),
-        arch(),
-        if OS == "windows" { "zip" } else { "tar.gz" },
-    );
-    utils::http::download(remote_url, &temp_path)?;
-
-    let local_path = utils::env::home_dir().join(".local");
-    let target_path = local_path.join("go");
-    utils::fs::delete_if_exists(&target_path);
-
-    if OS == "windows" {
-        utils::archive::extract_zip(&temp_path, &local_path)?;
+// extract_golang() verifies the archive against the SHA-256 in the download index
+fn extract_golang(file: &File, target: &Path, strip: usize) -> io::Result<()> {
+    let res = utils::http::stream(format!("{}/{}", mirrors::golang_dl(), &file.filename))?;
+    // archive contains a "go" directory
+    let mut reader = HashReader::new(res);
+    utils::archive::extract_stream(&mut reader, target, "go", strip)?;
+    checksum::verify(file.filename.as_str(), &reader.finish()?, &file.sha256)
+}
+
+fn current() -> Option<String> {
+    if utils::golang::is_installed() {
+        Some(utils::golang::current_version())
     } else {
-        utils::archive::extract_tar_gz(&temp_path, &local_path)?;
+        None
     }
+}
 
-    utils::fs::delete_if_exists(&temp_path);
-
-    Ok(Status::Changed(current, String::from(v)))
+fn install_golang(version: &str) -> io::Result<()> {
+    let platform = Platform::current();
+    let file = utils::golang::release_file(version, platform)?;
+    installs().install(version, |dir| {
+        extract_golang(&file, dir, 1)?;
+        let exe = dir.join("bin").join(format!("go{}", platform.exe_suffix()));
+        verify_command(&exe, "version")
+    })
 }
 
-fn sync() -> task::Result {
-    if utils::golang::is_installed() {
-        Ok(Status::NoChange(String::from("present")))
-    } else {
-        let latest_version = match utils::golang::latest_version() {
-            Ok(v) => v,
-            Err(error) => {
-                return Err(error);
-            }
-        };
-
-        install_golang(latest_version)
+// gc() deletes packages compiled by older Go versions,
+// and other versions of modules that binaries in GOPATH/bin need
+// (or with `all`, every module they do not need), returning how many bytes we reclaimed;
+// `sync` leaves GOPATH/pkg alone: https://github.com/golang/go/issues/4719
+pub fn gc(all: bool) -> io::Result<u64> {
+    let pkg = gopath().join("pkg");
+    let mut reclaimed = utils::golang::prune_stale_pkg(&pkg)?;
+
+    // without build info we would think nothing is needed, so we stop
+    if !utils::golang::is_installed() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "reclaimed {} bytes, but need {} to prune modules",
+                reclaimed,
+                utils::golang::go_exe().display()
+            ),
+        ));
     }
+    let keep: HashSet<String> = goget::read_binaries()?
+        .iter()
+        .flat_map(|b| b.modules.iter())
+        .map(|m| utils::golang::escape_module(m))
+        .collect();
+    reclaimed += utils::golang::prune_module_cache(&pkg.join("mod"), &keep, all)?;
+    Ok(reclaimed)
+}
 
-    // TODO: cleanup GOPATH/pkg: https://github.com/golang/go/issues/4719
+pub fn installs() -> Installs {
+    Installs::new("golang", goroot(), |s, v| version::matches(s, v))
+}
+
+fn sync() -> task::Result {
+    sync_versions(
+        &installs(),
+        &Runtimes::read(CONFIG),
+        current(),
+        false,
+        &utils::golang::latest_version,
+        &install_golang,
+    )
 }
 
 fn update(_: Status) -> task::Result {
+    if utils::http::is_offline() {
+        return Ok(Status::SkippedBecause(String::from("offline")));
+    }
     if !utils::golang::is_installed() {
         return Ok(Status::Skipped);
     }
 
-    let current = utils::golang::current_version();
-    let latest = match utils::golang::latest_version() {
-        Ok(v) => v,
-        Err(error) => {
-            return Err(error);
-        }
-    };
-
-    if current == latest {
-        Ok(Status::NoChange(current))
-    } else {
-        install_golang(latest)
-    }
+    sync_versions(
+        &installs(),
+        &Runtimes::read(CONFIG),
+        current(),
+        true,
+        &utils::golang::latest_version,
+        &install_golang,
+    )
 }
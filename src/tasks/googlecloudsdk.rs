@@ -1,11 +1,18 @@
 use crate::{
     lib::{
+        bundle::{self, Bundle},
         env::Exports,
         task::{self, Status, Task},
     },
     utils,
 };
 
+const SDK_URL: &str = "https://github.com/google-cloud-sdk/google-cloud-sdk.git";
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    bundle.add_git("googlecloudsdk", SDK_URL, ".local/google-cloud-sdk")
+}
+
 pub fn env(mut exports: Exports) -> Exports {
     let dir = utils::env::home_dir()
         .join(".local")
@@ -37,8 +44,7 @@ fn sync() -> task::Result {
 
     utils::fs::delete_if_exists(&sdk_path);
 
-    let sdk_url = "https://github.com/google-cloud-sdk/google-cloud-sdk.git";
-    utils::git::shallow_clone(sdk_url, &sdk_path.to_string_lossy())?;
+    utils::git::shallow_clone(SDK_URL, &sdk_path.to_string_lossy())?;
 
     Ok(Status::Done)
 }
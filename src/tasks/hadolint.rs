@@ -1,16 +1,18 @@
-use std::env::consts::{ARCH, EXE_SUFFIX};
-
-use inflector::Inflector;
 use regex::Regex;
 
 use crate::{
     lib::{
+        bundle::{self, Bundle},
         ghrtask::GHRTask,
         task::{self, Status, Task},
     },
-    utils::{github::Asset, golang::os},
+    utils::forge::Forge,
 };
 
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("hadolint"),
@@ -20,19 +22,14 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: None,
     command: "hadolint",
+    forge: Forge::GitHub,
     repo: ("hadolint", "hadolint"),
     trim_version,
     version_arg: "--version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let os_title = os().to_title_case();
-    let name = format!("hadolint-{}-{}{}", os_title, ARCH, EXE_SUFFIX);
-    asset.name == name
-}
-
 fn sync() -> task::Result {
     GHR_TASK.sync()
 }
@@ -8,5 +8,20 @@ pub fn env(mut exports: Exports) -> Exports {
         paths.append(&mut exports.path);
         exports.path = paths;
     }
+
+    // GitHub Release archives may install man pages and completions here
+    let man_dir = home_dir().join(".local").join("share").join("man");
+    if !exports.man_path.contains(&man_dir) {
+        exports.man_path.insert(0, man_dir);
+    }
+    let zsh_dir = home_dir()
+        .join(".local")
+        .join("share")
+        .join("zsh")
+        .join("site-functions");
+    if !exports.fpath.contains(&zsh_dir) {
+        exports.fpath.insert(0, zsh_dir);
+    }
+
     exports
 }
@@ -1,14 +1,16 @@
 use crate::{
     lib::{
+        bundle::{self, Bundle},
         ghrtask::GHRTask,
         task::{self, Status, Task},
     },
-    utils::{
-        github::Asset,
-        golang::{arch, os},
-    },
+    utils::{forge::Forge, github::Asset, platform::Platform},
 };
 
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("minikube"),
@@ -18,15 +20,16 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "minikube",
+    forge: Forge::GitHub,
     repo: ("kubernetes", "minikube"),
     trim_version,
     version_arg: "version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let name = format!("minikube-{}-{}", os(), arch());
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let name = format!("minikube-{}-{}", platform.go_os(), platform.go_arch());
 
     asset.name == name
 }
@@ -1,6 +1,14 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
-use crate::lib::{env::Exports, task::Task};
+use crate::{
+    lib::{
+        bundle::{self, Bundle},
+        env::Exports,
+        installs::Installs,
+        task::Task,
+    },
+    utils::{platform::Platform, progress::format_bytes},
+};
 
 mod atlantis;
 mod bash;
@@ -37,6 +45,25 @@ mod windows;
 mod yq;
 mod zsh;
 
+pub fn bundle<P>(platform: Platform, target: P)
+where
+    P: AsRef<Path>,
+{
+    let mut b = match Bundle::new(platform) {
+        Ok(b) => b,
+        Err(error) => return println!("bundle: error: {:?}", error),
+    };
+    for (name, bundle) in bundlers() {
+        if let Err(error) = bundle(&mut b) {
+            println!("{}: bundle error: {:?}", name, error);
+        }
+    }
+    match b.write(&target) {
+        Ok(()) => println!("bundle: wrote {}", target.as_ref().display()),
+        Err(error) => println!("bundle: error: {:?}", error),
+    }
+}
+
 pub fn env() -> Exports {
     let mut exports: Exports = Default::default();
     exports = brew::env(exports);
@@ -66,6 +93,65 @@ pub fn all() {
     }
 }
 
+// gc() deletes what a tool has accumulated but no longer needs,
+// and with `all` also what other projects may need
+pub fn gc<S>(name: S, all: bool)
+where
+    S: AsRef<str>,
+{
+    let result = match name.as_ref() {
+        "golang" => golang::gc(all),
+        _ => return println!("gc: error: {} has nothing to collect", name.as_ref()),
+    };
+    match result {
+        Ok(bytes) => println!("{}: gc reclaimed {}", name.as_ref(), format_bytes(bytes)),
+        Err(error) => println!("{}: gc error: {}", name.as_ref(), error),
+    }
+}
+
+// rollback() switches a versioned tool back to its previous version
+pub fn rollback<S>(name: S)
+where
+    S: AsRef<str>,
+{
+    let installs = match versioned(name.as_ref()) {
+        Some(i) => i,
+        None => return println!("rollback: error: {} does not keep versions", name.as_ref()),
+    };
+    match installs.rollback() {
+        Ok((from, to)) => println!("{}: {} -> {}", name.as_ref(), from, to),
+        Err(error) => println!("{}: rollback error: {}", name.as_ref(), error),
+    }
+}
+
+// use_version() switches the default version of a versioned tool,
+// choosing the newest installed version within `spec`
+pub fn use_version<S>(name: S, spec: S)
+where
+    S: AsRef<str>,
+{
+    let installs = match versioned(name.as_ref()) {
+        Some(i) => i,
+        None => return println!("use: error: {} does not keep versions", name.as_ref()),
+    };
+    let version = match installs.find(Some(spec.as_ref())) {
+        Some(v) => v,
+        None => {
+            return println!(
+                "{}: use error: {} is not installed, see `versions` in ~/.dotfiles/config/{}.toml",
+                name.as_ref(),
+                spec.as_ref(),
+                name.as_ref()
+            );
+        }
+    };
+    let before = installs.current().unwrap_or_else(|| String::from("absent"));
+    match installs.activate(&version) {
+        Ok(()) => println!("{}: {} -> {}", name.as_ref(), before, version),
+        Err(error) => println!("{}: use error: {}", name.as_ref(), error),
+    }
+}
+
 pub fn some<S>(input: S)
 where
     S: AsRef<str>,
@@ -78,6 +164,42 @@ where
     }
 }
 
+type Bundler = fn(&mut Bundle) -> bundle::Result;
+
+// bundlers() are the tasks that download something,
+// in the same order as sequence()
+fn bundlers() -> Vec<(&'static str, Bundler)> {
+    vec![
+        ("golang", golang::bundle),
+        ("nodejs", nodejs::bundle),
+        ("atlantis", atlantis::bundle),
+        ("bazel", bazel::bundle),
+        ("dep", dep::bundle),
+        ("gitleaks", gitleaks::bundle),
+        ("gitsizer", gitsizer::bundle),
+        ("hadolint", hadolint::bundle),
+        ("jq", jq::bundle),
+        ("minikube", minikube::bundle),
+        ("shfmt", shfmt::bundle),
+        ("skaffold", skaffold::bundle),
+        ("vale", vale::bundle),
+        ("yq", yq::bundle),
+        ("bash", bash::bundle),
+        ("googlecloudsdk", googlecloudsdk::bundle),
+        ("tmux", tmux::bundle),
+        ("vim", vim::bundle),
+        ("zsh", zsh::bundle),
+    ]
+}
+
+fn versioned(name: &str) -> Option<Installs> {
+    match name {
+        "golang" => Some(golang::installs()),
+        "nodejs" => Some(nodejs::installs()),
+        _ => None,
+    }
+}
+
 fn sequence() -> Vec<String> {
     vec![
         dotfiles::task().name, // provides: config; must be first
@@ -190,6 +312,22 @@ mod tests {
         assert_eq!(count, seq.len());
     }
 
+    #[test]
+    fn bundlers_follow_sequence() {
+        let seq = sequence();
+        let positions: Vec<usize> = bundlers()
+            .iter()
+            .map(|(name, _)| {
+                seq.iter()
+                    .position(|s| s == name)
+                    .expect("must be in sequence")
+            })
+            .collect();
+        let mut sorted = positions.clone();
+        sorted.sort_unstable();
+        assert_eq!(positions, sorted);
+    }
+
     #[test]
     fn mapping_maps_all_tasks() {
         let tasks = mapping();
@@ -1,15 +1,21 @@
-use std::{self, env::consts::OS, io};
+use std::{self, io, path::Path};
 
 use crate::lib::{
+    bundle::{self, Bundle, Entry, EntryKind},
     env::Exports,
+    installs::{sync_versions, verify_command, Installs, Runtimes},
+    mirrors,
     task::{self, Status, Task},
 };
 use crate::utils::{
     self,
-    fs::mkftemp,
-    nodejs::{arch, bin_dir, os},
+    checksum::{self, HashReader},
+    nodejs::bin_dir,
+    platform::{Os, Platform},
 };
 
+const CONFIG: &str = "nodejs.toml";
+
 pub fn env(mut exports: Exports) -> Exports {
     let dir = bin_dir();
     if !exports.path.contains(&dir) {
@@ -20,6 +26,24 @@ pub fn env(mut exports: Exports) -> Exports {
     exports
 }
 
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    let spec = Runtimes::read(CONFIG).default_spec().map(String::from);
+    let latest = utils::nodejs::latest_version(&bundle.platform, spec.as_deref())?;
+    let path = format!("nodejs/{}", &latest);
+    let target = bundle.path(&path)?;
+    std::fs::create_dir_all(&target)?;
+    extract_nodejs(&latest, &bundle.platform, &target)?;
+
+    bundle.add(Entry {
+        name: String::from("nodejs"),
+        version: latest,
+        kind: EntryKind::Versioned,
+        path,
+        dest: String::from(".local/node"),
+    });
+    Ok(())
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("nodejs"),
@@ -28,83 +52,101 @@ pub fn task() -> Task {
     }
 }
 
-fn install_nodejs<S>(version: S) -> io::Result<()>
-where
-    S: AsRef<str>,
-{
-    let temp_path = mkftemp()?;
+// extract_nodejs() downloads Node.js for `platform`,
+// and extracts it into `target`, checking it against SHASUMS256.txt
+fn extract_nodejs(version: &str, platform: &Platform, target: &Path) -> io::Result<()> {
+    let prefix = format!("node-{}-{}", version, platform.node_target());
 
-    let v = version.as_ref();
-    let prefix = format!("node-{}-{}-{}", &v, os(), arch());
+    let file_name = format!(
+        "{}.{}",
+        &prefix,
+        // .tar.xz is smaller, and is what nodejs.org suggests
+        if platform.os == Os::Windows {
+            "zip"
+        } else {
+            "tar.xz"
+        },
+    );
+    let want = utils::nodejs::checksum(platform, version, &file_name)?;
 
     let remote_url = format!(
-        "https://nodejs.org/dist/{}/{}.{}",
-        &v,
-        &prefix,
-        if OS == "windows" { "zip" } else { "tar.gz" },
+        "{}/{}/{}",
+        mirrors::nodejs_dist(platform),
+        version,
+        &file_name
     );
-    match utils::http::download(remote_url, &temp_path) {
-        Ok(()) => {}
+    let res = match utils::http::stream(remote_url) {
+        Ok(r) => r,
         Err(error) => {
             println!("error: cannot download: {}", error);
             return Err(error);
         }
     };
 
-    let local_path = utils::env::home_dir().join(".local");
-
     // archive contains a directory with name matching `prefix`
-    let interim_path = local_path.join(&prefix);
-    utils::fs::delete_if_exists(&interim_path);
+    let mut reader = HashReader::new(res);
+    utils::archive::extract_stream(&mut reader, target, &prefix, 1)?;
+    checksum::verify(file_name.as_str(), &reader.finish()?, &want)
+}
 
-    if OS == "windows" {
-        utils::archive::extract_zip(&temp_path, &local_path)?;
+fn current() -> Option<String> {
+    if utils::nodejs::has_node() {
+        Some(utils::nodejs::current_version())
     } else {
-        utils::archive::extract_tar_gz(&temp_path, &local_path)?;
+        None
     }
+}
 
-    let target_path = local_path.join("node");
-    utils::fs::delete_if_exists(&target_path);
-
-    std::fs::rename(&interim_path, &target_path)?;
+fn install_nodejs(v: &str) -> io::Result<()> {
+    let platform = Platform::current();
+    installs().install(v, |dir| {
+        extract_nodejs(v, platform, dir)?;
+        let exe = if platform.os == Os::Windows {
+            dir.join("node.exe")
+        } else {
+            dir.join("bin").join("node")
+        };
+        verify_command(&exe, "--version")
+    })
+}
 
-    utils::fs::delete_if_exists(&temp_path);
+pub fn installs() -> Installs {
+    Installs::new(
+        "nodejs",
+        utils::nodejs::install_path(),
+        utils::nodejs::matches_channel,
+    )
+}
 
-    Ok(())
+fn latest(spec: Option<&str>) -> Result<String, task::Error> {
+    Ok(utils::nodejs::latest_version(Platform::current(), spec)?)
 }
 
 fn sync() -> task::Result {
-    if utils::nodejs::has_node() {
-        return Ok(Status::Skipped);
-    }
-
-    let latest = utils::nodejs::latest_version()?;
-    match install_nodejs(latest.clone()) {
-        Ok(()) => Ok(Status::Changed(String::from("unknown"), latest)),
-        Err(error) => Err(task::Error::Io(
-            String::from("unable to install Node.js"),
-            error,
-        )),
-    }
+    sync_versions(
+        &installs(),
+        &Runtimes::read(CONFIG),
+        current(),
+        false,
+        &latest,
+        &install_nodejs,
+    )
 }
 
 fn update(_: Status) -> task::Result {
+    if utils::http::is_offline() {
+        return Ok(Status::SkippedBecause(String::from("offline")));
+    }
     if !utils::nodejs::has_node() {
         return Ok(Status::Skipped);
     }
 
-    let current = utils::nodejs::current_version();
-    let latest = utils::nodejs::latest_version()?;
-
-    if current == latest {
-        Ok(Status::NoChange(current))
-    } else {
-        match install_nodejs(latest.clone()) {
-            Ok(()) => Ok(Status::Changed(current, latest)),
-            Err(error) => Err(task::Error::Io(
-                String::from("unable to install Node.js"),
-                error,
-            )),
-        }
-    }
+    sync_versions(
+        &installs(),
+        &Runtimes::read(CONFIG),
+        current(),
+        true,
+        &latest,
+        &install_nodejs,
+    )
 }
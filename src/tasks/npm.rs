@@ -1,45 +1,14 @@
-use std::collections::HashMap;
 use std::path::Path;
-use std::{fs, io, str};
-
-use serde_derive::Deserialize;
+use std::{fs, io};
 
 use crate::lib::{
     self,
+    favourites::Favourites,
+    npm::{Manager, NpmFavourites},
     task::{self, Status, Task},
 };
 use crate::utils;
 
-const ERROR_MSG: &str = "error: nodejs";
-
-#[derive(Debug, Deserialize)]
-struct Globals {
-    #[serde(default)]
-    dependencies: HashMap<String, HashMap<String, String>>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Config {
-    install: Vec<String>,
-    uninstall: Vec<String>,
-}
-
-impl Config {
-    fn new() -> Config {
-        Config {
-            install: Vec::<String>::new(),
-            uninstall: Vec::<String>::new(),
-        }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct Package {
-    bin: HashMap<String, String>,
-    name: String,
-    version: String,
-}
-
 pub fn task() -> Task {
     Task {
         name: String::from("npm"),
@@ -64,56 +33,14 @@ fn configure_npm() -> io::Result<()> {
     Ok(())
 }
 
-fn is_global_package_bin_linked<S>(name: S) -> bool
-where
-    S: AsRef<str>,
-{
-    let pkg = match read_global_package(name.as_ref()) {
-        Ok(p) => p,
-        Err(_) => {
-            return false;
-        }
-    };
-    for pair in pkg.bin {
-        match which::which(pair.0) {
-            Ok(_) => {}
-            Err(_) => {
-                return false;
-            }
-        }
-    }
-    true
-}
-
-fn pkgs_installed() -> Vec<String> {
-    let output = utils::process::command_output("npm", &["ls", "--global", "--depth=0", "--json"])
-        .expect(ERROR_MSG);
-    let stdout = str::from_utf8(&output.stdout).unwrap();
-
-    let globals: Globals = match serde_json::from_str(stdout) {
-        Ok(globals) => globals,
-        Err(error) => {
-            println!("unable to parse JSON from `npm`: {:?}", error);
-            return Vec::<String>::new();
-        }
-    };
-
-    globals
-        .dependencies
-        .keys()
-        .cloned()
-        .filter(|name| is_global_package_bin_linked(name.as_str()))
-        .collect()
-}
-
-fn read_config() -> Config {
+fn read_config() -> NpmFavourites {
     let cfg_path = utils::env::home_dir().join(".dotfiles/config/nodejs.toml");
 
     let contents = match fs::read_to_string(&cfg_path) {
         Ok(s) => s,
         Err(error) => {
             println!("npm: ignoring config: {}", error);
-            return Config::new();
+            return Default::default();
         }
     };
 
@@ -125,26 +52,45 @@ fn read_config() -> Config {
                 &cfg_path.display(),
                 error
             );
-            Config::new()
+            Default::default()
+        }
+    }
+}
+
+// combine_statuses() reports what both fill_and_status() and cull_and_status() changed
+fn combine_statuses(fill: Status, cull: Status) -> Status {
+    let mut absent = Vec::<String>::new();
+    let mut present = Vec::<String>::new();
+    for status in [fill, cull] {
+        if let Status::Changed(a, p) = status {
+            absent.extend(a.split(',').filter(|s| !s.is_empty()).map(String::from));
+            present.extend(p.split(',').filter(|s| !s.is_empty()).map(String::from));
         }
     }
+    if absent.is_empty() && present.is_empty() {
+        Status::NoChange(String::new())
+    } else {
+        Status::Changed(absent.join(","), present.join(","))
+    }
 }
 
-fn read_global_package<S>(name: S) -> io::Result<Package>
-where
-    S: AsRef<str>,
-{
-    let pkg_path = utils::nodejs::lib_dir()
-        .join("node_modules")
-        .join(name.as_ref())
-        .join("package.json");
-    let contents = fs::read_to_string(&pkg_path)?;
-    match serde_json::from_str(&contents) {
-        Ok(pkg) => Ok(pkg),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+// managers() are npm and `manager`, as npm installed pnpm and yarn
+fn managers(manager: Manager) -> Vec<Manager> {
+    if manager == Manager::Npm {
+        vec![Manager::Npm]
+    } else {
+        vec![Manager::Npm, manager]
     }
 }
 
+// manager_versions() are e.g. "npm@6.14.8" for each of `managers`
+fn manager_versions(managers: &[Manager]) -> Vec<String> {
+    managers
+        .iter()
+        .filter_map(|m| m.version().map(|v| format!("{}@{}", m.exe(), v)))
+        .collect()
+}
+
 fn sync() -> task::Result {
     if !utils::nodejs::has_node() {
         return Ok(Status::Skipped);
@@ -179,52 +125,16 @@ fn sync() -> task::Result {
         };
     }
 
-    let config = read_config();
-    let pkgs = pkgs_installed();
-
-    let missing: Vec<String> = config
-        .install
-        .into_iter()
-        .filter(|pkg| !pkgs.contains(&pkg))
-        .collect();
-
-    if missing.is_empty() {
-        return Ok(Status::Done);
-    }
-
-    let mut install_args = vec![String::from("install"), String::from("--global")];
-    install_args.extend(missing);
-
-    match utils::process::command_spawn_wait("npm", &install_args) {
-        Ok(_status) => {}
-        Err(error) => println!(
-            "warning: npm: unable to install missing npm packages: {}",
-            error
-        ),
-    };
-
-    let found: Vec<String> = config
-        .uninstall
-        .into_iter()
-        .filter(|pkg| pkgs.contains(&pkg))
-        .collect();
-
-    if found.is_empty() {
-        return Ok(Status::Done);
+    let mut favs = read_config();
+    let manager = favs.manager();
+    if manager != Manager::Npm && !manager.has() {
+        Manager::Npm.run(&["install", "--global", manager.exe()])?;
     }
 
-    let mut uninstall_args = vec![String::from("uninstall"), String::from("--global")];
-    uninstall_args.extend(found);
+    let fill = Favourites::fill_and_status(&mut favs)?;
+    let cull = Favourites::cull_and_status(&mut favs)?;
 
-    match utils::process::command_spawn_wait("npm", &uninstall_args) {
-        Ok(_status) => {}
-        Err(error) => println!(
-            "warning: npm: unable to uninstall unused npm packages: {}",
-            error
-        ),
-    };
-
-    Ok(Status::Done)
+    Ok(combine_statuses(fill, cull))
 }
 
 fn update(_: Status) -> task::Result {
@@ -232,48 +142,72 @@ fn update(_: Status) -> task::Result {
         return Ok(Status::Skipped);
     }
 
-    if utils::nodejs::has_npx() {
-        match utils::process::command_spawn_wait("npx", &["-q", "npm", "update", "--global"]) {
-            Ok(_status) => {}
+    let favs = read_config();
+    let manager = favs.manager();
+    if !manager.has() {
+        return Ok(Status::Skipped);
+    }
+
+    let managers = managers(manager);
+    let before = [manager_versions(&managers), favs.found_versions()].concat();
+
+    let mut args = Manager::Npm.install_args();
+    args.extend(managers.iter().map(|m| format!("{}@latest", m.exe())));
+    if let Err(error) = Manager::Npm.run(&args) {
+        println!("warning: npm: unable to update itself: {}", error);
+    }
+
+    // installing again gets the latest within each range,
+    // whereas `npm update --global` would go past e.g. "typescript@4"
+    let specs = favs.installed_specs();
+    if !specs.is_empty() {
+        let mut args = manager.install_args();
+        args.extend(specs);
+        match manager.run(&args) {
+            Ok(()) => {}
             Err(_error) => {
                 // private packages will fail on incorrect networks, ignore this
             }
         }
     }
+    let after = [manager_versions(&managers), favs.found_versions()].concat();
 
-    Ok(Status::Done)
+    let older: Vec<String> = before
+        .iter()
+        .filter(|b| !after.contains(b))
+        .cloned()
+        .collect();
+    let newer: Vec<String> = after
+        .iter()
+        .filter(|a| !before.contains(a))
+        .cloned()
+        .collect();
+    if older.is_empty() && newer.is_empty() {
+        Ok(Status::NoChange(String::new()))
+    } else {
+        Ok(Status::Changed(older.join(","), newer.join(",")))
+    }
 }
 
-#[cfg(tests)]
+#[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn is_global_package_bin_linked_for_npx() {
-        if !utils::nodejs::has_npx() {
-            return;
-        }
-        assert!(is_global_package_bin_linked("npx"));
-    }
-
-    #[test]
-    fn pkgs_installed_works() {
-        let pkgs = pkgs_installed();
-        if utils::nodejs::has_npm() {
-            assert!(pkgs.contains("npm"));
-        }
-        if utils::nodejs::has_npx() {
-            assert!(pkgs.contains("npx"));
-        }
-    }
-
-    #[test]
-    fn read_global_package_for_npx() {
-        if !utils::nodejs::has_npx() {
-            return;
-        }
-        let pkg = read_global_package("npx").expect("must read");
-        assert_eq!(pkg.name, "npx");
-        assert!(pkg.bin.get("npx") != None);
+    fn combine_statuses_joins_changes() {
+        assert_eq!(
+            combine_statuses(
+                Status::Changed(String::new(), String::from("typescript")),
+                Status::Changed(String::from("tslint"), String::new()),
+            ),
+            Status::Changed(String::from("tslint"), String::from("typescript"))
+        );
+        assert_eq!(
+            combine_statuses(
+                Status::NoChange(String::new()),
+                Status::NoChange(String::new())
+            ),
+            Status::NoChange(String::new())
+        );
     }
 }
@@ -4,6 +4,7 @@ use crate::{
     lib::{
         env::Exports,
         favourites::Favourites,
+        pep440::Version,
         pip::{self, PipFavourites},
         python::exe_output,
         task::{self, Status, Task},
@@ -62,13 +63,42 @@ fn update(_: Status) -> task::Result {
     }
 
     let favs = read_config()?;
-    let mut args = vec![
-        String::from("install"),
-        String::from("--upgrade"),
-        String::from("--user"),
-    ];
-    args.extend(favs.found());
-    pip::pip(&args)?;
+    let installed = favs.installed_versions();
+    let mut shared = Vec::<String>::new();
+    for (req, isolated) in favs.requirements() {
+        let name = utils::pypi::normalize_name(&req.name);
+        let current = match installed.get(&name) {
+            Some(c) => c,
+            None => continue, // `sync` installs it
+        };
+        let latest = match utils::pypi::latest_version(&req.name, &req.specifiers) {
+            Ok(Some(l)) => l,
+            Ok(None) => continue,
+            Err(error) => {
+                println!("warning: pip: unable to check {}: {}", &req.name, error);
+                continue;
+            }
+        };
+        if Version::parse(current).is_some_and(|c| c >= latest) {
+            continue;
+        }
+
+        if isolated {
+            pip::upgrade_venv(&name, &req.text)?;
+        } else {
+            shared.push(req.text);
+        }
+    }
+
+    if !shared.is_empty() {
+        let mut args = vec![
+            String::from("install"),
+            String::from("--upgrade"),
+            String::from("--user"),
+        ];
+        args.extend(shared);
+        pip::pip(&args)?;
+    }
 
     Ok(Status::Done)
 }
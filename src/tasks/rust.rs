@@ -1,12 +1,11 @@
 use std::{fs, io};
 
-use regex::Regex;
+use semver::Version;
 
 use crate::{
     lib::{
-        cargo::{self, CargoFavourites},
+        cargo::{self, CargoFavourites, Krate},
         favourites::Favourites,
-        rust,
         task::{self, Status, Task},
     },
     utils,
@@ -20,51 +19,23 @@ pub fn task() -> Task {
     }
 }
 
-fn cargo_latest_version<S>(krate: S) -> Result<String, String>
-where
-    S: AsRef<str>,
-{
-    let mut pattern = String::from("^");
-    let k = krate.as_ref();
-    pattern.push_str(&k);
-    pattern.push_str(r#"\s=\s"(\S+)""#);
-    let re = Regex::new(&pattern).unwrap();
-    let stdout = cargo::cargo_output(&["search", "--limit", "1", &k]).unwrap_or_default();
-    let lines = stdout.lines();
-    for line in lines {
-        if let Some(caps) = re.captures(line) {
-            let version = String::from(caps.get(1).unwrap().as_str());
-            return Ok(version);
-        };
-    }
-    Err(String::from("not found"))
-}
-
-fn fix_cargo_fmt() -> io::Result<()> {
-    if !cargo::has_cargo() || !rust::has_rustup() {
-        return Ok(());
-    }
-    if has_cargo_installed_rustfmt() {
-        cargo::cargo(&["uninstall", "rustfmt", "rustfmt-nightly", "rustfmt-preview"])?;
-    }
-    if !has_cargo_fmt() {
-        rust::rustup(&["component", "remove", "rustfmt-preview"])?;
-        rust::rustup(&["component", "add", "rustfmt-preview"])?;
-    }
-    Ok(())
-}
+fn read_config() -> Result<CargoFavourites, task::Error> {
+    let cfg_path = utils::env::home_dir().join(".dotfiles/config/rust.toml");
 
-fn has_cargo_fmt() -> bool {
-    match utils::process::command_output("cargo", &["fmt", "--help"]) {
-        Ok(output) => output.status.success(),
-        Err(_error) => {
-            false // cargo probably not installed
+    let contents = match fs::read_to_string(&cfg_path) {
+        Ok(s) => s,
+        Err(error) => {
+            return Err(task::Error::Io(String::from("ignoring config"), error));
         }
-    }
-}
+    };
 
-fn has_cargo_installed_rustfmt() -> bool {
-    cargo::found_versions().contains_key("rustfmt")
+    match toml::from_str(&contents) {
+        Ok(c) => Ok(c),
+        Err(error) => Err(task::Error::Io(
+            format!("unable to parse {}", &cfg_path.display()),
+            io::Error::new(io::ErrorKind::InvalidData, error),
+        )),
+    }
 }
 
 fn sync() -> task::Result {
@@ -72,24 +43,10 @@ fn sync() -> task::Result {
         return Ok(Status::Skipped);
     }
 
-    let cfg_path = utils::env::home_dir().join(".dotfiles/config/rust.toml");
-
-    let contents = match fs::read_to_string(&cfg_path) {
-        Ok(s) => s,
-        Err(error) => {
-            return Err(task::Error::Io(String::from("ignoring config"), error));
-        }
-    };
-
-    let mut favs: CargoFavourites = toml::from_str(&contents).expect("cannot parse .../rust.toml");
+    let mut favs = read_config()?;
     Favourites::fill_and_status(&mut favs)?;
     Favourites::cull_and_status(&mut favs)?;
 
-    match fix_cargo_fmt() {
-        Ok(()) => {}
-        Err(error) => println!("error: rust: unable to fix `cargo fmt`: {:?}", error),
-    };
-
     Ok(Status::Done)
 }
 
@@ -98,42 +55,83 @@ fn update(_: Status) -> task::Result {
         return Ok(Status::Done);
     }
 
-    let krates = cargo::found_versions();
-
-    let outdated: Vec<String> = krates
-        .into_iter()
-        .filter_map(
-            |(krate, version)| match cargo_latest_version(krate.as_str()) {
-                Ok(latest) => {
-                    if version == latest {
-                        return None;
-                    }
-                    Some(krate)
+    let favs = read_config().unwrap_or_default();
+    for installed in cargo::read_installed() {
+        let krate = favs.find(&installed.name);
+
+        let install_args = if installed.is_registry() {
+            if krate.is_some_and(|k| !k.is_registry()) {
+                continue; // `sync` will replace it with the git or path source
+            }
+            let req = krate.and_then(|k| k.version_req());
+            let latest = match utils::cratesio::latest_version(&installed.name, req.as_ref()) {
+                Ok(Some(l)) => l,
+                Ok(None) => continue,
+                Err(error) => {
+                    println!(
+                        "warning: rust: unable to check {}: {}",
+                        &installed.name, error
+                    );
+                    continue;
                 }
-                Err(_) => None,
-            },
-        )
-        .collect();
-
-    if outdated.is_empty() {
-        return Ok(Status::Done);
+            };
+            if Version::parse(&installed.version).is_ok_and(|v| v >= latest) {
+                continue;
+            }
+            let mut args = match krate {
+                Some(k) => k.install_args(favs.locked()),
+                None => vec![String::from("install"), installed.name.clone()],
+            };
+            args.push(String::from("--force"));
+            args
+        } else {
+            // `cargo install` itself checks git sources for new commits
+            match krate {
+                Some(k) if installed.source.starts_with("git+") => k.install_args(favs.locked()),
+                _ => continue,
+            }
+        };
+        cargo::cargo(&install_args)?;
     }
 
-    let mut install_args = vec![String::from("install"), String::from("--force")];
-    install_args.extend(outdated);
+    update_prebuilt(&favs)?;
 
-    cargo::cargo(&install_args)?;
     Ok(Status::Done)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// update_prebuilt() keeps crates from GitHub Releases coming from there,
+// and only builds them when a newer release has no binary for this platform
+fn update_prebuilt(favs: &CargoFavourites) -> io::Result<()> {
+    let mut prebuilt = cargo::read_prebuilt();
+    for (name, record) in prebuilt.clone() {
+        let fallback = Krate::Name(name.clone());
+        let krate = favs.find(&name).unwrap_or(&fallback);
+        let req = krate.version_req();
+        let latest = match utils::cratesio::latest_version(&name, req.as_ref()) {
+            Ok(Some(l)) => l,
+            Ok(None) => continue,
+            Err(error) => {
+                println!("warning: rust: unable to check {}: {}", &name, error);
+                continue;
+            }
+        };
+        if Version::parse(&record.version).is_ok_and(|v| v >= latest) {
+            continue;
+        }
 
-    #[test]
-    fn test_cargo_latest_version() {
-        let version = cargo_latest_version("serde").expect("must execute");
-        assert!(!version.is_empty());
-        assert!(cargo_latest_version("this-does-not-exist-maybe").is_err());
+        match cargo::install_prebuilt(krate, &latest) {
+            Some(p) => {
+                prebuilt.insert(name, p);
+            }
+            None => {
+                record.delete_files();
+                prebuilt.remove(&name);
+                let mut args = krate.install_args(favs.locked());
+                args.push(String::from("--force"));
+                cargo::cargo(&args)?;
+            }
+        }
+        cargo::write_prebuilt(&prebuilt)?;
     }
+    Ok(())
 }
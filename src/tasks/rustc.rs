@@ -1,9 +1,19 @@
-use crate::lib::{
-    rust,
-    task::{self, Status, Task},
+use std::{collections::BTreeSet, fs, io};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    lib::{
+        cargo::{self, CargoFavourites},
+        favourites::Favourites,
+        rust::{self, RustupConfig},
+        task::{self, Status, Task},
+    },
+    utils,
 };
 
-const TOOLCHAINS: &[&str] = &["stable"];
+// LEGACY_RUSTFMT are crates that once provided `cargo fmt` instead of rustup's rustfmt
+const LEGACY_RUSTFMT: &[&str] = &["rustfmt", "rustfmt-nightly", "rustfmt-preview"];
 
 pub fn task() -> Task {
     Task {
@@ -13,23 +23,143 @@ pub fn task() -> Task {
     }
 }
 
+// read_config() is the rustup part of rust.toml, or the crates to `cargo install`
+fn read_config<T>() -> T
+where
+    T: DeserializeOwned + Default,
+{
+    let cfg_path = utils::env::home_dir().join(".dotfiles/config/rust.toml");
+
+    let contents = match fs::read_to_string(&cfg_path) {
+        Ok(s) => s,
+        Err(_error) => {
+            // toolchains are optional, so we use the defaults
+            return Default::default();
+        }
+    };
+
+    match toml::from_str(&contents) {
+        Ok(c) => c,
+        Err(error) => {
+            println!(
+                "warning: rustc: unable to parse {}, {}",
+                &cfg_path.display(),
+                error
+            );
+            Default::default()
+        }
+    }
+}
+
 fn sync() -> task::Result {
     if !rust::has_rustup() {
         return Ok(Status::Skipped);
     }
 
-    let toolchains = rust::rustup_output(&["toolchain", "list"])?;
+    let config: RustupConfig = read_config();
+    let installed = rust::installed_toolchains()?;
+    for (name, toolchain) in config.toolchains() {
+        if !rust::has_toolchain(&installed, &name) {
+            rust::rustup(&["toolchain", "install", &name])?;
+        }
+
+        let components = rust::installed_components(&name)?;
+        let missing: Vec<&String> = toolchain
+            .components
+            .iter()
+            .filter(|c| !rust::has_component(&components, c))
+            .collect();
+        if !missing.is_empty() {
+            let mut args = vec!["component", "add", "--toolchain", &name];
+            args.extend(missing.iter().map(|c| c.as_str()));
+            rust::rustup(&args)?;
+        }
 
-    for t in TOOLCHAINS {
-        let re = regex::Regex::new(&format!("^{}-", t)).unwrap();
-        if !re.is_match(&toolchains) {
-            rust::rustup(&["toolchain", "install", t])?;
+        let targets = rust::installed_targets(&name)?;
+        let missing: Vec<&String> = toolchain
+            .targets
+            .iter()
+            .filter(|t| !targets.contains(t))
+            .collect();
+        if !missing.is_empty() {
+            let mut args = vec!["target", "add", "--toolchain", &name];
+            args.extend(missing.iter().map(|t| t.as_str()));
+            rust::rustup(&args)?;
         }
     }
 
+    let default = config.default_toolchain();
+    let current = rust::default_toolchain().unwrap_or_default();
+    if !rust::has_toolchain(&[current], &default) {
+        rust::rustup(&["default", &default])?;
+    }
+
+    let crates: CargoFavourites = read_config();
+    restore_shadowed_components(&config, &crates.wanted())?;
+
     Ok(Status::Done)
 }
 
+fn has_cargo_fmt() -> bool {
+    match utils::process::command_output("cargo", &["fmt", "--help"]) {
+        Ok(output) => output.status.success(),
+        Err(_error) => {
+            false // cargo probably not installed
+        }
+    }
+}
+
+// restore_shadowed_components() uninstalls crates like rustfmt from `cargo install`,
+// which replace rustup's proxies in ~/.cargo/bin, and then restores those components,
+// unless rust.toml asks for the crate (in `install`) rather than the component
+fn restore_shadowed_components(config: &RustupConfig, wanted: &[String]) -> io::Result<()> {
+    if !cargo::has_cargo() {
+        return Ok(());
+    }
+    let default = config.default_toolchain();
+    let toolchains = config.toolchains();
+    let crates = cargo::found_versions();
+
+    let mut candidates: BTreeSet<&str> = toolchains
+        .values()
+        .flat_map(|t| t.components.iter().map(|c| c.as_str()))
+        .collect();
+    candidates.extend(LEGACY_RUSTFMT);
+    let shadowed: Vec<&str> = candidates
+        .into_iter()
+        .filter(|c| crates.contains_key(*c) && !wanted.iter().any(|w| w == c))
+        .collect();
+
+    // each is a toolchain and component, e.g. ("stable", "rustfmt")
+    let mut restore = BTreeSet::<(String, String)>::new();
+    if !shadowed.is_empty() {
+        let mut args = vec!["uninstall"];
+        args.extend(&shadowed);
+        cargo::cargo(&args)?;
+
+        for crate_name in &shadowed {
+            if LEGACY_RUSTFMT.contains(crate_name) {
+                restore.insert((default.clone(), String::from("rustfmt")));
+            }
+            for (name, toolchain) in &toolchains {
+                if toolchain.components.iter().any(|c| c == crate_name) {
+                    restore.insert((name.clone(), String::from(*crate_name)));
+                }
+            }
+        }
+    }
+    if !has_cargo_fmt() && !wanted.iter().any(|w| LEGACY_RUSTFMT.contains(&w.as_str())) {
+        restore.insert((default, String::from("rustfmt")));
+    }
+
+    for (name, component) in &restore {
+        // the component may not be installed, e.g. if the crate came first
+        rust::rustup(&["component", "remove", "--toolchain", name, component]).ok();
+        rust::rustup(&["component", "add", "--toolchain", name, component])?;
+    }
+    Ok(())
+}
+
 fn update(_: Status) -> task::Result {
     if !rust::has_rustup() {
         return Ok(Status::Skipped);
@@ -24,11 +24,14 @@ pub fn task() -> Task {
 
 fn sync() -> task::Result {
     if rust::has_rustup() {
-        Ok(Status::NoChange(rust::rustup_version()))
-    } else {
-        // TODO: automate installation from https://rustup.rs
-        Ok(Status::Skipped)
+        return Ok(Status::NoChange(rust::rustup_version()));
     }
+
+    rust::install_rustup()?;
+    Ok(Status::Changed(
+        String::from("absent"),
+        rust::rustup_version(),
+    ))
 }
 
 fn update(_: Status) -> task::Result {
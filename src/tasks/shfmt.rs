@@ -1,11 +1,15 @@
 use regex::Regex;
 
 use crate::lib::{
+    bundle::{self, Bundle},
     ghrtask::GHRTask,
     task::{self, Status, Task},
 };
-use crate::utils::github::Asset;
-use crate::utils::golang::{arch, os};
+use crate::utils::{forge::Forge, github::Asset, platform::Platform};
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
 
 pub fn task() -> Task {
     Task {
@@ -16,15 +20,21 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "shfmt",
+    forge: Forge::GitHub,
     repo: ("mvdan", "sh"),
     trim_version,
     version_arg: "--version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let re = Regex::new(&format!("^shfmt_.*_{}_{}(\\.exe)?$", os(), arch())).unwrap();
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let re = Regex::new(&format!(
+        "^shfmt_.*_{}_{}(\\.exe)?$",
+        platform.go_os(),
+        platform.go_arch()
+    ))
+    .unwrap();
 
     re.is_match(&asset.name)
 }
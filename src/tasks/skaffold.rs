@@ -1,16 +1,16 @@
-use std::env::consts::EXE_SUFFIX;
-
 use crate::{
     lib::{
+        bundle::{self, Bundle},
         ghrtask::GHRTask,
         task::{self, Status, Task},
     },
-    utils::{
-        github::Asset,
-        golang::{arch, os},
-    },
+    utils::{forge::Forge, github::Asset, platform::Platform},
 };
 
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHR_TASK.bundle(bundle)
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("skaffold"),
@@ -20,15 +20,21 @@ pub fn task() -> Task {
 }
 
 const GHR_TASK: GHRTask = GHRTask {
-    asset_filter,
+    asset_filter: Some(asset_filter),
     command: "skaffold",
+    forge: Forge::GitHub,
     repo: ("GoogleCloudPlatform", "skaffold"),
     trim_version,
     version_arg: "version",
 };
 
-fn asset_filter(asset: &Asset) -> bool {
-    let name = format!("skaffold-{}-{}{}", os(), arch(), EXE_SUFFIX);
+fn asset_filter(asset: &Asset, platform: &Platform) -> bool {
+    let name = format!(
+        "skaffold-{}-{}{}",
+        platform.go_os(),
+        platform.go_arch(),
+        platform.exe_suffix()
+    );
 
     asset.name == name
 }
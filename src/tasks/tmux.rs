@@ -1,5 +1,17 @@
-use crate::lib::task::{self, Status, Task};
-use crate::utils;
+use crate::lib::{
+    bundle::{self, Bundle},
+    task::{self, Status, Task},
+};
+use crate::utils::{self, platform::Os};
+
+const TPM_URL: &str = "https://github.com/tmux-plugins/tpm.git";
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    if bundle.platform.os == Os::Windows {
+        return Ok(());
+    }
+    bundle.add_git("tpm", TPM_URL, ".tmux/plugins/tpm")
+}
 
 pub fn task() -> Task {
     Task {
@@ -26,8 +38,7 @@ fn sync() -> task::Result {
     let tpm_path = utils::env::home_dir().join(".tmux/plugins/tpm");
     if !utils::git::path_is_git_repository(&tpm_path) {
         utils::fs::delete_if_exists(&tpm_path);
-        let tpm_url = "https://github.com/tmux-plugins/tpm.git";
-        match utils::git::shallow_clone(tpm_url, &tpm_path.to_string_lossy()) {
+        match utils::git::shallow_clone(TPM_URL, &tpm_path.to_string_lossy()) {
             Ok(()) => {}
             Err(error) => println!("tmux: unable to install tpm: {}", error),
         }
@@ -1,12 +1,13 @@
-use std::env::consts::{ARCH, OS};
-
-use regex::Regex;
-
 use crate::lib::{
+    bundle::{self, Bundle},
     ghratask::GHRATask,
     task::{self, Status, Task},
 };
-use crate::utils::github::Asset;
+use crate::utils::forge::Forge;
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    GHRA_TASK.bundle(bundle)
+}
 
 pub fn task() -> Task {
     Task {
@@ -17,46 +18,15 @@ pub fn task() -> Task {
 }
 
 const GHRA_TASK: GHRATask = GHRATask {
-    asset_filter,
+    asset_filter: None,
     command: "vale",
+    files: &[],
+    forge: Forge::GitHub,
     repo: ("errata-ai", "vale"),
     trim_version,
     version_arg: "--version",
 };
 
-pub fn arch() -> &'static str {
-    if ARCH == "x86_64" {
-        "64-bit"
-    } else {
-        ARCH
-    }
-}
-
-fn asset_filter(asset: &Asset) -> bool {
-    let re = Regex::new(&format!(
-        r"^vale_.*_{}_{}{}$",
-        os(),
-        arch(),
-        if OS == "windows" {
-            r"\.zip"
-        } else {
-            r"\.tar\.gz"
-        }
-    ))
-    .unwrap();
-
-    re.is_match(&asset.name)
-}
-
-pub fn os() -> &'static str {
-    match OS {
-        "linux" => "Linux",
-        "macos" => "macOS",
-        "windows" => "Windows",
-        _ => OS,
-    }
-}
-
 fn sync() -> task::Result {
     GHRA_TASK.sync()
 }
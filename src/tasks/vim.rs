@@ -3,7 +3,9 @@ use std::{self, io, path::PathBuf};
 use which::which;
 
 use crate::lib::{
+    bundle::{self, Bundle, Entry, EntryKind},
     env::Exports,
+    mirrors,
     task::{self, Status, Task},
 };
 use crate::utils;
@@ -30,6 +32,23 @@ const VIMS: [Vim; 2] = [
     },
 ];
 
+// bundle() only has vim-plug, as plugins are installed by vim-plug itself
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    let path = format!("vim/{}", PLUG_VIM);
+    utils::http::download(vim_plug_url(), bundle.path(&path)?)?;
+
+    for vim in &VIMS {
+        bundle.add(Entry {
+            name: String::from(vim.command),
+            version: String::from("master"),
+            kind: EntryKind::File,
+            path: path.clone(),
+            dest: format!("{}/autoload/{}", vim.autoload_parent_dir, PLUG_VIM),
+        });
+    }
+    Ok(())
+}
+
 pub fn env(mut exports: Exports) -> Exports {
     for vim in &VIMS {
         if let Ok(found) = which(&vim.command) {
@@ -114,6 +133,13 @@ fn update(_: Status) -> task::Result {
     Ok(Status::Done)
 }
 
+fn vim_plug_url() -> String {
+    format!(
+        "{}/junegunn/vim-plug/master/plug.vim",
+        mirrors::github_raw()
+    )
+}
+
 #[derive(Debug)]
 struct Vim<'a> {
     autoload_parent_dir: &'a str, // used in: $HOME/autoload_dir/autoload
@@ -147,9 +173,7 @@ impl<'a> Vim<'a> {
         std::fs::create_dir_all(self.autoload_dir())?;
 
         let vim_plug = self.autoload_dir().join(PLUG_VIM);
-        let vim_plug_url =
-            String::from("https://raw.githubusercontent.com/junegunn/vim-plug/master/plug.vim");
-        utils::http::download(vim_plug_url, vim_plug)?;
+        utils::http::download(vim_plug_url(), vim_plug)?;
         Ok(())
     }
 
@@ -1,10 +1,22 @@
 use std::env::consts::OS;
 
 use crate::{
-    lib::task::{self, Status, Task},
-    utils,
+    lib::{
+        bundle::{self, Bundle},
+        task::{self, Status, Task},
+    },
+    utils::{self, platform::Os},
 };
 
+const OH_MY_ZSH_URL: &str = "https://github.com/robbyrussell/oh-my-zsh.git";
+
+pub fn bundle(bundle: &mut Bundle) -> bundle::Result {
+    if bundle.platform.os == Os::Windows {
+        return Ok(());
+    }
+    bundle.add_git("oh-my-zsh", OH_MY_ZSH_URL, ".oh-my-zsh")
+}
+
 pub fn task() -> Task {
     Task {
         name: String::from("zsh"),
@@ -30,8 +42,7 @@ fn sync() -> task::Result {
     let oh_path = utils::env::home_dir().join(".oh-my-zsh");
     if !utils::git::path_is_git_repository(&oh_path) {
         utils::fs::delete_if_exists(&oh_path);
-        let oh_url = "https://github.com/robbyrussell/oh-my-zsh.git";
-        match utils::git::shallow_clone(oh_url, &oh_path.to_string_lossy()) {
+        match utils::git::shallow_clone(OH_MY_ZSH_URL, &oh_path.to_string_lossy()) {
             Ok(()) => {}
             Err(error) => println!("zsh: unable to install oh-my-zsh: {}", error),
         }
@@ -1,87 +1,159 @@
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter};
-use std::path::Path;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Component, Path, PathBuf};
 
 use crate::utils::{self, fs::mkftemp};
 
-pub fn extract_gz(source: &Path, target: &Path) -> io::Result<()> {
-    let source_file = match File::open(&source) {
-        Ok(f) => f,
-        Err(error) => {
-            println!(
-                "extract_gz: unable to open source file: {}",
-                source.display()
-            );
-            return Err(error);
-        }
-    };
-    let mut source_reader = BufReader::new(source_file);
+// Format is what we detect from the first bytes of an archive,
+// as file names and extensions are not always trustworthy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Bzip2,
+    Gzip,
+    Tar,
+    Xz,
+    Zip,
+    Zstd,
+    Unknown,
+}
 
-    let target_file = match File::create(&target) {
-        Ok(f) => f,
-        Err(error) => {
-            println!(
-                "extract_gz: unable to open target file: {}",
-                target.display()
-            );
-            return Err(error);
-        }
-    };
-    let mut target_writer = BufWriter::new(target_file);
+const TAR_MAGIC_OFFSET: usize = 257;
 
-    let mut decoder = libflate::gzip::Decoder::new(&mut source_reader).unwrap();
-    std::io::copy(&mut decoder, &mut target_writer)?;
-    Ok(())
+pub fn detect(source: &Path) -> io::Result<Format> {
+    Ok(peek(File::open(source)?)?.0)
 }
 
-pub fn extract_tar(source: &Path, target: &Path) -> io::Result<()> {
-    let file = match File::open(&source) {
-        Ok(f) => f,
-        Err(error) => {
-            println!(
-                "extract_tar: unable to open source file: {}",
-                source.display()
-            );
-            return Err(error);
-        }
-    };
-    let mut a = tar::Archive::new(file);
+// peek() detects the format of a stream,
+// returning a reader that still starts from the beginning
+pub fn peek<R>(mut reader: R) -> io::Result<(Format, impl Read)>
+where
+    R: Read,
+{
+    let mut header = Vec::<u8>::new();
+    (&mut reader)
+        .take(TAR_MAGIC_OFFSET as u64 + 8)
+        .read_to_end(&mut header)?;
+    Ok((detect_magic(&header), io::Cursor::new(header).chain(reader)))
+}
 
-    #[allow(clippy::identity_conversion)]
-    for entry in a.entries()? {
-        // Make sure there wasn't an I/O error
-        let mut entry = entry?;
+fn detect_magic(header: &[u8]) -> Format {
+    if header.starts_with(&[0x1f, 0x8b]) {
+        Format::Gzip
+    } else if header.starts_with(b"BZh") {
+        Format::Bzip2
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Format::Xz
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Format::Zstd
+    } else if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Format::Zip
+    } else if header.len() > TAR_MAGIC_OFFSET + 5
+        && &header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5] == b"ustar"
+    {
+        Format::Tar
+    } else {
+        Format::Unknown
+    }
+}
 
-        if !entry.header().entry_type().is_file() {
-            continue;
+// decoder() decompresses `reader`, which must be a compressed `format`
+pub fn decoder<'r, R>(format: Format, reader: R) -> io::Result<Box<dyn Read + 'r>>
+where
+    R: Read + 'r,
+{
+    let buffered = BufReader::new(reader);
+    Ok(match format {
+        Format::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(buffered)),
+        Format::Gzip => Box::new(libflate::gzip::MultiDecoder::new(buffered)?),
+        Format::Xz => Box::new(lzma_rust2::XzReader::new(buffered, true)),
+        Format::Zstd => Box::new(
+            ruzstd::streaming_decoder::StreamingDecoder::new(buffered)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+        ),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a compressed format", format),
+            ));
         }
+    })
+}
 
-        entry.set_preserve_permissions(true);
-        entry.unpack_in(&target)?;
-    }
-
-    Ok(())
+// extract() detects the format of `source`, and extracts it into `target`,
+// with a compressed file that is not a tarball extracted as `target/bare_name`
+pub fn extract(source: &Path, target: &Path, bare_name: &str) -> io::Result<()> {
+    extract_strip(source, target, bare_name, 0)
 }
 
-pub fn extract_tar_gz(source: &Path, target: &Path) -> io::Result<()> {
-    let temp_path = mkftemp()?;
-    extract_gz(&source, &temp_path)?;
-    extract_tar(&temp_path, &target)?;
+// extract_strip() is extract() that also drops the first `strip_components`
+// directories from every path in the archive, like `tar --strip-components`
+pub fn extract_strip(
+    source: &Path,
+    target: &Path,
+    bare_name: &str,
+    strip_components: usize,
+) -> io::Result<()> {
+    if detect(source)? == Format::Zip {
+        return extract_zip(source, target, strip_components);
+    }
+    extract_stream(File::open(source)?, target, bare_name, strip_components)
+}
 
-    utils::fs::delete_if_exists(&temp_path);
-    Ok(())
+// extract_stream() decompresses and unpacks in a single pass,
+// without writing the archive to disk first (except for .zip files)
+pub fn extract_stream<R>(
+    reader: R,
+    target: &Path,
+    bare_name: &str,
+    strip_components: usize,
+) -> io::Result<()>
+where
+    R: Read,
+{
+    let (format, mut reader) = peek(reader)?;
+    match format {
+        Format::Tar => unpack_tar(reader, target, strip_components),
+        Format::Zip => {
+            // .zip files keep their index at the end, so we need to seek
+            let temp_path = mkftemp()?;
+            {
+                let mut writer = BufWriter::new(File::create(&temp_path)?);
+                io::copy(&mut reader, &mut writer)?;
+            }
+            let result = extract_zip(&temp_path, target, strip_components);
+            utils::fs::delete_if_exists(&temp_path);
+            result
+        }
+        Format::Unknown => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unknown archive format",
+        )),
+        _ => {
+            let (inner, mut reader) = peek(decoder(format, reader)?)?;
+            if inner == Format::Tar {
+                unpack_tar(reader, target, strip_components)
+            } else {
+                std::fs::create_dir_all(target)?;
+                let mut writer = BufWriter::new(File::create(target.join(bare_name))?);
+                io::copy(&mut reader, &mut writer)?;
+                Ok(())
+            }
+        }
+    }
 }
 
-pub fn extract_zip(source: &Path, target: &Path) -> io::Result<()> {
-    extract_zip_pattern(source, target, &|_| true)
+pub fn extract_zip(source: &Path, target: &Path, strip_components: usize) -> io::Result<()> {
+    extract_zip_pattern(source, target, strip_components, &|_| true)
 }
 
 pub fn extract_zip_pattern(
     source: &Path,
     target: &Path,
+    strip_components: usize,
     p: &dyn Fn(String) -> bool,
 ) -> io::Result<()> {
-    let zip_file = match File::open(&source) {
+    let zip_file = match File::open(source) {
         Ok(f) => f,
         Err(error) => {
             println!(
@@ -92,45 +164,284 @@ pub fn extract_zip_pattern(
         }
     };
     let mut zip = zip::ZipArchive::new(zip_file)?;
+    std::fs::create_dir_all(target)?;
+
+    let mut dir_modes = Vec::<(PathBuf, u32)>::new();
 
     for i in 0..zip.len() {
         let mut entry = zip.by_index(i)?;
-        let entry_path = entry.sanitized_name();
+        let entry_path = match entry.enclosed_name() {
+            Some(e) => e.to_path_buf(),
+            None => return Err(unsafe_path(Path::new(entry.name()))),
+        };
 
-        if entry.name().ends_with('/') {
-            continue; // skip directories
-        }
         if !p(String::from(entry.name())) {
             continue; // skip non-matching file names
         }
+        let relative = match strip(&entry_path, strip_components)? {
+            Some(r) => r,
+            None => continue,
+        };
+        let output_path = prepare_output(target, &relative)?;
+        let mode = entry.unix_mode();
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&output_path)?;
+            if let Some(m) = mode {
+                dir_modes.push((output_path, m));
+            }
+            continue;
+        }
 
-        let output_path = target.join(entry_path);
-        std::fs::create_dir_all(&output_path.parent().unwrap_or(&output_path))?;
+        if mode.is_some_and(|m| m & S_IFMT == S_IFLNK) {
+            let mut link = String::new();
+            entry.read_to_string(&mut link)?;
+            create_symlink(&relative, Path::new(&link), &output_path)?;
+            continue;
+        }
 
         let mut output_file = File::create(&output_path)?;
         std::io::copy(&mut entry, &mut output_file)?;
+        if let Some(m) = mode {
+            set_mode(&output_path, m)?;
+        }
+    }
+
+    for (dir, mode) in dir_modes.iter().rev() {
+        set_mode(dir, *mode)?;
+    }
+
+    Ok(())
+}
+
+fn unpack_tar<R>(reader: R, target: &Path, strip_components: usize) -> io::Result<()>
+where
+    R: Read,
+{
+    let mut a = tar::Archive::new(reader);
+    std::fs::create_dir_all(target)?;
+
+    // set directory modes last, in case they are not writable
+    let mut dir_modes = Vec::<(PathBuf, u32)>::new();
+
+    for entry in a.entries()? {
+        // Make sure there wasn't an I/O error
+        let mut entry = entry?;
+
+        let relative = match strip(&entry.path()?, strip_components)? {
+            Some(r) => r,
+            None => continue,
+        };
+        let entry_type = entry.header().entry_type();
+
+        // resolve hard links before prepare_output() removes anything
+        let original = if entry_type.is_hard_link() {
+            let link = link_name(entry.link_name()?)?;
+            match strip(&link, strip_components)? {
+                Some(l) => Some(hard_link_original(target, &l)?),
+                None => return Err(unsafe_path(&link)),
+            }
+        } else {
+            None
+        };
+        let output_path = prepare_output(target, &relative)?;
+
+        if entry_type.is_dir() {
+            std::fs::create_dir_all(&output_path)?;
+            dir_modes.push((output_path, entry.header().mode()?));
+        } else if entry_type.is_symlink() {
+            let link = link_name(entry.link_name()?)?;
+            create_symlink(&relative, &link, &output_path)?;
+        } else if let Some(original) = original {
+            std::fs::hard_link(original, &output_path)?;
+        } else if entry_type.is_file() || entry_type.is_contiguous() {
+            entry.set_preserve_permissions(true);
+            entry.unpack(&output_path)?;
+        }
+        // skip devices, FIFOs, and other entries that we never need
+    }
+
+    for (dir, mode) in dir_modes.iter().rev() {
+        set_mode(dir, *mode)?;
+    }
+
+    Ok(())
+}
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+
+// strip() checks that `path` stays within the extraction directory,
+// then drops its first `count` directories (or returns None if nothing is left)
+fn strip(path: &Path, count: usize) -> io::Result<Option<PathBuf>> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            _ => return Err(unsafe_path(path)),
+        }
+    }
+    if parts.len() <= count {
+        return Ok(None);
+    }
+    Ok(Some(parts[count..].iter().collect()))
+}
+
+// prepare_output() creates the parent directories for `relative`,
+// refusing to write through a symlink that an earlier entry created
+fn prepare_output(target: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let mut output_path = target.to_path_buf();
+    let mut parts = relative.iter().peekable();
+    while let Some(part) = parts.next() {
+        output_path.push(part);
+        if let Ok(attr) = std::fs::symlink_metadata(&output_path) {
+            if attr.file_type().is_symlink() {
+                if parts.peek().is_some() {
+                    return Err(unsafe_path(relative));
+                }
+                // replace the symlink, rather than write to what it points at
+                utils::fs::delete_if_exists(&output_path);
+            }
+        }
+    }
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(output_path)
+}
+
+// hard_link_original() rejects hard links through or to symlinks, which could
+// point outside the target
+fn hard_link_original(target: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let mut original = target.to_path_buf();
+    for part in relative {
+        original.push(part);
+        if let Ok(attr) = std::fs::symlink_metadata(&original) {
+            if attr.file_type().is_symlink() {
+                return Err(unsafe_path(relative));
+            }
+        }
     }
+    Ok(original)
+}
+
+// create_symlink() only creates relative symlinks to paths within the archive,
+// even if they do not exist (yet)
+fn create_symlink(relative: &Path, link: &Path, output_path: &Path) -> io::Result<()> {
+    let mut depth = relative.components().count() - 1;
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "symlink escapes archive: {} -> {}",
+                        relative.display(),
+                        link.display()
+                    ),
+                ));
+            }
+        }
+    }
+    utils::fs::symbolic_link(link, output_path)
+}
 
+fn link_name(link: Option<Cow<'_, Path>>) -> io::Result<PathBuf> {
+    match link {
+        Some(l) => Ok(l.into_owned()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "link without a target",
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
     Ok(())
 }
 
+fn unsafe_path(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unsafe path in archive: {}", path.display()),
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use std::io::Read;
-
     use crate::utils::fs::mkdtemp;
 
     use super::*;
 
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn detect_fixture_formats() {
+        let cases = vec![
+            ("foo.txt.gz", Format::Gzip),
+            ("foo.txt.tar", Format::Tar),
+            ("foo.txt.tar.bz2", Format::Bzip2),
+            ("foo.txt.tar.xz", Format::Xz),
+            ("foo.txt.tar.zst", Format::Zstd),
+            ("foo.txt.zip", Format::Zip),
+            ("ssh_config.input.txt", Format::Unknown),
+        ];
+        for (name, want) in cases {
+            assert_eq!(detect(&fixture(name)).expect("must read"), want, "{}", name);
+        }
+    }
+
+    #[test]
+    fn extract_detects_compressed_tarballs() {
+        for name in &["foo.txt.tar.bz2", "foo.txt.tar.xz", "foo.txt.tar.zst"] {
+            let temp_path = mkdtemp().unwrap();
+
+            extract(&fixture(name), &temp_path, "unused").expect(name);
+
+            let got = std::fs::read_to_string(temp_path.join("foo.txt")).expect(name);
+            assert_eq!(got.trim(), "hello, world!");
+            assert!(!temp_path.join("unused").exists());
+
+            utils::fs::delete_if_exists(&temp_path);
+        }
+    }
+
+    #[test]
+    fn extract_bare_xz_file() {
+        let temp_path = mkdtemp().unwrap();
+
+        extract(&fixture("foo.txt.xz"), &temp_path, "foo").expect("error");
+
+        let got = std::fs::read_to_string(temp_path.join("foo")).expect("error");
+        assert_eq!(got.trim(), "hello, world!");
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
     #[test]
     fn extract_fixture_foo_txt_gz() {
-        let temp_path = mkftemp().unwrap();
+        let temp_path = mkdtemp().unwrap();
 
         let foo_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.gz");
 
-        extract_gz(&foo_path, &temp_path).expect("error");
+        extract(&foo_path, &temp_path, "foo.txt").expect("error");
 
-        let extracted = File::open(&temp_path).unwrap();
+        let extracted = File::open(temp_path.join("foo.txt")).unwrap();
         let mut reader = BufReader::new(extracted);
         let mut got = String::new();
         reader.read_to_string(&mut got).unwrap();
@@ -146,7 +457,7 @@ mod tests {
 
         let foo_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.tar");
 
-        extract_tar(&foo_path, &temp_path).expect("error");
+        extract(&foo_path, &temp_path, "foo.txt").expect("error");
 
         let extracted = File::open(&temp_path.join("foo.txt")).unwrap();
         let mut reader = BufReader::new(extracted);
@@ -164,7 +475,7 @@ mod tests {
 
         let foo_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.zip");
 
-        extract_zip(&foo_path, &temp_path).expect("error");
+        extract_zip(&foo_path, &temp_path, 0).expect("error");
 
         let extracted = File::open(&temp_path.join("foo.txt")).unwrap();
         let mut reader = BufReader::new(extracted);
@@ -175,4 +486,133 @@ mod tests {
 
         utils::fs::delete_if_exists(&temp_path);
     }
+
+    #[test]
+    fn extract_stream_compressed_tarball() {
+        let temp_path = mkdtemp().unwrap();
+        let file = File::open(fixture("foo.txt.tar.zst")).unwrap();
+
+        extract_stream(file, &temp_path, "foo", 0).expect("error");
+
+        let got = std::fs::read_to_string(temp_path.join("foo.txt")).expect("error");
+        assert_eq!(got.trim(), "hello, world!");
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn peek_keeps_the_whole_stream() {
+        let want = std::fs::read(fixture("foo.txt.tar")).unwrap();
+        let (format, mut reader) = peek(&want[..]).expect("must read");
+        assert_eq!(format, Format::Tar);
+        let mut got = Vec::<u8>::new();
+        reader.read_to_end(&mut got).unwrap();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn extract_links_and_directories() {
+        for name in &["links.tar", "links.zip"] {
+            let temp_path = mkdtemp().unwrap();
+
+            extract(&fixture(name), &temp_path, "node").expect(name);
+
+            let npm = temp_path.join("node/bin/npm");
+            assert_eq!(
+                std::fs::read_link(&npm).expect(name),
+                Path::new("../lib/cli.js")
+            );
+            let got = std::fs::read_to_string(&npm).expect(name);
+            assert_eq!(got.trim(), "hello, world!");
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = |p: &str| {
+                    let attr = std::fs::metadata(temp_path.join(p)).expect(name);
+                    attr.permissions().mode() & 0o777
+                };
+                assert_eq!(mode("node/lib/cli.js"), 0o755, "{}", name);
+            }
+
+            utils::fs::delete_if_exists(&temp_path);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn extract_hard_links_and_directory_modes() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        let temp_path = mkdtemp().unwrap();
+
+        extract(&fixture("links.tar"), &temp_path, "node").expect("error");
+
+        let cli = std::fs::metadata(temp_path.join("node/lib/cli.js")).unwrap();
+        let copy = std::fs::metadata(temp_path.join("node/lib/copy.js")).unwrap();
+        assert_eq!(cli.ino(), copy.ino());
+
+        let private = std::fs::metadata(temp_path.join("node/private")).unwrap();
+        assert_eq!(private.permissions().mode() & 0o777, 0o700);
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn extract_strip_components() {
+        for name in &["links.tar", "links.zip"] {
+            let temp_path = mkdtemp().unwrap();
+
+            extract_strip(&fixture(name), &temp_path, "node", 1).expect(name);
+
+            assert!(temp_path.join("lib/cli.js").is_file(), "{}", name);
+            assert!(temp_path.join("bin/npm").is_file(), "{}", name);
+            assert!(!temp_path.join("node").exists(), "{}", name);
+
+            utils::fs::delete_if_exists(&temp_path);
+        }
+    }
+
+    #[test]
+    fn extract_rejects_unsafe_paths() {
+        let names = vec![
+            "absolute-path.tar",
+            "absolute-symlink.tar",
+            "escaping-symlink.tar",
+            "symlink-then-file.tar",
+            "traversal.tar",
+            "traversal.zip",
+        ];
+        for name in names {
+            let parent = mkdtemp().unwrap();
+            let temp_path = parent.join("target");
+
+            let error = extract(&fixture(name), &temp_path, "evil").expect_err(name);
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", name);
+            assert!(!parent.join("evil.txt").exists(), "{}", name);
+
+            utils::fs::delete_if_exists(&parent);
+        }
+    }
+
+    #[test]
+    fn extract_rejects_hard_link_to_symlink() {
+        let temp_path = mkdtemp().unwrap();
+
+        let error = extract(&fixture("hardlink-to-symlink.tar"), &temp_path, "evil")
+            .expect_err("must reject");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let sym = temp_path.join("evil/sym");
+        assert_eq!(std::fs::read_link(&sym).unwrap(), Path::new("real.txt"));
+        assert!(!temp_path.join("evil/copy").exists());
+
+        utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn strip_drops_leading_directories() {
+        let got = strip(Path::new("./node/bin/npm"), 1).expect("must be safe");
+        assert_eq!(got, Some(PathBuf::from("bin/npm")));
+        assert_eq!(strip(Path::new("node/"), 1).expect("must be safe"), None);
+        assert!(strip(Path::new("node/../../evil"), 0).is_err());
+    }
 }
@@ -0,0 +1,83 @@
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+// HashReader hashes everything read through it,
+// so that we can verify a download while we extract it
+pub struct HashReader<R> {
+    hasher: Sha256,
+    inner: R,
+}
+
+impl<R> HashReader<R>
+where
+    R: Read,
+{
+    pub fn new(inner: R) -> HashReader<R> {
+        HashReader {
+            hasher: Sha256::new(),
+            inner,
+        }
+    }
+
+    // finish() also hashes whatever the caller did not read,
+    // e.g. the padding after the end of a tarball
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(format!("{:x}", self.hasher.result()))
+    }
+}
+
+impl<R> Read for HashReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.input(&buf[..n]);
+        Ok(n)
+    }
+}
+
+pub fn verify<S>(name: S, got: S, want: S) -> io::Result<()>
+where
+    S: AsRef<str>,
+{
+    if got.as_ref().eq_ignore_ascii_case(want.as_ref().trim()) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: expected SHA-256 {}, but got {}",
+                name.as_ref(),
+                want.as_ref().trim(),
+                got.as_ref()
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // echo -n "hello, world!" | sha256sum
+    const HELLO_SHA256: &str = "68e656b251e67e8358bef8483ab0d51c6619f3e7a1a9f0e75838d41ff368f728";
+
+    #[test]
+    fn hashes_unread_bytes_too() {
+        let mut reader = HashReader::new(&b"hello, world!"[..]);
+        let mut start = [0u8; 5];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"hello");
+        assert_eq!(reader.finish().unwrap(), HELLO_SHA256);
+    }
+
+    #[test]
+    fn verify_compares_hex_case_insensitively() {
+        assert!(verify("hello", HELLO_SHA256, HELLO_SHA256.to_uppercase().as_str()).is_ok());
+        let error = verify("hello", HELLO_SHA256, "00").expect_err("must mismatch");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
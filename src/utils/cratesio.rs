@@ -0,0 +1,106 @@
+use std::io::{self, Read};
+
+use semver::{Version, VersionReq};
+use serde_derive::Deserialize;
+
+use crate::{lib::mirrors, utils};
+
+// IndexEntry is one line of a crate's file in the index,
+// see https://doc.rust-lang.org/cargo/reference/registry-index.html
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    repository: Option<String>,
+}
+
+// latest_version() is the newest release of `name` within `req`,
+// from the sparse index, or a clone of the git index with a `file://` mirror
+pub fn latest_version(name: &str, req: Option<&VersionReq>) -> io::Result<Option<Version>> {
+    let url = format!("{}/{}", mirrors::crates_index(), index_path(name));
+    let mut res = utils::http::fetch_request(utils::http::create_request(url, None))?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    Ok(pick_latest(&body, req))
+}
+
+// repository() is the source repository that the crate's author published
+pub fn repository(name: &str) -> io::Result<Option<String>> {
+    let url = format!("{}/crates/{}", mirrors::crates_io_api(), name);
+    let mut res = utils::http::fetch_request(utils::http::create_request(url, None))?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    let info: CrateResponse = serde_json::from_str(&body)?;
+    Ok(info.krate.repository)
+}
+
+// index_path() is where the index keeps `name`, e.g. "se/rd/serde" or "3/l/log"
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+// pick_latest() skips yanked releases, and prereleases unless `req` asks for them
+fn pick_latest(body: &str, req: Option<&VersionReq>) -> Option<Version> {
+    body.lines()
+        .filter_map(|line| serde_json::from_str::<IndexEntry>(line).ok())
+        .filter(|e| !e.yanked)
+        .filter_map(|e| Version::parse(&e.vers).ok())
+        .filter(|v| match req {
+            Some(r) => r.matches(v),
+            None => v.pre.is_empty(),
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERDE: &str = r#"{"name":"serde","vers":"1.0.117","deps":[],"cksum":"a","features":{},"yanked":false}
+{"name":"serde","vers":"1.0.118","deps":[],"cksum":"b","features":{},"yanked":false}
+{"name":"serde","vers":"1.0.119","deps":[],"cksum":"c","features":{},"yanked":true}
+{"name":"serde","vers":"2.0.0-alpha.1","deps":[],"cksum":"d","features":{},"yanked":false}
+{"name":"serde","vers":"0.9.15","deps":[],"cksum":"e","features":{},"yanked":false}
+"#;
+
+    #[test]
+    fn index_path_follows_cargo_layout() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("cc"), "2/cc");
+        assert_eq!(index_path("log"), "3/l/log");
+        assert_eq!(index_path("Serde"), "se/rd/serde");
+    }
+
+    #[test]
+    fn pick_latest_skips_yanked_and_prereleases() {
+        assert_eq!(pick_latest(SERDE, None), Version::parse("1.0.118").ok());
+        let req = VersionReq::parse("0.9").unwrap();
+        assert_eq!(
+            pick_latest(SERDE, Some(&req)),
+            Version::parse("0.9.15").ok()
+        );
+        let req = VersionReq::parse("2.0.0-alpha").unwrap();
+        assert_eq!(
+            pick_latest(SERDE, Some(&req)),
+            Version::parse("2.0.0-alpha.1").ok()
+        );
+        assert_eq!(pick_latest("", None), None);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{env::var, io::Read, path::Path};
+
+use reqwest::{header, Url};
+
+use crate::lib::mirrors;
+use crate::utils::{
+    self, gitea,
+    github::{self, Release, Result},
+    gitlab,
+};
+
+const GITLAB_COM: &str = "gitlab.com";
+
+// Forge is where a tool publishes its releases,
+// with hosts like "gitlab.com" or "https://git.example.com"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Forge<'a> {
+    GitHub,
+    GitHubEnterprise(&'a str),
+    GitLab(&'a str),
+    Gitea(&'a str), // also Forgejo, e.g. "codeberg.org"
+}
+
+impl<'a> Forge<'a> {
+    // from_repository() is the forge, owner, and name for a repository URL,
+    // e.g. "https://gitlab.com/group/subgroup/tool/-/tree/main" or "https://codeberg.org/owner/tool",
+    // recognising self-hosted forges by their conventional host names, e.g. "gitea.example.com"
+    pub fn from_repository(url: &'a str) -> Option<(Forge<'a>, &'a str, &'a str)> {
+        let rest = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.");
+        let (host, path) = rest.split_once('/')?;
+        let forge = match host {
+            "github.com" => Forge::GitHub,
+            "gitlab.com" => Forge::GitLab(host),
+            "codeberg.org" | "gitea.com" => Forge::Gitea(host),
+            h if h.starts_with("github.") => Forge::GitHubEnterprise(host),
+            h if h.starts_with("gitlab.") => Forge::GitLab(host),
+            h if h.starts_with("gitea.") || h.starts_with("forgejo.") => Forge::Gitea(host),
+            _ => return None,
+        };
+
+        let path = path.trim_end_matches('/');
+        let (owner, repo) = match forge {
+            // GitLab has subgroups, and puts everything else after "/-/"
+            Forge::GitLab(_) => path.split("/-/").next()?.rsplit_once('/')?,
+            _ => {
+                let end = path
+                    .match_indices('/')
+                    .nth(1)
+                    .map_or(path.len(), |(i, _)| i);
+                path[..end].split_once('/')?
+            }
+        };
+        let repo = repo.trim_end_matches(".git");
+        if owner.is_empty() || repo.is_empty() {
+            return None;
+        }
+        Some((forge, owner, repo))
+    }
+
+    pub fn download<P, S>(&self, url: S, target: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let req = utils::http::create_request(url.as_ref(), Some(self.headers_for(url.as_ref())));
+        utils::http::download_request(req, target.as_ref())?;
+        Ok(())
+    }
+
+    pub fn stream<S>(&self, url: S) -> Result<Box<dyn Read>>
+    where
+        S: AsRef<str>,
+    {
+        let req = utils::http::create_request(url.as_ref(), Some(self.headers_for(url.as_ref())));
+        Ok(utils::http::stream_request(req)?)
+    }
+
+    pub fn latest_release<S>(&self, owner: S, repo: S) -> Result<Release>
+    where
+        S: AsRef<str>,
+    {
+        github::latest_stable(self.fetch_releases(owner, repo)?)
+    }
+
+    // auth_headers() are for this forge's own API,
+    // and as Authorization headers, reqwest drops them when a redirect leaves the host
+    fn auth_headers(&self) -> header::HeaderMap {
+        match self {
+            Forge::GitHub => github::auth_headers(),
+            Forge::GitHubEnterprise(host) => {
+                github::token_headers(var(token_var("GITHUB_TOKEN", host)).ok())
+            }
+            Forge::GitLab(host) => {
+                let token = var(token_var("GITLAB_TOKEN", host)).ok().or_else(|| {
+                    if *host == GITLAB_COM {
+                        var("GITLAB_TOKEN").ok()
+                    } else {
+                        None
+                    }
+                });
+                let mut headers = header::HeaderMap::new();
+                if let Some(t) = token {
+                    headers.insert(
+                        header::AUTHORIZATION,
+                        header::HeaderValue::from_str(&format!("Bearer {}", &t))
+                            .expect("Authorization header"),
+                    );
+                }
+                headers
+            }
+            Forge::Gitea(host) => github::token_headers(var(token_var("GITEA_TOKEN", host)).ok()),
+        }
+    }
+
+    // headers_for() only sends our token when `url` is on this forge,
+    // as release links (e.g. on GitLab) may point anywhere
+    fn headers_for(&self, url: &str) -> header::HeaderMap {
+        if self.sends_token_to(url) {
+            self.auth_headers()
+        } else {
+            header::HeaderMap::new()
+        }
+    }
+
+    fn sends_token_to(&self, url: &str) -> bool {
+        let origin = match Url::parse(url) {
+            Ok(u) => u.origin(),
+            Err(_) => return false,
+        };
+        let bases = match self {
+            // release assets are on github.com, rather than the API host
+            Forge::GitHub => vec![mirrors::github_api(), String::from("https://github.com")],
+            Forge::GitHubEnterprise(host) | Forge::GitLab(host) | Forge::Gitea(host) => {
+                vec![base_url(host)]
+            }
+        };
+        bases
+            .iter()
+            .filter_map(|b| Url::parse(b).ok())
+            .any(|b| b.origin().is_tuple() && b.origin() == origin)
+    }
+
+    pub fn fetch_releases<S>(&self, owner: S, repo: S) -> Result<Vec<Release>>
+    where
+        S: AsRef<str>,
+    {
+        match self {
+            Forge::GitHub => github::fetch_releases(owner, repo),
+            Forge::GitHubEnterprise(host) => github::fetch_releases_from(
+                format!("{}/api/v3", base_url(host)),
+                self.auth_headers(),
+                owner,
+                repo,
+            ),
+            Forge::GitLab(host) => {
+                gitlab::fetch_releases(base_url(host), self.auth_headers(), owner, repo)
+            }
+            Forge::Gitea(host) => {
+                gitea::fetch_releases(base_url(host), self.auth_headers(), owner, repo)
+            }
+        }
+    }
+}
+
+pub fn release_versus_current<S>(current: S, forge: Forge, owner: S, repo: S) -> Option<Release>
+where
+    S: AsRef<str>,
+{
+    match forge.latest_release(owner, repo) {
+        Ok(r) => github::release_versus_current(current, r),
+        Err(error) => {
+            println!("error: {}", error);
+            None
+        }
+    }
+}
+
+fn base_url<S>(host: S) -> String
+where
+    S: AsRef<str>,
+{
+    let h = host.as_ref().trim_end_matches('/');
+    if h.contains("://") {
+        String::from(h)
+    } else {
+        format!("https://{}", h)
+    }
+}
+
+// token_var() is e.g. "GITLAB_TOKEN_GITLAB_EXAMPLE_COM" for "gitlab.example.com"
+fn token_var<S>(prefix: S, host: S) -> String
+where
+    S: AsRef<str>,
+{
+    let h = host.as_ref();
+    let bare = match h.find("://") {
+        Some(i) => &h[i + 3..],
+        None => h,
+    };
+    let suffix: String = bare
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}_{}", prefix.as_ref(), suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_defaults_to_https() {
+        assert_eq!(base_url("gitlab.com"), "https://gitlab.com");
+        assert_eq!(base_url("http://127.0.0.1:3000/"), "http://127.0.0.1:3000");
+    }
+
+    #[test]
+    fn from_repository_urls() {
+        let ripgrep = Some((Forge::GitHub, "BurntSushi", "ripgrep"));
+        assert_eq!(
+            Forge::from_repository("https://github.com/BurntSushi/ripgrep"),
+            ripgrep
+        );
+        assert_eq!(
+            Forge::from_repository("https://github.com/BurntSushi/ripgrep.git"),
+            ripgrep
+        );
+        assert_eq!(
+            Forge::from_repository("https://github.com/BurntSushi/ripgrep/tree/master/crates/core"),
+            ripgrep
+        );
+        assert_eq!(
+            Forge::from_repository("https://gitlab.com/group/subgroup/tool/-/tree/main"),
+            Some((Forge::GitLab("gitlab.com"), "group/subgroup", "tool"))
+        );
+        assert_eq!(
+            Forge::from_repository("https://codeberg.org/owner/tool"),
+            Some((Forge::Gitea("codeberg.org"), "owner", "tool"))
+        );
+        assert_eq!(
+            Forge::from_repository("https://github.example.com/owner/tool/"),
+            Some((
+                Forge::GitHubEnterprise("github.example.com"),
+                "owner",
+                "tool"
+            ))
+        );
+        assert_eq!(
+            Forge::from_repository("https://github.com/BurntSushi"),
+            None
+        );
+        assert_eq!(
+            Forge::from_repository("https://git.example.com/owner/tool"),
+            None
+        );
+    }
+
+    #[test]
+    fn sends_token_only_to_forge_host() {
+        let gitlab = Forge::GitLab("gitlab.example.com");
+        assert!(gitlab.sends_token_to("https://gitlab.example.com/group/tool/-/releases"));
+        assert!(!gitlab.sends_token_to("https://cdn.example.net/tool-1.0.0.tar.gz"));
+        assert!(!gitlab.sends_token_to("https://gitlab.example.com.example.net/tool"));
+        assert!(!gitlab.sends_token_to("http://gitlab.example.com/tool"));
+        assert!(!gitlab.sends_token_to("file:///srv/gitlab.example.com/tool"));
+
+        let gitea = Forge::Gitea("http://127.0.0.1:3000/");
+        assert!(gitea.sends_token_to("http://127.0.0.1:3000/owner/tool/releases"));
+        assert!(!gitea.sends_token_to("http://127.0.0.1:4000/owner/tool/releases"));
+
+        assert!(Forge::GitHub.sends_token_to("https://github.com/owner/tool/releases"));
+        assert!(!Forge::GitHub.sends_token_to("https://objects.example.com/tool"));
+    }
+
+    #[test]
+    fn token_var_is_per_host() {
+        assert_eq!(
+            token_var("GITHUB_TOKEN", "github.example.com"),
+            "GITHUB_TOKEN_GITHUB_EXAMPLE_COM"
+        );
+        assert_eq!(
+            token_var("GITEA_TOKEN", "https://codeberg.org/"),
+            "GITEA_TOKEN_CODEBERG_ORG"
+        );
+    }
+}
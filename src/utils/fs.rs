@@ -40,6 +40,71 @@ where
     }
 }
 
+// delete_empty_ancestors() removes the parents of `path` that are now empty,
+// stopping at `root`
+pub fn delete_empty_ancestors<P>(path: P, root: P)
+where
+    P: AsRef<Path>,
+{
+    let root = root.as_ref();
+    let mut dir = path.as_ref().parent();
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+// disk_usage() is the total size of files within `path`, without following symlinks
+pub fn disk_usage<P>(path: P) -> u64
+where
+    P: AsRef<Path>,
+{
+    let attr = match std::fs::symlink_metadata(path.as_ref()) {
+        Ok(attr) => attr,
+        Err(_error) => return 0,
+    };
+    if !attr.is_dir() {
+        return attr.len();
+    }
+    match std::fs::read_dir(path.as_ref()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| disk_usage(e.path()))
+            .sum(),
+        Err(_error) => 0,
+    }
+}
+
+// make_writable() undoes read-only directories, e.g. in Go's module cache,
+// so that we are able to delete what is within them
+#[cfg(unix)]
+pub fn make_writable<P>(path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let attr = std::fs::symlink_metadata(path.as_ref())?;
+    if !attr.is_dir() {
+        return Ok(());
+    }
+    let mut perms = attr.permissions();
+    perms.set_mode(perms.mode() | 0o700);
+    std::fs::set_permissions(path.as_ref(), perms)?;
+    for entry in std::fs::read_dir(path.as_ref())? {
+        make_writable(entry?.path())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn make_writable<P>(_path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    Ok(())
+}
+
 #[cfg(unix)]
 pub fn set_executable<P>(target: P) -> std::io::Result<()>
 where
@@ -104,8 +169,7 @@ where
 }
 
 #[cfg(not(windows))]
-
-fn symbolic_link<P>(src: P, dest: P) -> io::Result<()>
+pub fn symbolic_link<P>(src: P, dest: P) -> io::Result<()>
 where
     P: AsRef<Path>,
 {
@@ -113,8 +177,7 @@ where
 }
 
 #[cfg(windows)]
-
-fn symbolic_link<P>(src: P, dest: P) -> io::Result<()>
+pub fn symbolic_link<P>(src: P, dest: P) -> io::Result<()>
 where
     P: AsRef<Path>,
 {
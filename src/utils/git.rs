@@ -1,4 +1,4 @@
-use std::{io, path::Path};
+use std::{io, path::Path, str};
 
 use crate::utils;
 
@@ -9,6 +9,43 @@ pub fn has() -> bool {
     }
 }
 
+// fetch_from() is shallow_fetch() from a local clone, e.g. in a bundle
+pub fn fetch_from<P>(target: P, source: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let t = target.as_ref().to_string_lossy();
+    let s = source.as_ref().to_string_lossy();
+    utils::process::command_spawn_wait("git", &["-C", &t, "fetch", "--depth", "1", &s])?;
+    utils::process::command_spawn_wait("git", &["-C", &t, "reset", "--hard", "FETCH_HEAD"])?;
+    Ok(())
+}
+
+pub fn head_commit<P>(path: P) -> io::Result<String>
+where
+    P: AsRef<Path>,
+{
+    let output = utils::process::command_output(
+        "git",
+        &[
+            "-C",
+            &path.as_ref().to_string_lossy(),
+            "rev-parse",
+            "--short",
+            "HEAD",
+        ],
+    )?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`git rev-parse` failed in {}",
+            path.as_ref().display()
+        )));
+    }
+    Ok(String::from(
+        str::from_utf8(&output.stdout).unwrap_or_default().trim(),
+    ))
+}
+
 pub fn path_is_git_repository<P>(path: P) -> bool
 where
     P: AsRef<Path>,
@@ -109,6 +146,8 @@ mod tests {
 
         shallow_fetch(temp_path.to_string_lossy()).unwrap();
 
+        assert!(!head_commit(&temp_path).unwrap().is_empty());
+
         utils::fs::delete_if_exists(&temp_path);
     }
 }
@@ -0,0 +1,64 @@
+use std::io::Read;
+
+use reqwest::header;
+
+use crate::utils::{
+    self,
+    github::{self, Release, Result},
+};
+
+// Gitea (and Forgejo) releases look just like GitHub Releases
+pub fn fetch_releases<B, S>(
+    base: B,
+    headers: header::HeaderMap,
+    owner: S,
+    repo: S,
+) -> Result<Vec<Release>>
+where
+    B: AsRef<str>,
+    S: AsRef<str>,
+{
+    let uri = format!(
+        "{}/api/v1/repos/{}/{}/releases",
+        base.as_ref(),
+        owner.as_ref(),
+        repo.as_ref(),
+    );
+    let req = utils::http::create_request(uri, Some(headers));
+    let mut res = utils::http::fetch_request(req)?;
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap_or_default();
+
+    let releases: Vec<Release> = match github::parse_releases_json(&body) {
+        Ok(r) => r,
+        Err(error) => {
+            println!("cannot fetch Gitea releases: {:?}", error);
+            Vec::<Release>::new()
+        }
+    };
+    Ok(releases)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    #[test]
+    fn parse_gitea_releases_json() {
+        let fixture_path = PathBuf::from("tests/fixtures/gitea-releases.json");
+        let fixture = fs::read_to_string(&fixture_path).expect("must read");
+        let releases = github::parse_releases_json(&fixture).expect("must parse");
+        assert_eq!(releases.len(), 2);
+        assert!(releases[0].prelease);
+        assert_eq!(releases[1].tag_name, "v1.2.0");
+        assert_eq!(
+            releases[1].assets[0].browser_download_url,
+            "https://codeberg.org/example/tool/releases/download/v1.2.0/tool_linux_amd64.tar.gz"
+        );
+
+        let latest = github::latest_stable(releases).expect("must find");
+        assert_eq!(latest.tag_name, "v1.2.0");
+    }
+}
@@ -1,9 +1,9 @@
 use std::{
+    cmp::Reverse,
     env::consts::{ARCH, OS},
     error::Error,
     fmt,
     io::{self, Read},
-    path::Path,
     str,
 };
 
@@ -11,27 +11,32 @@ use reqwest::header;
 use serde_derive::Deserialize;
 
 use crate::{
-    lib::version,
-    utils::{self, http::Request},
+    lib::{mirrors, version},
+    utils::{self, platform::Platform},
 };
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Asset {
     pub browser_download_url: String,
     pub name: String,
-    state: String,
-    updated_at: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub updated_at: String,
 }
 
+// Release is also what we convert other forges' releases into,
+// see utils::forge
 #[derive(Clone, Debug, Deserialize)]
 pub struct Release {
     pub assets: Vec<Asset>,
     #[serde(default = "default_json_false")]
-    draft: bool,
-    name: Option<String>,
-    #[serde(default = "default_json_false")]
-    prelease: bool,
+    pub draft: bool,
+    pub name: Option<String>,
+    #[serde(default = "default_json_false", rename = "prerelease")]
+    pub prelease: bool,
     pub tag_name: String,
+    #[serde(default)]
     pub zipball_url: String,
 }
 
@@ -39,15 +44,9 @@ fn default_json_false() -> bool {
     false
 }
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct Tag {
-    #[serde(rename = "ref")]
-    pub id: String,
-    pub url: String,
-}
-
 #[derive(Debug)]
 pub enum GitHubError {
+    AmbiguousAssets(Vec<String>),
     CompatibleAssetNotFound,
     EmptyReleases,
     IoError(String, io::Error),
@@ -57,6 +56,13 @@ pub enum GitHubError {
 impl fmt::Display for GitHubError {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
         match *self {
+            GitHubError::AmbiguousAssets(ref names) => write!(
+                f,
+                "Multiple assets equally compatible with {} {}: {}",
+                OS,
+                ARCH,
+                names.join(", ")
+            ),
             GitHubError::CompatibleAssetNotFound => {
                 write!(f, "No asset compatible with {} {}", OS, ARCH)
             }
@@ -70,6 +76,7 @@ impl fmt::Display for GitHubError {
 impl Error for GitHubError {
     fn cause(&self) -> Option<&dyn Error> {
         match *self {
+            GitHubError::AmbiguousAssets(_) => None,
             GitHubError::CompatibleAssetNotFound => None,
             GitHubError::EmptyReleases => None,
             GitHubError::IoError(_, ref err) => Some(err as &dyn Error),
@@ -86,6 +93,85 @@ impl From<io::Error> for GitHubError {
 
 pub type Result<T> = std::result::Result<T, GitHubError>;
 
+// AssetKind is whether a task wants a bare executable, or an archive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AssetKind {
+    Archive,
+    Binary,
+}
+
+// longest first, so that ".tar.gz" is found before ".gz"
+const ARCHIVE_SUFFIXES: &[&str] = &[
+    ".tar.bz2", ".tar.zst", ".tar.gz", ".tar.xz", ".tbz2", ".tgz", ".txz", ".bz2", ".zip", ".zst",
+    ".gz", ".xz",
+];
+
+// tarballs in order of preference, for projects that publish several
+const TARBALL_SUFFIXES: &[&str] = &[
+    ".tar.xz", ".txz", ".tar.zst", ".tar.gz", ".tgz", ".tar.bz2", ".tbz2",
+];
+
+// checksums, signatures, and OS packages are never what we want
+const IGNORED_SUFFIXES: &[&str] = &[
+    ".asc",
+    ".deb",
+    ".dmg",
+    ".json",
+    ".md5",
+    ".msi",
+    ".pem",
+    ".pkg",
+    ".rpm",
+    ".sbom",
+    ".sha1",
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".sh",
+    ".sig",
+    ".txt",
+];
+
+// aliases are tokens, so they are lowercase and without separators,
+// e.g. "x86_64" and "x86-64" both become "x8664"
+const JOINED_ALIASES: &[&str] = &["32bit", "64bit", "x8664"];
+const OS_ALIASES: &[(&str, &[&str])] = &[
+    ("freebsd", &["freebsd"]),
+    ("linux", &["linux", "linux32", "linux64"]),
+    (
+        "macos",
+        &[
+            "apple", "darwin", "darwin64", "mac", "macos", "osx", "osx64",
+        ],
+    ),
+    ("netbsd", &["netbsd"]),
+    ("openbsd", &["openbsd"]),
+    ("windows", &["win", "win32", "win64", "windows"]),
+];
+const ARCH_ALIASES: &[(&str, &[&str])] = &[
+    ("aarch64", &["aarch64", "arm64", "armv8"]),
+    (
+        "armv7",
+        &["arm", "armhf", "armv5", "armv6", "armv7", "armv7l"],
+    ),
+    ("mips", &["mips", "mips64", "mips64le", "mipsle"]),
+    ("powerpc64", &["ppc64", "ppc64le", "powerpc64"]),
+    ("riscv64", &["riscv64"]),
+    ("s390x", &["s390x"]),
+    (
+        "x86",
+        &["32bit", "386", "i386", "i686", "linux32", "win32", "x86"],
+    ),
+    (
+        "x86_64",
+        &[
+            "64bit", "amd64", "darwin64", "linux64", "osx64", "win64", "x64", "x8664",
+        ],
+    ),
+];
+const LIBC_GNU: &[&str] = &["glibc", "gnu", "gnueabi", "gnueabihf"];
+const LIBC_MUSL: &[&str] = &["musl", "musleabi", "musleabihf", "static"];
+
 pub fn compatible_asset(release: &Release, filter: &dyn Fn(&Asset) -> bool) -> Result<Asset> {
     match release
         .assets
@@ -98,129 +184,190 @@ pub fn compatible_asset(release: &Release, filter: &dyn Fn(&Asset) -> bool) -> R
     }
 }
 
-pub fn download<S, P>(url: S, target: P) -> Result<()>
-where
-    P: AsRef<Path>,
-    S: AsRef<str>,
-{
-    let req = create_request(url);
-    let bp = target.as_ref();
-    utils::http::download_request(req, &bp)?;
-    Ok(())
+// default_asset() is for tasks without their own asset filter,
+// and picks the asset whose name best matches the platform
+pub fn default_asset(
+    release: &Release,
+    platform: &Platform,
+    command: &str,
+    kind: AssetKind,
+) -> Result<Asset> {
+    let names: Vec<&str> = release
+        .assets
+        .iter()
+        .map(|a| a.name.as_str())
+        .filter(|n| version::is_stable(n))
+        .collect();
+    let best = best_asset(&names, platform, command, kind)?;
+    match release.assets.iter().find(|a| a.name == best) {
+        Some(a) => Ok(a.clone()),
+        None => Err(GitHubError::CompatibleAssetNotFound {}),
+    }
 }
 
-pub fn download_release_asset<P>(asset: &Asset, bin_path: P) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    download(asset.browser_download_url.clone(), &bin_path)?;
-    utils::fs::set_executable(&bin_path)?;
-    Ok(())
-}
+fn best_asset<'n>(
+    names: &[&'n str],
+    platform: &Platform,
+    command: &str,
+    kind: AssetKind,
+) -> Result<&'n str> {
+    let mut scored: Vec<((u32, u32), &str)> = names
+        .iter()
+        .filter_map(|n| score_asset(n, platform, command, kind).map(|s| (s, *n)))
+        .collect();
+    scored.sort_by_key(|(s, _)| Reverse(*s));
 
-pub fn fetch_tags<S>(owner: S, repo: S) -> Result<Vec<Tag>>
-where
-    S: AsRef<str>,
-{
-    let uri = format!(
-        "https://api.github.com/repos/{}/{}/git/refs/tags",
-        owner.as_ref(),
-        repo.as_ref(),
-    );
-    let req = create_request(uri);
-    let mut res = utils::http::fetch_request(req)?;
-    let mut body = String::new();
-    res.read_to_string(&mut body).unwrap_or_default();
+    match scored.as_slice() {
+        [] => Err(GitHubError::CompatibleAssetNotFound {}),
+        [(best, _), (next, _), ..] if best == next => Err(GitHubError::AmbiguousAssets(
+            scored
+                .iter()
+                .filter(|(s, _)| s == best)
+                .map(|(_, n)| String::from(*n))
+                .collect(),
+        )),
+        [(_, name), ..] => Ok(name),
+    }
+}
 
-    let tags: Vec<Tag> = match serde_json::from_str(&body) {
-        Ok(t) => t,
-        Err(error) => {
-            println!("cannot fetch GitHub tags: {:?}", error);
-            Vec::<Tag>::new()
+// score_asset() is None for assets that cannot work here at all,
+// otherwise higher scores are better matches,
+// with ties broken by our preferred archive formats
+fn score_asset(
+    name: &str,
+    platform: &Platform,
+    command: &str,
+    kind: AssetKind,
+) -> Option<(u32, u32)> {
+    let os = platform.os.as_str();
+    let arch = platform.arch.as_str();
+    let lower = name.to_lowercase();
+    if IGNORED_SUFFIXES.iter().any(|s| lower.ends_with(s)) {
+        return None;
+    }
+    let archive = ARCHIVE_SUFFIXES.iter().find(|s| lower.ends_with(*s));
+    let stem = match (kind, archive) {
+        (AssetKind::Archive, Some(suffix)) => lower.trim_end_matches(suffix),
+        (AssetKind::Binary, None) => {
+            if (os == "windows") != lower.ends_with(".exe") {
+                return None;
+            }
+            lower.trim_end_matches(".exe")
         }
+        _ => return None,
     };
-    Ok(tags
-        .into_iter()
-        .map(|t| Tag {
-            id: str::replace(&t.id, "refs/tags/", ""),
-            url: t.url,
-        })
-        .collect())
-}
+    let tokens = tokenise(stem);
 
-pub fn latest_release<S>(owner: S, repo: S) -> Result<Release>
-where
-    S: AsRef<str>,
-{
-    let releases = fetch_releases(owner, repo)?;
-    if releases.is_empty() {
-        return Err(GitHubError::EmptyReleases {});
+    if matching(&tokens, OS_ALIASES) != [os] {
+        return None;
     }
-    match releases.into_iter().find(|r| {
-        let name = r.name.clone().unwrap_or_default();
-        !r.draft && !r.prelease && version::is_stable(name.as_str())
-    }) {
-        Some(latest) => Ok(latest),
-        None => Err(GitHubError::ValidReleaseNotFound {}),
+    let mut score = 10;
+
+    let arches = matching(&tokens, ARCH_ALIASES);
+    let universal = os == "macos" && has_any(&tokens, &["universal"]);
+    if arches == [arch] || (arches.is_empty() && universal) {
+        score += 10;
+    } else if arches.is_empty() && arch == "x86_64" {
+        // assume that a build without an architecture is for the most common one
+        score += 1;
+    } else {
+        return None;
     }
-}
 
-pub fn release_versus_current<S>(current: S, owner: S, repo: S) -> Option<Release>
-where
-    S: AsRef<str>,
-{
-    let release = match latest_release(owner, repo) {
-        Ok(r) => r,
-        Err(error) => {
-            println!("error: {}", error);
+    if os == "linux" {
+        // prefer static binaries, which work regardless of the system's libc
+        if has_any(&tokens, LIBC_MUSL) {
+            score += 2;
+        } else if !has_any(&tokens, LIBC_GNU) {
+            score += 1;
+        } else if platform.is_musl() {
             return None;
         }
+    }
+
+    let command_tokens = tokenise(&command.to_lowercase());
+    if command_tokens.iter().all(|c| tokens.contains(c)) {
+        score += 2;
+    }
+
+    let rank = match archive {
+        Some(suffix) if os == "windows" => u32::from(*suffix == ".zip"),
+        Some(suffix) => match TARBALL_SUFFIXES.iter().position(|t| t == suffix) {
+            Some(i) => (TARBALL_SUFFIXES.len() - i) as u32,
+            None => 0,
+        },
+        None => 0,
     };
 
-    let c = current.as_ref();
-    let installed = c.trim_start_matches(|c: char| !c.is_digit(10)).trim();
-    let tag_name = release.tag_name.clone();
-    let latest = tag_name
-        .trim_start_matches(|c: char| !c.is_digit(10))
-        .trim();
+    Some((score, rank))
+}
 
-    if installed == latest {
-        None
-    } else {
-        Some(release)
+fn has_any(tokens: &[String], aliases: &[&str]) -> bool {
+    tokens.iter().any(|t| aliases.contains(&t.as_str()))
+}
+
+// matching() returns every group with an alias amongst `tokens`
+fn matching<'g>(tokens: &[String], groups: &[(&'g str, &[&str])]) -> Vec<&'g str> {
+    groups
+        .iter()
+        .filter(|(_, aliases)| has_any(tokens, aliases))
+        .map(|(group, _)| *group)
+        .collect()
+}
+
+fn tokenise(name: &str) -> Vec<String> {
+    let parts: Vec<&str> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|p| !p.is_empty())
+        .collect();
+    let mut tokens = Vec::<String>::new();
+    let mut i = 0;
+    while i < parts.len() {
+        if let Some(next) = parts.get(i + 1) {
+            let joined = format!("{}{}", parts[i], next);
+            if JOINED_ALIASES.contains(&joined.as_str()) {
+                tokens.push(joined);
+                i += 2;
+                continue;
+            }
+        }
+        tokens.push(String::from(parts[i]));
+        i += 1;
     }
+    tokens
 }
 
-fn create_request<S>(url: S) -> Request
+// auth_headers() uses GITHUB_TOKEN for github.com only
+pub fn auth_headers() -> header::HeaderMap {
+    token_headers(std::env::var("GITHUB_TOKEN").ok())
+}
+
+pub fn fetch_releases<S>(owner: S, repo: S) -> Result<Vec<Release>>
 where
     S: AsRef<str>,
 {
-    let mut headers = header::HeaderMap::new();
-
-    match std::env::var("GITHUB_TOKEN") {
-        Ok(token) => {
-            headers.insert(
-                header::AUTHORIZATION,
-                header::HeaderValue::from_str(&format!("token {}", &token))
-                    .expect("Authorization header"),
-            );
-        }
-        Err(_error) => {}
-    };
-
-    utils::http::create_request(url, Some(headers))
+    fetch_releases_from(mirrors::github_api(), auth_headers(), owner, repo)
 }
 
-fn fetch_releases<S>(owner: S, repo: S) -> Result<Vec<Release>>
+// fetch_releases_from() also works for GitHub Enterprise,
+// with an `api` like "https://github.example.com/api/v3"
+pub fn fetch_releases_from<A, S>(
+    api: A,
+    headers: header::HeaderMap,
+    owner: S,
+    repo: S,
+) -> Result<Vec<Release>>
 where
+    A: AsRef<str>,
     S: AsRef<str>,
 {
     let uri = format!(
-        "https://api.github.com/repos/{}/{}/releases",
+        "{}/repos/{}/{}/releases",
+        api.as_ref(),
         owner.as_ref(),
         repo.as_ref(),
     );
-    let req = create_request(uri);
+    let req = utils::http::create_request(uri, Some(headers));
     let mut res = utils::http::fetch_request(req)?;
     let mut body = String::new();
     res.read_to_string(&mut body).unwrap_or_default();
@@ -235,31 +382,213 @@ where
     Ok(releases)
 }
 
-fn parse_releases_json<S>(json: S) -> serde_json::Result<Vec<Release>>
+// latest_stable() expects `releases` to be sorted newest-first
+pub fn latest_stable(releases: Vec<Release>) -> Result<Release> {
+    if releases.is_empty() {
+        return Err(GitHubError::EmptyReleases {});
+    }
+    match releases.into_iter().find(|r| {
+        let name = r.name.clone().unwrap_or_default();
+        !r.draft && !r.prelease && version::is_stable(name.as_str())
+    }) {
+        Some(latest) => Ok(latest),
+        None => Err(GitHubError::ValidReleaseNotFound {}),
+    }
+}
+
+pub fn parse_releases_json<S>(json: S) -> serde_json::Result<Vec<Release>>
 where
     S: AsRef<str>,
 {
     serde_json::from_str(json.as_ref())
 }
 
+// release_versus_current() returns `release` only when it is newer
+pub fn release_versus_current<S>(current: S, release: Release) -> Option<Release>
+where
+    S: AsRef<str>,
+{
+    if version::is_newer(release.tag_name.as_str(), current.as_ref()) {
+        Some(release)
+    } else {
+        None
+    }
+}
+
+// token_headers() uses the "Authorization: token ..." scheme,
+// which GitHub, GitHub Enterprise, and Gitea all accept
+pub fn token_headers(token: Option<String>) -> header::HeaderMap {
+    let mut headers = header::HeaderMap::new();
+
+    if let Some(t) = token {
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_str(&format!("token {}", &t)).expect("Authorization header"),
+        );
+    }
+
+    headers
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, path::PathBuf};
 
+    use crate::utils::platform::{Arch, Libc, Os};
+
     use super::*;
 
+    fn platform(os: Os, arch: Arch) -> Platform {
+        Platform {
+            arch,
+            glibc_version: None,
+            libc: if os == Os::Linux {
+                Libc::Glibc
+            } else {
+                Libc::System
+            },
+            os,
+        }
+    }
+
+    #[test]
+    fn best_asset_for_rust_style_names() {
+        let names = vec![
+            "ripgrep-12.1.1-arm-unknown-linux-gnueabihf.tar.gz",
+            "ripgrep-12.1.1-i686-unknown-linux-musl.tar.gz",
+            "ripgrep-12.1.1-x86_64-apple-darwin.tar.gz",
+            "ripgrep-12.1.1-x86_64-pc-windows-msvc.zip",
+            "ripgrep-12.1.1-x86_64-unknown-linux-gnu.tar.gz",
+            "ripgrep-12.1.1-x86_64-unknown-linux-musl.tar.gz",
+            "ripgrep-12.1.1-x86_64-unknown-linux-musl.tar.gz.sha256",
+            "ripgrep_12.1.1_amd64.deb",
+        ];
+        let best = |os, arch| best_asset(&names, &platform(os, arch), "rg", AssetKind::Archive);
+        assert_eq!(
+            best(Os::Linux, Arch::X86_64).expect("must find"),
+            "ripgrep-12.1.1-x86_64-unknown-linux-musl.tar.gz"
+        );
+        assert_eq!(
+            best(Os::Linux, Arch::X86).expect("must find"),
+            "ripgrep-12.1.1-i686-unknown-linux-musl.tar.gz"
+        );
+        assert_eq!(
+            best(Os::Linux, Arch::Armv7).expect("must find"),
+            "ripgrep-12.1.1-arm-unknown-linux-gnueabihf.tar.gz"
+        );
+        assert_eq!(
+            best(Os::MacOS, Arch::X86_64).expect("must find"),
+            "ripgrep-12.1.1-x86_64-apple-darwin.tar.gz"
+        );
+        assert_eq!(
+            best(Os::Windows, Arch::X86_64).expect("must find"),
+            "ripgrep-12.1.1-x86_64-pc-windows-msvc.zip"
+        );
+        assert!(best(Os::Linux, Arch::Aarch64).is_err());
+
+        let alpine = Platform {
+            libc: Libc::Musl,
+            ..platform(Os::Linux, Arch::Armv7)
+        };
+        assert!(best_asset(&names, &alpine, "rg", AssetKind::Archive).is_err());
+    }
+
+    #[test]
+    fn best_asset_for_binaries() {
+        let jq = vec![
+            "jq-1.6.tar.gz",
+            "jq-linux32",
+            "jq-linux64",
+            "jq-osx-amd64",
+            "jq-win32.exe",
+            "jq-win64.exe",
+        ];
+        let best = |os, arch| best_asset(&jq, &platform(os, arch), "jq", AssetKind::Binary);
+        assert_eq!(
+            best(Os::Linux, Arch::X86_64).expect("must find"),
+            "jq-linux64"
+        );
+        assert_eq!(best(Os::Linux, Arch::X86).expect("must find"), "jq-linux32");
+        assert_eq!(
+            best(Os::MacOS, Arch::X86_64).expect("must find"),
+            "jq-osx-amd64"
+        );
+        assert_eq!(
+            best(Os::Windows, Arch::X86_64).expect("must find"),
+            "jq-win64.exe"
+        );
+
+        let hadolint = vec![
+            "hadolint-Darwin-x86_64",
+            "hadolint-Darwin-x86_64.sha256",
+            "hadolint-Linux-x86_64",
+            "hadolint-Linux-x86_64.sha256",
+            "hadolint-Windows-x86_64.exe",
+        ];
+        assert_eq!(
+            best_asset(
+                &hadolint,
+                &platform(Os::Linux, Arch::X86_64),
+                "hadolint",
+                AssetKind::Binary
+            )
+            .expect("must find"),
+            "hadolint-Linux-x86_64"
+        );
+    }
+
+    #[test]
+    fn best_asset_prefers_archive_format_for_os() {
+        let names = vec![
+            "vale_2.6.1_Linux_64-bit.tar.gz",
+            "vale_2.6.1_Linux_64-bit.tar.xz",
+            "vale_2.6.1_Linux_64-bit.zip",
+            "vale_2.6.1_Windows_64-bit.tar.gz",
+            "vale_2.6.1_Windows_64-bit.zip",
+            "vale_2.6.1_checksums.txt",
+            "vale_2.6.1_macOS_64-bit.tar.gz",
+        ];
+        let best = |os, arch| best_asset(&names, &platform(os, arch), "vale", AssetKind::Archive);
+        assert_eq!(
+            best(Os::Linux, Arch::X86_64).expect("must find"),
+            "vale_2.6.1_Linux_64-bit.tar.xz"
+        );
+        assert_eq!(
+            best(Os::Windows, Arch::X86_64).expect("must find"),
+            "vale_2.6.1_Windows_64-bit.zip"
+        );
+    }
+
+    #[test]
+    fn best_asset_reports_ambiguity() {
+        let names = vec!["tool-linux-amd64", "tool-linux-x86_64"];
+        match best_asset(
+            &names,
+            &platform(Os::Linux, Arch::X86_64),
+            "tool",
+            AssetKind::Binary,
+        ) {
+            Err(GitHubError::AmbiguousAssets(found)) => assert_eq!(found.len(), 2),
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
     #[test]
-    fn fetch_tags_github_hub() {
-        let tags = fetch_tags("github", "hub").expect("must fetch");
-        assert!(!tags.is_empty());
-        let first = tags.into_iter().next().unwrap();
-        assert!(!first.id.is_empty());
-        assert!(!first.url.is_empty());
+    fn tokenise_joins_split_aliases() {
+        assert_eq!(
+            tokenise("vale_2.6.1_linux_64-bit"),
+            vec!["vale", "2", "6", "1", "linux", "64bit"]
+        );
+        assert_eq!(
+            tokenise("x86_64-unknown-linux-musl"),
+            vec!["x8664", "unknown", "linux", "musl"]
+        );
     }
 
     #[test]
     fn latest_release_github_hub() {
-        let release = latest_release("github", "hub").expect("must fetch");
+        let releases = fetch_releases("github", "hub").expect("must fetch");
+        let release = latest_stable(releases).expect("must find");
         assert!(!release.assets.is_empty());
         assert_eq!(release.draft, false);
         assert!(release.name.unwrap_or_default().contains("hub"));
@@ -0,0 +1,160 @@
+use std::io::Read;
+
+use reqwest::header;
+use serde_derive::Deserialize;
+
+use crate::utils::{
+    self,
+    github::{Asset, Release, Result},
+};
+
+#[derive(Debug, Deserialize)]
+struct GitLabAssets {
+    #[serde(default)]
+    links: Vec<GitLabLink>,
+    #[serde(default)]
+    sources: Vec<GitLabSource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabLink {
+    direct_asset_url: Option<String>,
+    name: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabRelease {
+    assets: GitLabAssets,
+    name: Option<String>,
+    #[serde(default)]
+    released_at: String,
+    tag_name: String,
+    #[serde(default)]
+    upcoming_release: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabSource {
+    format: String,
+    url: String,
+}
+
+impl From<GitLabRelease> for Release {
+    fn from(r: GitLabRelease) -> Release {
+        let released_at = r.released_at;
+        Release {
+            assets: r
+                .assets
+                .links
+                .into_iter()
+                .map(|l| Asset {
+                    browser_download_url: l.direct_asset_url.unwrap_or(l.url),
+                    name: l.name,
+                    state: String::from("uploaded"),
+                    updated_at: released_at.clone(),
+                })
+                .collect(),
+            draft: false,
+            name: r.name,
+            prelease: r.upcoming_release,
+            tag_name: r.tag_name,
+            zipball_url: r
+                .assets
+                .sources
+                .into_iter()
+                .find(|s| s.format == "zip")
+                .map(|s| s.url)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn fetch_releases<B, S>(
+    base: B,
+    headers: header::HeaderMap,
+    owner: S,
+    repo: S,
+) -> Result<Vec<Release>>
+where
+    B: AsRef<str>,
+    S: AsRef<str>,
+{
+    let uri = format!(
+        "{}/api/v4/projects/{}/releases",
+        base.as_ref(),
+        project_id(owner, repo),
+    );
+    let req = utils::http::create_request(uri, Some(headers));
+    let mut res = utils::http::fetch_request(req)?;
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap_or_default();
+
+    let releases = match parse_releases_json(&body) {
+        Ok(r) => r,
+        Err(error) => {
+            println!("cannot fetch GitLab releases: {:?}", error);
+            Vec::<Release>::new()
+        }
+    };
+    Ok(releases)
+}
+
+fn parse_releases_json<S>(json: S) -> serde_json::Result<Vec<Release>>
+where
+    S: AsRef<str>,
+{
+    let releases: Vec<GitLabRelease> = serde_json::from_str(json.as_ref())?;
+    Ok(releases.into_iter().map(Release::from).collect())
+}
+
+// project_id() URL-encodes the full path, which may include subgroups
+fn project_id<S>(owner: S, repo: S) -> String
+where
+    S: AsRef<str>,
+{
+    format!("{}/{}", owner.as_ref(), repo.as_ref()).replace('/', "%2F")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::utils::github;
+
+    use super::*;
+
+    #[test]
+    fn parse_releases_json_works() {
+        let fixture_path = PathBuf::from("tests/fixtures/gitlab-releases.json");
+        let fixture = fs::read_to_string(&fixture_path).expect("must read");
+        let releases = parse_releases_json(&fixture).expect("must parse");
+        assert_eq!(releases.len(), 2);
+
+        assert_eq!(releases[0].tag_name, "v0.39.0");
+        assert!(!releases[0].prelease);
+        assert_eq!(
+            releases[0].assets[0].name,
+            "jokeyrhyme-dotfiles-x86_64-unknown-linux-musl"
+        );
+        assert_eq!(releases[0].assets[0].browser_download_url, "https://gitlab.com/jokeyrhyme/dotfiles-rs/-/releases/v0.39.0/downloads/jokeyrhyme-dotfiles-x86_64-unknown-linux-musl");
+        assert!(releases[0].zipball_url.ends_with(".zip"));
+
+        assert!(releases[1].prelease);
+
+        let latest = github::latest_stable(releases).expect("must find");
+        assert_eq!(latest.tag_name, "v0.39.0");
+    }
+
+    #[test]
+    fn project_id_encodes_subgroups() {
+        assert_eq!(
+            project_id("jokeyrhyme", "dotfiles-rs"),
+            "jokeyrhyme%2Fdotfiles-rs"
+        );
+        assert_eq!(
+            project_id("group/subgroup", "tool"),
+            "group%2Fsubgroup%2Ftool"
+        );
+    }
+}
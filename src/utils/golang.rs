@@ -1,18 +1,37 @@
 use std::{
-    env::consts::{ARCH, OS},
-    path::PathBuf,
+    collections::HashSet,
+    env::{self, consts::OS},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
     str,
 };
 
-use crate::lib::{task, version};
-use crate::utils;
+use serde_derive::Deserialize;
 
-pub fn arch() -> &'static str {
-    if ARCH == "x86_64" {
-        "amd64"
-    } else {
-        ARCH
-    }
+use crate::lib::{mirrors, task, version};
+use crate::utils::{
+    self,
+    fs::{delete_empty_ancestors, delete_if_exists, disk_usage, make_writable},
+    platform::Platform,
+};
+
+// Release is an entry in the go.dev download index
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub files: Vec<File>,
+    pub stable: bool,
+    pub version: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct File {
+    pub arch: String,
+    pub filename: String,
+    pub kind: String, // "archive", "installer", or "source"
+    pub os: String,
+    pub sha256: String,
+    pub version: String,
 }
 
 pub fn bin_dir() -> PathBuf {
@@ -20,11 +39,11 @@ pub fn bin_dir() -> PathBuf {
 }
 
 pub fn current_version() -> String {
-    let exe_path = bin_dir().join(if OS == "windows" { "go.exe" } else { "go" });
-    match utils::process::command_output(exe_path.to_str().unwrap(), &["version"]) {
+    match utils::process::command_output(go_exe(), &["version"]) {
         Ok(output) => {
             let stdout = str::from_utf8(&output.stdout).unwrap_or_default().trim();
-            let trailer = format!(" {}/{}", os(), arch());
+            let platform = Platform::current();
+            let trailer = format!(" {}/{}", platform.go_os(), platform.go_arch());
             let headless = str::replace(stdout, "go version ", "");
             str::replace(&headless, &trailer, "")
         }
@@ -32,6 +51,11 @@ pub fn current_version() -> String {
     }
 }
 
+// go_exe() is our `go`, which may not be the first on PATH
+pub fn go_exe() -> PathBuf {
+    bin_dir().join(if OS == "windows" { "go.exe" } else { "go" })
+}
+
 pub fn gopath() -> PathBuf {
     utils::env::home_dir().join("go")
 }
@@ -41,54 +65,395 @@ pub fn goroot() -> PathBuf {
 }
 
 pub fn is_installed() -> bool {
-    let exe_path = bin_dir().join(if OS == "windows" { "go.exe" } else { "go" });
-    exe_path.is_file()
-}
-
-pub fn latest_version() -> Result<String, task::Error> {
-    let tags: Vec<utils::github::Tag> = utils::github::fetch_tags("golang", "go")?;
-    let release_tags: Vec<utils::github::Tag> = tags
-        .into_iter()
-        .filter(|t| {
-            // release tags look like "go1.10.2"
-            // other tags start with "weekly.", or "release.", etc
-            t.id.starts_with("go") && version::is_stable(t.id.as_str())
-        })
+    go_exe().is_file()
+}
+
+// latest_file() is the archive of the newest stable release for `platform` within `spec`,
+// e.g. "1.15" for the latest 1.15.x
+pub fn latest_file(platform: &Platform, spec: Option<&str>) -> io::Result<File> {
+    let releases = fetch_releases()?;
+    match pick_file(&releases, platform, |r| {
+        r.stable && spec.is_none_or(|s| version::matches(s, r.version.as_str()))
+    }) {
+        Some(f) => Ok(f),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no stable Go release for {}-{} within {}",
+                platform.go_os(),
+                platform.go_dl_arch(),
+                spec.unwrap_or("latest")
+            ),
+        )),
+    }
+}
+
+pub fn latest_version(spec: Option<&str>) -> Result<String, task::Error> {
+    Ok(latest_file(Platform::current(), spec)?.version)
+}
+
+// prune_module_cache() deletes versions of modules in `mod_dir` (i.e. $GOPATH/pkg/mod)
+// unless `keep` has them, where `keep` has each escaped "module@version",
+// and returns how many bytes we deleted;
+// unless `all`, we only delete other versions of modules in `keep`,
+// as the rest may be for the user's own projects
+pub fn prune_module_cache(mod_dir: &Path, keep: &HashSet<String>, all: bool) -> io::Result<u64> {
+    let mut reclaimed = 0;
+    let kept_modules: HashSet<&str> = keep
+        .iter()
+        .filter_map(|k| k.rsplit_once('@').map(|(m, _)| m))
         .collect();
+    let prunable = |module: &str| all || kept_modules.contains(module);
+
+    // extracted modules, e.g. pkg/mod/golang.org/x/tools@v0.1.0/
+    let mut roots = Vec::<PathBuf>::new();
+    find_module_roots(mod_dir, &mod_dir.join("cache"), &mut roots)?;
+    for root in roots {
+        let id = relative_slashes(mod_dir, &root);
+        let module = id.rsplit_once('@').map_or(id.as_str(), |(m, _)| m);
+        if prunable(module) && !keep.contains(&id) {
+            reclaimed += delete_module_path(&root, mod_dir)?;
+        }
+    }
+
+    // downloads, e.g. pkg/mod/cache/download/golang.org/x/tools/@v/v0.1.0.zip
+    let download = mod_dir.join("cache").join("download");
+    let mut versions_dirs = Vec::<PathBuf>::new();
+    find_named_dirs(&download, "@v", &mut versions_dirs)?;
+    for versions_dir in versions_dirs {
+        let module_dir = match versions_dir.parent() {
+            Some(m) => m.to_path_buf(),
+            None => continue,
+        };
+        let module = relative_slashes(&download, &module_dir);
+        if !prunable(&module) {
+            continue;
+        }
+        let mut remaining = false;
+        for entry in fs::read_dir(&versions_dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let version = match file_name.rsplit_once('.') {
+                Some((v, "info" | "lock" | "mod" | "zip" | "ziphash")) => v,
+                _ => continue, // e.g. "list" of versions
+            };
+            if keep.contains(&format!("{}@{}", &module, version)) {
+                remaining = true;
+            } else {
+                reclaimed += delete_module_path(&path, &download)?;
+            }
+        }
+        if !remaining {
+            reclaimed += delete_module_path(&module_dir, &download)?;
+        }
+    }
+
+    Ok(reclaimed)
+}
+
+// prune_stale_pkg() deletes packages that older Go versions compiled into `pkg_dir`
+// (i.e. $GOPATH/pkg/linux_amd64 and similar), which modules and GOCACHE replace
+pub fn prune_stale_pkg(pkg_dir: &Path) -> io::Result<u64> {
+    let mut reclaimed = 0;
+    let entries = match fs::read_dir(pkg_dir) {
+        Ok(e) => e,
+        Err(_error) => return Ok(0),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() && name.contains('_') {
+            reclaimed += disk_usage(&path);
+            delete_if_exists(&path);
+        }
+    }
+    Ok(reclaimed)
+}
+
+// proxy_latest() asks the module proxy for the newest version of `module`
+pub fn proxy_latest(module: &str) -> io::Result<String> {
+    #[derive(Deserialize)]
+    struct Info {
+        #[serde(rename = "Version")]
+        version: String,
+    }
+
+    let url = format!(
+        "{}/{}/@latest",
+        pick_proxy(env::var("GOPROXY").ok()),
+        escape_module(module)
+    );
+    let mut res = utils::http::fetch_request(utils::http::create_request(url, None))?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    let info: Info = serde_json::from_str(&body)?;
+    Ok(info.version)
+}
+
+// release_file() is the archive of exactly `version` for `platform`
+pub fn release_file(version: &str, platform: &Platform) -> io::Result<File> {
+    let releases = fetch_releases()?;
+    match pick_file(&releases, platform, |r| r.version == version) {
+        Some(f) => Ok(f),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no Go {} for {}-{}",
+                version,
+                platform.go_os(),
+                platform.go_dl_arch()
+            ),
+        )),
+    }
+}
+
+fn fetch_releases() -> io::Result<Vec<Release>> {
+    let req = utils::http::create_request(mirrors::golang_index(), None);
+    let mut res = utils::http::fetch_request(req)?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+// escape_module() is the "!"-escaping of upper-case letters in proxy URLs,
+// e.g. "github.com/Azure/azure-sdk" becomes "github.com/!azure/azure-sdk"
+pub fn escape_module(module: &str) -> String {
+    let mut escaped = String::new();
+    for c in module.chars() {
+        if c.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(c.to_ascii_lowercase());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// pick_proxy() is the first URL in GOPROXY, skipping "direct" and "off"
+fn pick_proxy(goproxy: Option<String>) -> String {
+    goproxy
+        .unwrap_or_default()
+        .split([',', '|'])
+        .map(str::trim)
+        .find(|p| p.contains("://"))
+        .unwrap_or("https://proxy.golang.org")
+        .trim_end_matches('/')
+        .to_string()
+}
 
-    if release_tags.is_empty() {
-        return Err(task::Error::NoTags {});
+// delete_module_path() copes with the read-only directories in the module cache
+fn delete_module_path(path: &Path, root: &Path) -> io::Result<u64> {
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(0); // e.g. already empty, so already deleted
     }
-    match release_tags.last() {
-        Some(latest) => Ok(latest.clone().id),
-        None => Err(task::Error::NoTags {}),
+    let size = disk_usage(path);
+    make_writable(path)?;
+    delete_if_exists(path);
+    delete_empty_ancestors(path, root);
+    Ok(size)
+}
+
+// find_module_roots() finds the directories with "@" in their name,
+// without looking within them, or within `skip` (i.e. the download cache)
+fn find_module_roots(dir: &Path, skip: &Path, roots: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_error) => return Ok(()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().contains('@') {
+            roots.push(path);
+        } else if path != skip {
+            find_module_roots(&path, skip, roots)?;
+        }
     }
+    Ok(())
 }
 
-pub fn os() -> &'static str {
-    if OS == "macos" {
-        "darwin"
-    } else {
-        OS
+fn find_named_dirs(dir: &Path, name: &str, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_error) => return Ok(()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if entry.file_name() == name {
+            found.push(entry.path());
+        } else {
+            find_named_dirs(&entry.path(), name, found)?;
+        }
     }
+    Ok(())
+}
+
+fn relative_slashes(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// pick_file() finds the archive for `platform` in the newest release that `filter` accepts,
+// skipping releases without one, e.g. no darwin-386 after Go 1.14
+fn pick_file<F>(releases: &[Release], platform: &Platform, filter: F) -> Option<File>
+where
+    F: Fn(&Release) -> bool,
+{
+    releases
+        .iter()
+        .filter(|r| filter(r))
+        .filter_map(|r| {
+            r.files
+                .iter()
+                .find(|f| {
+                    f.kind == "archive"
+                        && f.os == platform.go_os()
+                        && f.arch == platform.go_dl_arch()
+                })
+                .map(|f| (version::Version::parse(&r.version), f))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, f)| f.clone())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::utils::platform::{Arch, Libc, Os};
+
     use super::*;
 
     #[test]
-    fn arch_is_not_x86_64() {
-        assert_ne!(arch(), "x86_64");
+    fn pick_file_finds_newest_stable_archive() {
+        let releases: Vec<Release> = serde_json::from_str(
+            r#"[
+                {"version":"go1.16beta1","stable":false,"files":[
+                    {"filename":"go1.16beta1.linux-amd64.tar.gz","os":"linux","arch":"amd64","version":"go1.16beta1","sha256":"aa","size":1,"kind":"archive"}
+                ]},
+                {"version":"go1.14.12","stable":true,"files":[
+                    {"filename":"go1.14.12.linux-amd64.tar.gz","os":"linux","arch":"amd64","version":"go1.14.12","sha256":"bb","size":1,"kind":"archive"}
+                ]},
+                {"version":"go1.15.5","stable":true,"files":[
+                    {"filename":"go1.15.5.src.tar.gz","os":"","arch":"","version":"go1.15.5","sha256":"cc","size":1,"kind":"source"},
+                    {"filename":"go1.15.5.linux-amd64.tar.gz","os":"linux","arch":"amd64","version":"go1.15.5","sha256":"dd","size":1,"kind":"archive"},
+                    {"filename":"go1.15.5.windows-amd64.msi","os":"windows","arch":"amd64","version":"go1.15.5","sha256":"ee","size":1,"kind":"installer"},
+                    {"filename":"go1.15.5.windows-amd64.zip","os":"windows","arch":"amd64","version":"go1.15.5","sha256":"ff","size":1,"kind":"archive"}
+                ]}
+            ]"#,
+        )
+        .expect("must parse");
+
+        let linux = Platform {
+            arch: Arch::X86_64,
+            glibc_version: None,
+            libc: Libc::Glibc,
+            os: Os::Linux,
+        };
+        let windows = Platform {
+            libc: Libc::System,
+            os: Os::Windows,
+            ..linux.clone()
+        };
+        let stable = |r: &Release| r.stable;
+
+        let got = pick_file(&releases, &linux, stable).expect("must find");
+        assert_eq!(got.filename, "go1.15.5.linux-amd64.tar.gz");
+        assert_eq!(got.sha256, "dd");
+        let got = pick_file(&releases, &windows, stable).expect("must find");
+        assert_eq!(got.filename, "go1.15.5.windows-amd64.zip");
+        let got = pick_file(&releases, &linux, |r| {
+            r.stable && version::matches("1.14", r.version.as_str())
+        })
+        .expect("must find");
+        assert_eq!(got.version, "go1.14.12");
+        assert!(pick_file(&releases, &windows, |r| r.version == "go1.14.12").is_none());
     }
 
     #[test]
-    fn latest_version_found() {
-        assert!(!latest_version().unwrap().is_empty());
+    fn prune_module_cache_keeps_needed_modules() {
+        let gopath = utils::fs::mkdtemp().unwrap();
+        let pkg = gopath.join("pkg");
+        let mod_dir = pkg.join("mod");
+        for f in &[
+            "linux_amd64/github.com/old/thing.a",
+            "sumdb/sum.golang.org/latest",
+            "mod/golang.org/x/tools@v0.1.0/go.mod",
+            "mod/golang.org/x/tools@v0.0.1/go.mod",
+            "mod/github.com/!azure/sdk@v1.0.0/go.mod",
+            "mod/cache/download/golang.org/x/tools/@v/list",
+            "mod/cache/download/golang.org/x/tools/@v/v0.1.0.zip",
+            "mod/cache/download/golang.org/x/tools/@v/v0.0.1.zip",
+            "mod/cache/download/github.com/!azure/sdk/@v/v1.0.0.zip",
+        ] {
+            let p = pkg.join(f);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(&p, "1234").unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // like the Go module cache
+            let old = mod_dir.join("golang.org/x/tools@v0.0.1");
+            fs::set_permissions(&old, fs::Permissions::from_mode(0o555)).unwrap();
+        }
+
+        assert_eq!(prune_stale_pkg(&pkg).unwrap(), 4);
+        assert!(!pkg.join("linux_amd64").exists());
+        assert!(pkg.join("sumdb").exists());
+
+        let keep: HashSet<String> = [String::from("golang.org/x/tools@v0.1.0")]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(prune_module_cache(&mod_dir, &keep, false).unwrap(), 8);
+        assert!(mod_dir.join("golang.org/x/tools@v0.1.0/go.mod").is_file());
+        assert!(!mod_dir.join("golang.org/x/tools@v0.0.1").exists());
+        let download = mod_dir.join("cache/download");
+        assert!(download.join("golang.org/x/tools/@v/v0.1.0.zip").is_file());
+        assert!(!download.join("golang.org/x/tools/@v/v0.0.1.zip").exists());
+        // e.g. for the user's own projects
+        assert!(mod_dir
+            .join("github.com/!azure/sdk@v1.0.0/go.mod")
+            .is_file());
+        assert!(download
+            .join("github.com/!azure/sdk/@v/v1.0.0.zip")
+            .is_file());
+
+        assert_eq!(prune_module_cache(&mod_dir, &keep, true).unwrap(), 8);
+        assert!(mod_dir.join("golang.org/x/tools@v0.1.0/go.mod").is_file());
+        assert!(!mod_dir.join("github.com").exists());
+        assert!(!download.join("github.com").exists());
+
+        utils::fs::delete_if_exists(&gopath);
     }
 
     #[test]
-    fn os_is_not_macos() {
-        assert_ne!(os(), "macos");
+    fn proxy_urls() {
+        assert_eq!(
+            escape_module("github.com/Azure/azure-sdk"),
+            "github.com/!azure/azure-sdk"
+        );
+        assert_eq!(pick_proxy(None), "https://proxy.golang.org");
+        assert_eq!(
+            pick_proxy(Some(String::from(
+                "direct,https://goproxy.example.com/|off"
+            ))),
+            "https://goproxy.example.com"
+        );
+    }
+
+    #[test]
+    fn latest_version_found() {
+        assert!(!latest_version(None).unwrap().is_empty());
     }
 }
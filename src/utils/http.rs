@@ -1,26 +1,47 @@
-use std::fs::{create_dir_all, File};
-use std::io::{self, Read};
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{thread, time};
 
 use chrono::{offset::Utc, Duration};
 pub use reqwest::blocking::{Request, Response};
-use reqwest::{blocking::Client, header, Url};
+use reqwest::{blocking::Client, header, StatusCode, Url};
 
 use crate::lib::cache;
+use crate::utils::progress::Progress;
+
+const DOWNLOAD_ATTEMPTS: u32 = 4;
+const DOWNLOAD_TIMEOUT_SECS: u64 = 10 * 60;
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
 
 pub fn create_request<S>(url: S, headers: Option<header::HeaderMap>) -> Request
 where
     S: AsRef<str>,
 {
-    create_client()
-        .get(Url::parse(url.as_ref()).expect("parse URL"))
+    let u = Url::parse(url.as_ref()).expect("parse URL");
+    // reqwest refuses to build requests for `file://` URLs,
+    // so we build with a placeholder and swap the URL back in afterwards,
+    // as fetch_request() never sends these over the network anyway
+    let is_file = u.scheme() == "file";
+    let mut req = create_client()
+        .get(if is_file {
+            Url::parse("http://localhost/").expect("parse URL")
+        } else {
+            u.clone()
+        })
         .header("User-Agent", user_agent())
         .headers(match headers {
             Some(h) => h,
             None => header::HeaderMap::new(),
         })
         .build()
-        .expect("new HTTP(S) request")
+        .expect("new HTTP(S) request");
+    if is_file {
+        *req.url_mut() = u;
+    }
+    req
 }
 
 pub fn download<P, S>(url: S, dest: P) -> io::Result<()>
@@ -37,27 +58,36 @@ pub fn download_request<P>(req: Request, dest: P) -> io::Result<()>
 where
     P: AsRef<Path>,
 {
-    let mut res = fetch_request(req)?;
-
     let d = dest.as_ref();
     if let Some(parent) = d.parent() {
         create_dir_all(&parent)?;
     };
 
-    let mut file = File::create(d)?;
-    match io::copy(&mut res, &mut file) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))),
+    let url = req.url().clone();
+    if url.scheme() == "file" {
+        return copy_to_file(&mut open_file_url(&url)?, d);
     }
+    if let Some(cached) = from_cache(&url, is_offline()) {
+        return copy_to_file(&mut cached?, d);
+    }
+    // proceed with fresh HTTP request
+
+    with_retries(&url, || match req.try_clone() {
+        Some(r) => resume_download(r),
+        None => Err(io::Error::other("cannot clone request")),
+    })?;
+
+    cache::store_partial(&url)?;
+    copy_to_file(&mut cache::load_response_body(&url)?, d)
 }
 
 pub fn fetch_request(req: Request) -> io::Result<impl Read> {
     let url = req.url().clone();
-    if let Ok(rm) = cache::load_response_metadata(&url) {
-        let a_while_ago = Utc::now() - Duration::minutes(15);
-        if rm.date > a_while_ago {
-            return cache::load_response_body(&url);
-        }
+    if url.scheme() == "file" {
+        return open_file_url(&url);
+    }
+    if let Some(cached) = from_cache(&url, is_offline()) {
+        return cached;
     }
     // proceed with fresh HTTP request
 
@@ -79,6 +109,109 @@ pub fn fetch_request(req: Request) -> io::Result<impl Read> {
     }
 }
 
+pub fn stream<S>(url: S) -> io::Result<Box<dyn Read>>
+where
+    S: AsRef<str>,
+{
+    stream_request(create_request(url, None))
+}
+
+// stream_request() is for large files that we process as they arrive,
+// so we neither write them to disk first nor store them in our cache,
+// and resumes with a Range request if the connection drops part way through
+pub fn stream_request(req: Request) -> io::Result<Box<dyn Read>> {
+    let url = req.url().clone();
+    if url.scheme() == "file" {
+        return Ok(Box::new(open_file_url(&url)?));
+    }
+    if let Some(cached) = from_cache(&url, is_offline()) {
+        return Ok(Box::new(cached?));
+    }
+    // proceed with fresh HTTP request
+
+    // we can only retry until we hand the response to our caller
+    let res = with_retries(&url, || {
+        let r = match req.try_clone() {
+            Some(r) => r,
+            None => return Err(io::Error::other("cannot clone request")),
+        };
+        let res = match create_download_client().execute(r) {
+            Ok(r) => r,
+            Err(e) => return Err(io::Error::other(format!("{:?}", e))),
+        };
+        if res.status().is_success() {
+            Ok(res)
+        } else {
+            println!("{:?} GET {}", &res.version(), &res.url());
+            Err(status_error(res.status()))
+        }
+    })?;
+
+    let total = res.content_length();
+    let progress = Progress::new(label(&url), 0, total);
+    let reopen = move |offset: u64| -> io::Result<(Box<dyn Read>, u64)> {
+        let mut r = match req.try_clone() {
+            Some(r) => r,
+            None => return Err(io::Error::other("cannot clone request")),
+        };
+        r.headers_mut().insert(
+            header::RANGE,
+            header::HeaderValue::from_str(&format!("bytes={}-", offset)).expect("Range header"),
+        );
+        let res = match create_download_client().execute(r) {
+            Ok(r) => r,
+            Err(e) => return Err(io::Error::other(format!("{:?}", e))),
+        };
+        match res.status() {
+            StatusCode::PARTIAL_CONTENT => Ok((Box::new(res), offset)),
+            // the server may ignore our Range, so we start over
+            status if status.is_success() => Ok((Box::new(res), 0)),
+            status => Err(status_error(status)),
+        }
+    };
+    Ok(Box::new(ProgressReader {
+        finished: false,
+        inner: ResumingReader {
+            attempts: 0,
+            backoff: time::Duration::from_secs(1),
+            inner: Box::new(res),
+            offset: 0,
+            reopen,
+            total,
+            url,
+        },
+        progress,
+    }))
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+// set_offline() makes fetch_request() use only cached responses,
+// regardless of their age, and never touch the network
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+fn content_range_total(headers: &header::HeaderMap) -> Option<u64> {
+    // e.g. "Content-Range: bytes 100-999/1000"
+    let value = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (_, total) = value.rsplit_once('/')?;
+    total.trim().parse().ok()
+}
+
+fn copy_to_file<R>(source: &mut R, dest: &Path) -> io::Result<()>
+where
+    R: Read,
+{
+    let mut file = File::create(dest)?;
+    match io::copy(source, &mut file) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))),
+    }
+}
+
 fn create_client() -> Client {
     let mut headers = header::HeaderMap::new();
     headers.insert(header::USER_AGENT, user_agent());
@@ -88,6 +221,171 @@ fn create_client() -> Client {
         .expect("new HTTP(S) client")
 }
 
+// create_download_client() allows more time than usual for large files
+fn create_download_client() -> Client {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(header::USER_AGENT, user_agent());
+    Client::builder()
+        .default_headers(headers)
+        .connect_timeout(time::Duration::from_secs(30))
+        .timeout(time::Duration::from_secs(DOWNLOAD_TIMEOUT_SECS))
+        .build()
+        .expect("new HTTP(S) client")
+}
+
+// from_cache() returns `None` when we should proceed with a fresh HTTP request
+fn from_cache(url: &Url, offline: bool) -> Option<io::Result<File>> {
+    if let Ok(rm) = cache::load_response_metadata(url) {
+        let a_while_ago = Utc::now() - Duration::minutes(15);
+        if offline || rm.date > a_while_ago {
+            return Some(cache::load_response_body(url));
+        }
+    }
+    if offline {
+        return Some(Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("offline, and no cached response for {}", url),
+        )));
+    }
+    None
+}
+
+fn label(url: &Url) -> &str {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+}
+
+// open_file_url() supports mirrors that are just local directories
+fn open_file_url(url: &Url) -> io::Result<File> {
+    match url.to_file_path() {
+        Ok(p) => File::open(p),
+        Err(()) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot convert {} to a local path", url),
+        )),
+    }
+}
+
+fn is_retryable(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+    )
+}
+
+// resume_download() continues from cache::partial_path(),
+// and fails unless we end up with exactly Content-Length bytes
+fn resume_download(mut req: Request) -> io::Result<()> {
+    let url = req.url().clone();
+    let partial = cache::partial_path(&url);
+    if let Some(parent) = partial.parent() {
+        create_dir_all(parent)?;
+    };
+
+    let existing = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+    if existing > 0 {
+        req.headers_mut().insert(
+            header::RANGE,
+            header::HeaderValue::from_str(&format!("bytes={}-", existing)).expect("Range header"),
+        );
+    }
+
+    let mut res = match create_download_client().execute(req) {
+        Ok(r) => r,
+        Err(e) => return Err(io::Error::other(format!("{:?}", e))),
+    };
+
+    let status = res.status();
+    let (mut file, done, total) = if status == StatusCode::PARTIAL_CONTENT {
+        let total = content_range_total(res.headers())
+            .or_else(|| res.content_length().map(|l| l + existing));
+        (
+            OpenOptions::new().append(true).open(&partial)?,
+            existing,
+            total,
+        )
+    } else if status.is_success() {
+        // the server may ignore our Range, so we start over
+        (File::create(&partial)?, 0, res.content_length())
+    } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        fs::remove_file(&partial)?;
+        return Err(io::Error::other("cannot resume partial download"));
+    } else {
+        println!("{:?} GET {}", &res.version(), &res.url());
+        return Err(status_error(status));
+    };
+
+    let mut progress = Progress::new(label(&url), done, total);
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let n = match res.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                progress.finish();
+                return Err(e);
+            }
+        };
+        file.write_all(&buffer[..n])?;
+        progress.add(n as u64);
+    }
+    progress.finish();
+    file.flush()?;
+
+    let got = fs::metadata(&partial)?.len();
+    match total {
+        Some(want) if got != want => {
+            if got > want {
+                fs::remove_file(&partial)?;
+            }
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("expected {} bytes, but got {}", want, got),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn status_error(status: StatusCode) -> io::Error {
+    let kind = match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => io::ErrorKind::NotFound,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("non-success: {}", status))
+}
+
+// with_retries() backs off exponentially between attempts,
+// but gives up straight away on errors that retrying will not fix
+fn with_retries<F, T>(url: &Url, mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(t) => return Ok(t),
+            Err(error) => {
+                attempt += 1;
+                if attempt >= DOWNLOAD_ATTEMPTS || !is_retryable(&error) {
+                    return Err(error);
+                }
+                let backoff = time::Duration::from_secs(1 << attempt);
+                println!(
+                    "warning: GET {}: {}, retrying in {}s",
+                    url,
+                    error,
+                    backoff.as_secs()
+                );
+                thread::sleep(backoff);
+            }
+        }
+    }
+}
+
 fn user_agent() -> header::HeaderValue {
     header::HeaderValue::from_str(&format!(
         "rust crate {} {}",
@@ -97,6 +395,116 @@ fn user_agent() -> header::HeaderValue {
     .expect("User-Agent header")
 }
 
+// ResumingReader reissues a request from where we got to when a response fails part way,
+// with `reopen` returning the new response and the offset that it starts from
+struct ResumingReader<F> {
+    attempts: u32,
+    backoff: time::Duration,
+    inner: Box<dyn Read>,
+    offset: u64,
+    reopen: F,
+    total: Option<u64>,
+    url: Url,
+}
+
+impl<F> ResumingReader<F>
+where
+    F: FnMut(u64) -> io::Result<(Box<dyn Read>, u64)>,
+{
+    fn resume(&mut self, mut error: io::Error) -> io::Result<()> {
+        loop {
+            self.attempts += 1;
+            if self.attempts >= DOWNLOAD_ATTEMPTS || !is_retryable(&error) {
+                return Err(error);
+            }
+            let backoff = self.backoff * (1 << self.attempts);
+            println!(
+                "warning: GET {}: {}, resuming from byte {} in {}s",
+                self.url,
+                error,
+                self.offset,
+                backoff.as_secs()
+            );
+            thread::sleep(backoff);
+
+            match (self.reopen)(self.offset) {
+                Ok((mut inner, start)) => {
+                    // skip what our caller already has, which may also fail part way
+                    let skip = self.offset - start;
+                    match io::copy(&mut (&mut inner).take(skip), &mut io::sink()) {
+                        Ok(n) if n == skip => {
+                            self.inner = inner;
+                            return Ok(());
+                        }
+                        Ok(n) => {
+                            error = io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                format!("expected to skip {} bytes, but got {}", skip, n),
+                            )
+                        }
+                        Err(e) => error = e,
+                    }
+                }
+                Err(e) => error = e,
+            }
+        }
+    }
+}
+
+impl<F> Read for ResumingReader<F>
+where
+    F: FnMut(u64) -> io::Result<(Box<dyn Read>, u64)>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let error = match self.inner.read(buf) {
+                Ok(0) if self.total.is_some_and(|t| self.offset < t) => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "expected {} bytes, but got {}",
+                        self.total.unwrap_or_default(),
+                        self.offset
+                    ),
+                ),
+                Ok(n) => {
+                    self.offset += n as u64;
+                    // each drop gets its own attempts, however many came before
+                    if n > 0 {
+                        self.attempts = 0;
+                    }
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+            self.resume(error)?;
+        }
+    }
+}
+
+// ProgressReader draws progress as our caller consumes a response
+struct ProgressReader<R> {
+    finished: bool,
+    inner: R,
+    progress: Progress,
+}
+
+impl<R> Read for ProgressReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.progress.add(n as u64);
+        } else if !self.finished {
+            self.finished = true;
+            self.progress.finish();
+        }
+        Ok(n)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,4 +517,160 @@ mod tests {
         res.read_to_string(&mut body).unwrap();
         assert!(body.contains("dotfiles-rs"));
     }
+
+    #[test]
+    fn content_range_total_parses_header() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(content_range_total(&headers), None);
+        headers.insert(
+            header::CONTENT_RANGE,
+            header::HeaderValue::from_static("bytes 100-999/1000"),
+        );
+        assert_eq!(content_range_total(&headers), Some(1000));
+        headers.insert(
+            header::CONTENT_RANGE,
+            header::HeaderValue::from_static("bytes 100-999/*"),
+        );
+        assert_eq!(content_range_total(&headers), None);
+    }
+
+    #[test]
+    fn download_file_url() {
+        let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.gz");
+        let url = Url::from_file_path(&fixture_path).expect("must convert");
+        let temp_path = crate::utils::fs::mkftemp().unwrap();
+        download(url.as_str(), &temp_path).expect("must download");
+        assert_eq!(
+            fs::read(&temp_path).unwrap(),
+            fs::read(&fixture_path).unwrap()
+        );
+        crate::utils::fs::delete_if_exists(&temp_path);
+    }
+
+    #[test]
+    fn fetch_request_file_url() {
+        let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.tar");
+        let url = Url::from_file_path(&fixture_path).expect("must convert");
+        let req = create_request(url.as_str(), None);
+        let mut res = fetch_request(req).expect("must open");
+        let mut body = Vec::<u8>::new();
+        res.read_to_end(&mut body).unwrap();
+        assert_eq!(body, fs::read(&fixture_path).unwrap());
+    }
+
+    #[test]
+    fn stream_file_url() {
+        let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/foo.txt.tar");
+        let url = Url::from_file_path(&fixture_path).expect("must convert");
+        let mut res = stream(url.as_str()).expect("must open");
+        let mut body = Vec::<u8>::new();
+        res.read_to_end(&mut body).unwrap();
+        assert_eq!(body, fs::read(&fixture_path).unwrap());
+    }
+
+    // FlakyReader fails with a reset connection after `fail_after` bytes
+    struct FlakyReader {
+        data: io::Cursor<Vec<u8>>,
+        fail_after: usize,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_after == 0 {
+                return Err(io::Error::from(io::ErrorKind::ConnectionReset));
+            }
+            let len = buf.len().min(self.fail_after);
+            let n = self.data.read(&mut buf[..len])?;
+            self.fail_after -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn resuming_reader_continues_after_drops() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let flaky = |from: usize, fail_after: usize| -> Box<dyn Read> {
+            Box::new(FlakyReader {
+                data: io::Cursor::new(data[from..].to_vec()),
+                fail_after,
+            })
+        };
+        let resuming = |honour_range: bool, fail_after: usize| ResumingReader {
+            attempts: 0,
+            backoff: time::Duration::from_secs(0),
+            inner: flaky(0, 3000),
+            offset: 0,
+            reopen: move |offset: u64| {
+                let from = if honour_range { offset } else { 0 };
+                Ok((flaky(from as usize, fail_after), from))
+            },
+            total: Some(data.len() as u64),
+            url: Url::parse("https://example.com/tool.tar.gz").unwrap(),
+        };
+
+        // without Range, we skip what we already had
+        for (honour_range, fail_after) in [(true, 4000), (false, usize::MAX)] {
+            let mut body = Vec::<u8>::new();
+            resuming(honour_range, fail_after)
+                .read_to_end(&mut body)
+                .expect("must resume");
+            assert_eq!(body, data);
+        }
+
+        // more drops than DOWNLOAD_ATTEMPTS, each of which recovers
+        let mut body = Vec::<u8>::new();
+        resuming(true, 1500)
+            .read_to_end(&mut body)
+            .expect("must resume each drop");
+        assert_eq!(body, data);
+
+        // without Range, the skip itself drops once
+        let mut reopened = 0;
+        let mut body = Vec::<u8>::new();
+        ResumingReader {
+            attempts: 0,
+            backoff: time::Duration::from_secs(0),
+            inner: flaky(0, 3000),
+            offset: 0,
+            reopen: |_offset: u64| {
+                reopened += 1;
+                let fail_after = if reopened == 1 { 1000 } else { usize::MAX };
+                Ok((flaky(0, fail_after), 0))
+            },
+            total: Some(data.len() as u64),
+            url: Url::parse("https://example.com/tool.tar.gz").unwrap(),
+        }
+        .read_to_end(&mut body)
+        .expect("must retry the skip");
+        assert_eq!(body, data);
+        assert_eq!(reopened, 2);
+
+        let mut body = Vec::<u8>::new();
+        let error = resuming(true, 0)
+            .read_to_end(&mut body)
+            .expect_err("must give up");
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(body.len(), 3000);
+    }
+
+    #[test]
+    fn is_retryable_except_for_client_errors() {
+        assert!(is_retryable(&status_error(StatusCode::BAD_GATEWAY)));
+        assert!(is_retryable(&io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            ""
+        )));
+        assert!(!is_retryable(&status_error(StatusCode::NOT_FOUND)));
+        assert!(!is_retryable(&status_error(StatusCode::FORBIDDEN)));
+    }
+
+    #[test]
+    fn from_cache_offline_fails_fast_when_not_cached() {
+        let url = Url::parse("https://example.com/never/fetched/by/dotfiles-rs").unwrap();
+        match from_cache(&url, true) {
+            Some(Err(error)) => assert_eq!(error.kind(), io::ErrorKind::NotFound),
+            _ => panic!("expected NotFound error"),
+        }
+        assert!(from_cache(&url, false).is_none());
+    }
 }
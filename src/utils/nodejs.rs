@@ -1,25 +1,54 @@
 use std::{
-    env::consts::{ARCH, OS},
+    env::consts::OS,
     io::{self, Read},
     path::PathBuf,
+    sync::OnceLock,
 };
 
 use serde_derive::Deserialize;
 
-use crate::{lib::version, utils};
-
-const DIST_JSON_URL: &str = "https://nodejs.org/dist/index.json";
+use crate::{
+    lib::{mirrors, version},
+    utils::{self, platform::Platform},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Release {
     pub files: Vec<String>,
+    #[serde(default)]
+    pub lts: Lts,
     pub version: String,
 }
 
-pub fn arch() -> &'static str {
-    match ARCH {
-        "x86_64" => "x64",
-        _ => ARCH,
+// Lts is `false` in index.json for Current releases,
+// and otherwise the codename of the LTS line, e.g. "Erbium"
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Lts {
+    Codename(String),
+    No(bool),
+}
+
+impl Default for Lts {
+    fn default() -> Lts {
+        Lts::No(false)
+    }
+}
+
+impl Release {
+    // is_on() is true when this release is on `channel`:
+    // "current" (any release), "lts" (any LTS release),
+    // an LTS codename like "erbium", or a version like "12"
+    pub fn is_on(&self, channel: &str) -> bool {
+        if is_version_channel(channel) {
+            return matches_version(channel, self.version.as_str());
+        }
+        match &self.lts {
+            Lts::Codename(codename) => {
+                channel.eq_ignore_ascii_case("lts") || channel.eq_ignore_ascii_case(codename)
+            }
+            Lts::No(_) => false,
+        }
     }
 }
 
@@ -31,6 +60,30 @@ pub fn bin_dir() -> PathBuf {
     }
 }
 
+// checksum() is the SHA-256 of `file_name`,
+// as published in the SHASUMS256.txt for `version`
+pub fn checksum(platform: &Platform, version: &str, file_name: &str) -> io::Result<String> {
+    let req = utils::http::create_request(
+        format!(
+            "{}/{}/SHASUMS256.txt",
+            mirrors::nodejs_dist(platform),
+            version
+        ),
+        None,
+    );
+    let mut res = utils::http::fetch_request(req)?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+
+    match parse_shasums(&body, file_name) {
+        Some(sum) => Ok(sum),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no checksum for {}", file_name),
+        )),
+    }
+}
+
 pub fn current_version() -> String {
     match utils::process::command_output("node", &["--version"]) {
         Ok(output) => String::from(
@@ -79,31 +132,62 @@ pub fn install_path() -> PathBuf {
     utils::env::home_dir().join(".local").join("node")
 }
 
-pub fn latest_version() -> io::Result<String> {
-    let req = utils::http::create_request(DIST_JSON_URL, None);
-    let mut res = utils::http::fetch_request(req)?;
-    let mut body = String::new();
-    res.read_to_string(&mut body)?;
-    let releases: Vec<Release> = serde_json::from_str(&body)?;
+// latest_version() is the newest stable release for `platform` on `channel`,
+// see Release::is_on()
+pub fn latest_version(platform: &Platform, channel: Option<&str>) -> io::Result<String> {
+    let releases = fetch_releases(platform)?;
 
-    let latest_release: &Release = releases
-        .iter()
-        .find(|r| {
-            version::is_stable(r.version.as_str())
-                && !r.files.is_empty()
-                && r.files.iter().any(|f| {
-                    f.starts_with(&format!(
-                        "{}-{}",
-                        utils::nodejs::release_os(),
-                        utils::nodejs::arch()
-                    ))
-                })
-        })
-        .unwrap();
+    let file = format!(
+        "{}-{}{}",
+        platform.node_release_os(),
+        platform.node_arch(),
+        if platform.is_musl() { "-musl" } else { "" }
+    );
+    let latest_release: &Release = match releases.iter().find(|r| {
+        version::is_stable(r.version.as_str())
+            && channel.is_none_or(|c| r.is_on(c))
+            && !r.files.is_empty()
+            && r.files.iter().any(|f| f.starts_with(&file))
+    }) {
+        Some(r) => r,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no Node.js release for {} on {}",
+                    &file,
+                    channel.unwrap_or("current")
+                ),
+            ));
+        }
+    };
 
     Ok(String::from(latest_release.version.as_str().trim()))
 }
 
+// matches_channel() is true when the installed `version` is on `channel`,
+// and only needs index.json for "lts" and codenames
+pub fn matches_channel(channel: &str, version: &str) -> bool {
+    static RELEASES: OnceLock<Vec<Release>> = OnceLock::new();
+
+    if is_version_channel(channel) {
+        return matches_version(channel, version);
+    }
+    let releases = RELEASES.get_or_init(|| match fetch_releases(Platform::current()) {
+        Ok(r) => r,
+        Err(error) => {
+            println!(
+                "warning: nodejs: unable to check release channels: {}",
+                error
+            );
+            Vec::new()
+        }
+    });
+    releases
+        .iter()
+        .any(|r| r.version == version && r.is_on(channel))
+}
+
 pub fn lib_dir() -> PathBuf {
     if OS == "windows" {
         install_path()
@@ -112,20 +196,36 @@ pub fn lib_dir() -> PathBuf {
     }
 }
 
-pub fn os() -> &'static str {
-    match OS {
-        "macos" => "darwin",
-        "windows" => "win",
-        _ => OS,
-    }
+fn fetch_releases(platform: &Platform) -> io::Result<Vec<Release>> {
+    let req = utils::http::create_request(
+        format!("{}/index.json", mirrors::nodejs_dist(platform)),
+        None,
+    );
+    let mut res = utils::http::fetch_request(req)?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    Ok(serde_json::from_str(&body)?)
 }
 
-// the OS information in the release JSON is a bit different again :shrug:
-pub fn release_os() -> &'static str {
-    match OS {
-        "macos" => "osx",
-        _ => os(),
-    }
+// is_version_channel() is true for "current", and for versions like "12",
+// which we can match without index.json
+fn is_version_channel(channel: &str) -> bool {
+    channel.eq_ignore_ascii_case("current") || version::Version::parse(channel).is_some()
+}
+
+fn matches_version(channel: &str, version: &str) -> bool {
+    channel.eq_ignore_ascii_case("current") || version::matches(channel, version)
+}
+
+// e.g. "<sha256>  node-v14.15.0-linux-x64.tar.xz"
+fn parse_shasums(text: &str, file_name: &str) -> Option<String> {
+    text.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(sum), Some(name)) if name == file_name => Some(String::from(sum)),
+            _ => None,
+        }
+    })
 }
 
 #[cfg(test)]
@@ -133,19 +233,50 @@ mod tests {
     use super::*;
 
     #[test]
-    fn arch_is_not_x86_64() {
-        assert_ne!(arch(), "x86_64");
+    fn parse_shasums_finds_file() {
+        let text = "\
+aaaa  node-v14.15.0-darwin-x64.tar.gz
+bbbb  node-v14.15.0-linux-x64.tar.xz
+cccc  node-v14.15.0-linux-x64.tar.xz.sig
+";
+        assert_eq!(
+            parse_shasums(text, "node-v14.15.0-linux-x64.tar.xz"),
+            Some(String::from("bbbb"))
+        );
+        assert_eq!(parse_shasums(text, "node-v14.15.0-win-x64.zip"), None);
     }
 
     #[test]
-    fn latest_version_found() {
-        let version = latest_version().expect("must fetch");
-        assert!(version.starts_with('v'));
+    fn release_is_on_channel() {
+        let releases: Vec<Release> = serde_json::from_str(
+            r#"[
+                {"version":"v15.2.1","files":["linux-x64"],"lts":false},
+                {"version":"v14.15.1","files":["linux-x64"],"lts":"Fermium"},
+                {"version":"v12.19.1","files":["linux-x64"],"lts":"Erbium"},
+                {"version":"v0.12.18","files":["linux-x64"]}
+            ]"#,
+        )
+        .expect("must parse");
+        assert_eq!(releases[1].lts, Lts::Codename(String::from("Fermium")));
+        assert_eq!(releases[3].lts, Lts::No(false));
+
+        let on = |channel: &str| -> Vec<&str> {
+            releases
+                .iter()
+                .filter(|r| r.is_on(channel))
+                .map(|r| r.version.as_str())
+                .collect()
+        };
+        assert_eq!(on("current").len(), 4);
+        assert_eq!(on("lts"), vec!["v14.15.1", "v12.19.1"]);
+        assert_eq!(on("erbium"), vec!["v12.19.1"]);
+        assert_eq!(on("15"), vec!["v15.2.1"]);
+        assert!(on("argon").is_empty());
     }
 
     #[test]
-    fn os_is_not_macos_or_windows() {
-        assert_ne!(os(), "macos");
-        assert_ne!(os(), "windows");
+    fn latest_version_found() {
+        let version = latest_version(Platform::current(), None).expect("must fetch");
+        assert!(version.starts_with('v'));
     }
 }
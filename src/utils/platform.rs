@@ -0,0 +1,445 @@
+use std::{
+    env::consts::{ARCH, OS},
+    fmt, fs,
+    path::Path,
+    str::{self, FromStr},
+    sync::OnceLock,
+};
+
+use crate::{lib::version::Version, utils};
+
+const LOADER_DIRS: &[&str] = &["/lib", "/lib64", "/usr/lib"];
+
+static CURRENT: OnceLock<Platform> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Os {
+    Linux,
+    MacOS,
+    Windows,
+    Other(&'static str),
+}
+
+impl Os {
+    // as_str() matches std::env::consts::OS
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Os::Linux => "linux",
+            Os::MacOS => "macos",
+            Os::Windows => "windows",
+            Os::Other(os) => os,
+        }
+    }
+
+    fn from_consts(os: &'static str) -> Os {
+        match os {
+            "linux" => Os::Linux,
+            "macos" => Os::MacOS,
+            "windows" => Os::Windows,
+            _ => Os::Other(os),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arch {
+    Aarch64,
+    Armv7,
+    X86,
+    X86_64,
+    Other(&'static str),
+}
+
+impl Arch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Arch::Aarch64 => "aarch64",
+            Arch::Armv7 => "armv7",
+            Arch::X86 => "x86",
+            Arch::X86_64 => "x86_64",
+            Arch::Other(arch) => arch,
+        }
+    }
+
+    // from_consts() trusts our own build's architecture,
+    // as that is what the userland can run (e.g. 32-bit Raspbian on a 64-bit CPU)
+    fn from_consts(arch: &'static str) -> Arch {
+        match arch {
+            "aarch64" => Arch::Aarch64,
+            "arm" => Arch::Armv7,
+            "x86" => Arch::X86,
+            "x86_64" => Arch::X86_64,
+            _ => Arch::Other(arch),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Libc {
+    Glibc,
+    Musl,
+    System, // i.e. not Linux, so there is only the one
+}
+
+impl Libc {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Libc::Glibc => "glibc",
+            Libc::Musl => "musl",
+            Libc::System => "system",
+        }
+    }
+}
+
+// Platform is what we download binaries for,
+// with helpers for each ecosystem's spelling of it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Platform {
+    pub arch: Arch,
+    pub glibc_version: Option<Version>,
+    pub libc: Libc,
+    pub os: Os,
+}
+
+impl Platform {
+    // current() detects the platform once, as this runs a few commands
+    pub fn current() -> &'static Platform {
+        CURRENT.get_or_init(Platform::detect)
+    }
+
+    fn detect() -> Platform {
+        let os = Os::from_consts(OS);
+        let loaders = if os == Os::Linux {
+            find_loaders()
+        } else {
+            Vec::new()
+        };
+        let libc = match os {
+            Os::Linux => libc_from_loaders(&loaders),
+            _ => Libc::System,
+        };
+        let glibc_version = if libc == Libc::Glibc {
+            detect_glibc_version(&loaders)
+        } else {
+            None
+        };
+        Platform {
+            arch: Arch::from_consts(ARCH),
+            glibc_version,
+            libc,
+            os,
+        }
+    }
+
+    pub fn exe_suffix(&self) -> &'static str {
+        match self.os {
+            Os::Windows => ".exe",
+            _ => "",
+        }
+    }
+
+    // go_arch() is GOARCH, which most Go projects use in asset names
+    pub fn go_arch(&self) -> &'static str {
+        match self.arch {
+            Arch::Aarch64 => "arm64",
+            Arch::Armv7 => "arm",
+            Arch::X86 => "386",
+            Arch::X86_64 => "amd64",
+            Arch::Other(arch) => arch,
+        }
+    }
+
+    // go_dl_arch() is for the Go project's own downloads,
+    // which only offer "armv6l" for 32-bit ARM
+    pub fn go_dl_arch(&self) -> &'static str {
+        match self.arch {
+            Arch::Armv7 => "armv6l",
+            _ => self.go_arch(),
+        }
+    }
+
+    // go_os() is GOOS
+    pub fn go_os(&self) -> &'static str {
+        match self.os {
+            Os::MacOS => "darwin",
+            _ => self.os.as_str(),
+        }
+    }
+
+    pub fn is_musl(&self) -> bool {
+        self.libc == Libc::Musl
+    }
+
+    pub fn node_arch(&self) -> &'static str {
+        match self.arch {
+            Arch::Aarch64 => "arm64",
+            Arch::Armv7 => "armv7l",
+            Arch::X86 => "x86",
+            Arch::X86_64 => "x64",
+            Arch::Other(arch) => arch,
+        }
+    }
+
+    pub fn node_os(&self) -> &'static str {
+        match self.os {
+            Os::MacOS => "darwin",
+            Os::Windows => "win",
+            _ => self.os.as_str(),
+        }
+    }
+
+    // node_release_os() is for the "files" in index.json,
+    // which are a bit different again :shrug:
+    pub fn node_release_os(&self) -> &'static str {
+        match self.os {
+            Os::MacOS => "osx",
+            _ => self.node_os(),
+        }
+    }
+
+    // node_target() is e.g. "linux-x64",
+    // or "linux-x64-musl" as on unofficial-builds.nodejs.org
+    pub fn node_target(&self) -> String {
+        format!(
+            "{}-{}{}",
+            self.node_os(),
+            self.node_arch(),
+            if self.is_musl() { "-musl" } else { "" }
+        )
+    }
+
+    // rust_target() is the target triple, e.g. "x86_64-unknown-linux-gnu"
+    pub fn rust_target(&self) -> String {
+        let arch = match self.arch {
+            Arch::X86 => "i686",
+            _ => self.arch.as_str(),
+        };
+        match self.os {
+            Os::Linux => {
+                let abi = match (self.arch, self.libc) {
+                    (Arch::Armv7, Libc::Musl) => "musleabihf",
+                    (Arch::Armv7, _) => "gnueabihf",
+                    (_, Libc::Musl) => "musl",
+                    _ => "gnu",
+                };
+                format!("{}-unknown-linux-{}", arch, abi)
+            }
+            Os::MacOS => format!("{}-apple-darwin", arch),
+            Os::Windows => format!("{}-pc-windows-msvc", arch),
+            Os::Other(os) => format!("{}-unknown-{}", arch, os),
+        }
+    }
+}
+
+// from_str() accepts e.g. "linux-aarch64-musl", "linux-armv7", or "macos-x86_64",
+// with the GOOS / GOARCH spellings also allowed, e.g. "darwin-arm64"
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        let parts: Vec<String> = s.split('-').map(|p| p.to_lowercase()).collect();
+        let os = match parts.first().map(String::as_str) {
+            Some("linux") => Os::Linux,
+            Some("darwin") | Some("macos") => Os::MacOS,
+            Some("windows") => Os::Windows,
+            _ => return Err(format!("unknown OS in platform: {}", s)),
+        };
+        let arch = match parts.get(1).map(String::as_str) {
+            Some("aarch64") | Some("arm64") => Arch::Aarch64,
+            Some("arm") | Some("armv7") => Arch::Armv7,
+            Some("386") | Some("x86") => Arch::X86,
+            Some("amd64") | Some("x86_64") => Arch::X86_64,
+            _ => return Err(format!("unknown architecture in platform: {}", s)),
+        };
+        let mut glibc_version = None;
+        let libc = match (os, parts.get(2).map(String::as_str)) {
+            (Os::Linux, None) | (Os::Linux, Some("gnu")) => Libc::Glibc,
+            (Os::Linux, Some(l)) if l.starts_with("glibc") => {
+                // as per our Display, e.g. "glibc2.31.0"
+                glibc_version = Version::parse(&l["glibc".len()..]);
+                Libc::Glibc
+            }
+            (Os::Linux, Some("musl")) => Libc::Musl,
+            (_, None) => Libc::System,
+            _ => return Err(format!("unknown libc in platform: {}", s)),
+        };
+        Ok(Platform {
+            arch,
+            glibc_version,
+            libc,
+            os,
+        })
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.os.as_str(), self.arch.as_str())?;
+        match (self.libc, &self.glibc_version) {
+            (Libc::System, _) => Ok(()),
+            (Libc::Glibc, Some(v)) => write!(f, "-glibc{}", v),
+            (libc, _) => write!(f, "-{}", libc.as_str()),
+        }
+    }
+}
+
+fn detect_glibc_version(loaders: &[String]) -> Option<Version> {
+    // the loader itself is executable, and `ldd` is a fallback
+    let commands = loaders
+        .iter()
+        .filter(|l| l.starts_with("ld-linux"))
+        .flat_map(|l| LOADER_DIRS.iter().map(move |d| Path::new(d).join(l)))
+        .filter(|p| p.is_file())
+        .map(|p| p.to_string_lossy().into_owned())
+        .chain(std::iter::once(String::from("ldd")));
+    for cmd in commands {
+        if let Ok(output) = utils::process::command_output(&cmd, &["--version"]) {
+            let stdout = str::from_utf8(&output.stdout).unwrap_or_default();
+            if let Some(v) = parse_glibc_version(stdout) {
+                return Some(v);
+            }
+        }
+    }
+    None
+}
+
+fn find_loaders() -> Vec<String> {
+    LOADER_DIRS
+        .iter()
+        .filter_map(|d| fs::read_dir(d).ok())
+        .flat_map(|entries| entries.filter_map(|e| e.ok()))
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|n| n.starts_with("ld-"))
+        .collect()
+}
+
+// libc_from_loaders() looks for e.g. "ld-musl-x86_64.so.1",
+// and otherwise assumes glibc, e.g. "ld-linux-x86-64.so.2"
+fn libc_from_loaders(loaders: &[String]) -> Libc {
+    let has_musl = loaders.iter().any(|l| l.starts_with("ld-musl-"));
+    let has_glibc = loaders.iter().any(|l| l.starts_with("ld-linux"));
+    if has_musl && !has_glibc {
+        Libc::Musl
+    } else {
+        Libc::Glibc
+    }
+}
+
+// parse_glibc_version() expects output like "ldd (GNU libc) 2.31",
+// or "ld.so (Ubuntu GLIBC 2.31-0ubuntu9) stable release version 2.31."
+fn parse_glibc_version(stdout: &str) -> Option<Version> {
+    let first = stdout.lines().next()?;
+    let upper = first.to_uppercase();
+    if !upper.contains("GNU LIBC") && !upper.contains("GLIBC") {
+        return None;
+    }
+    Version::parse(first.split_whitespace().last()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linux(arch: Arch, libc: Libc) -> Platform {
+        Platform {
+            arch,
+            glibc_version: None,
+            libc,
+            os: Os::Linux,
+        }
+    }
+
+    #[test]
+    fn libc_from_loaders_prefers_glibc() {
+        let names = |ns: &[&str]| ns.iter().map(|n| String::from(*n)).collect::<Vec<_>>();
+        assert_eq!(
+            libc_from_loaders(&names(&["ld-musl-x86_64.so.1"])),
+            Libc::Musl
+        );
+        assert_eq!(
+            libc_from_loaders(&names(&["ld-linux-x86-64.so.2"])),
+            Libc::Glibc
+        );
+        // e.g. Debian with the musl package installed
+        assert_eq!(
+            libc_from_loaders(&names(&["ld-linux-armhf.so.3", "ld-musl-armhf.so.1"])),
+            Libc::Glibc
+        );
+        assert_eq!(libc_from_loaders(&[]), Libc::Glibc);
+    }
+
+    #[test]
+    fn parse_glibc_version_from_ldd_and_loader() {
+        let v = |s| Version::parse(s);
+        assert_eq!(
+            parse_glibc_version("ldd (GNU libc) 2.32\nCopyright (C) 2020"),
+            v("2.32")
+        );
+        assert_eq!(
+            parse_glibc_version(
+                "ld.so (Ubuntu GLIBC 2.31-0ubuntu9.1) stable release version 2.31.\n"
+            ),
+            v("2.31")
+        );
+        assert_eq!(
+            parse_glibc_version("musl libc (x86_64)\nVersion 1.2.1"),
+            None
+        );
+    }
+
+    #[test]
+    fn per_ecosystem_names() {
+        let pi = linux(Arch::Armv7, Libc::Glibc);
+        assert_eq!(pi.go_arch(), "arm");
+        assert_eq!(pi.go_dl_arch(), "armv6l");
+        assert_eq!(pi.node_target(), "linux-armv7l");
+        assert_eq!(pi.rust_target(), "armv7-unknown-linux-gnueabihf");
+
+        let alpine = linux(Arch::X86_64, Libc::Musl);
+        assert_eq!(alpine.go_arch(), "amd64");
+        assert_eq!(alpine.node_target(), "linux-x64-musl");
+        assert_eq!(alpine.rust_target(), "x86_64-unknown-linux-musl");
+
+        let mac = Platform {
+            arch: Arch::Aarch64,
+            glibc_version: None,
+            libc: Libc::System,
+            os: Os::MacOS,
+        };
+        assert_eq!(mac.go_os(), "darwin");
+        assert_eq!(mac.go_arch(), "arm64");
+        assert_eq!(mac.node_release_os(), "osx");
+        assert_eq!(mac.rust_target(), "aarch64-apple-darwin");
+        assert_eq!(mac.to_string(), "macos-aarch64");
+    }
+
+    #[test]
+    fn from_str_accepts_common_spellings() {
+        assert_eq!(
+            "linux-aarch64-musl".parse::<Platform>(),
+            Ok(linux(Arch::Aarch64, Libc::Musl))
+        );
+        assert_eq!(
+            "linux-armv7".parse::<Platform>(),
+            Ok(linux(Arch::Armv7, Libc::Glibc))
+        );
+        let glibc = "linux-x86_64-glibc2.31.0"
+            .parse::<Platform>()
+            .expect("must parse");
+        assert_eq!(glibc.glibc_version, Version::parse("2.31"));
+        assert_eq!(glibc.to_string(), "linux-x86_64-glibc2.31.0");
+        let mac = "darwin-arm64".parse::<Platform>().expect("must parse");
+        assert_eq!(mac.to_string(), "macos-aarch64");
+        assert!("linux".parse::<Platform>().is_err());
+        assert!("macos-x86_64-musl".parse::<Platform>().is_err());
+        assert!("plan9-x86_64".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn current_is_not_rust_naming() {
+        let current = Platform::current();
+        assert_ne!(current.go_arch(), "x86_64");
+        assert_ne!(current.go_os(), "macos");
+        assert_ne!(current.node_os(), "windows");
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+// Progress draws a single-line progress bar on stderr,
+// but only when stderr is a terminal
+pub struct Progress {
+    done: u64,
+    enabled: bool,
+    label: String,
+    last_draw: Option<Instant>,
+    started: Instant,
+    total: Option<u64>,
+}
+
+impl Progress {
+    pub fn new<S>(label: S, done: u64, total: Option<u64>) -> Progress
+    where
+        S: AsRef<str>,
+    {
+        Progress {
+            done,
+            enabled: io::stderr().is_terminal(),
+            label: String::from(label.as_ref()),
+            last_draw: None,
+            started: Instant::now(),
+            total,
+        }
+    }
+
+    pub fn add(&mut self, bytes: u64) {
+        self.done += bytes;
+        if !self.enabled {
+            return;
+        }
+        if let Some(last) = self.last_draw {
+            if last.elapsed() < REDRAW_INTERVAL {
+                return;
+            }
+        }
+        self.draw();
+    }
+
+    pub fn finish(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.draw();
+        eprintln!();
+    }
+
+    fn draw(&mut self) {
+        self.last_draw = Some(Instant::now());
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            (self.done as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let line = match self.total {
+            Some(total) if total > 0 => format!(
+                "{}: {} / {} ({}%) {}/s",
+                self.label,
+                format_bytes(self.done),
+                format_bytes(total),
+                self.done * 100 / total,
+                format_bytes(rate),
+            ),
+            _ => format!(
+                "{}: {} {}/s",
+                self.label,
+                format_bytes(self.done),
+                format_bytes(rate)
+            ),
+        };
+        let mut stderr = io::stderr();
+        // progress is cosmetic, so ignore errors here
+        let _ = write!(stderr, "\r\x1b[2K{}", line);
+        let _ = stderr.flush();
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_bytes_uses_binary_units() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(100 * 1024 * 1024), "100.0 MiB");
+    }
+}
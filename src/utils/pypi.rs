@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+use serde_derive::Deserialize;
+
+use crate::{
+    lib::{
+        mirrors,
+        pep440::{SpecifierSet, Version},
+    },
+    utils,
+};
+
+// ProjectResponse is the part of PyPI's JSON API that we need,
+// see https://warehouse.pypa.io/api-reference/json.html
+#[derive(Debug, Deserialize)]
+struct ProjectResponse {
+    releases: HashMap<String, Vec<ReleaseFile>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseFile {
+    #[serde(default)]
+    yanked: bool,
+}
+
+// latest_version() is the newest release of `name` that `specifiers` allow
+pub fn latest_version(name: &str, specifiers: &SpecifierSet) -> io::Result<Option<Version>> {
+    let url = format!("{}/{}/json", mirrors::pypi(), normalize_name(name));
+    let mut res = utils::http::fetch_request(utils::http::create_request(url, None))?;
+    let mut body = String::new();
+    res.read_to_string(&mut body)?;
+    let project: ProjectResponse = serde_json::from_str(&body)?;
+    Ok(pick_latest(&project, specifiers))
+}
+
+// normalize_name() is per PEP 503, e.g. "PyYAML", "py_yaml", and "py.yaml" are "pyyaml" / "py-yaml"
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        if c == '-' || c == '_' || c == '.' {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
+            }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+// pick_latest() skips releases without files or with only yanked files,
+// and prereleases unless `specifiers` ask for them
+fn pick_latest(project: &ProjectResponse, specifiers: &SpecifierSet) -> Option<Version> {
+    let prereleases = specifiers.allows_prereleases();
+    project
+        .releases
+        .iter()
+        .filter(|(_, files)| files.iter().any(|f| !f.yanked))
+        .filter_map(|(v, _)| Version::parse(v))
+        .filter(|v| prereleases || !v.is_prerelease())
+        .filter(|v| specifiers.contains(v))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: &str = r#"{"info":{"name":"black","version":"20.8b1"},"releases":{
+        "19.3b0":[{"yanked":false}],
+        "19.10b0":[{"yanked":false}],
+        "20.8b0":[{"yanked":true}],
+        "20.8b1":[{"yanked":false}],
+        "21.0":[],
+        "1.0":[{"yanked":false}]
+    }}"#;
+
+    #[test]
+    fn normalize_names_per_pep_503() {
+        assert_eq!(normalize_name("PyYAML"), "pyyaml");
+        assert_eq!(normalize_name("py_yaml"), "py-yaml");
+        assert_eq!(normalize_name("Py.-_YAML"), "py-yaml");
+    }
+
+    #[test]
+    fn pick_latest_skips_yanked_empty_and_prereleases() {
+        let project: ProjectResponse = serde_json::from_str(BLACK).expect("must parse");
+        let set = |s| SpecifierSet::parse(s).expect("must parse");
+        assert_eq!(pick_latest(&project, &set("")), Version::parse("1.0"));
+        assert_eq!(
+            pick_latest(&project, &set(">=19.10b0")),
+            Version::parse("20.8b1")
+        );
+        assert_eq!(
+            pick_latest(&project, &set("==19.3b0")),
+            Version::parse("19.3b0")
+        );
+        assert_eq!(pick_latest(&project, &set(">2")), None);
+    }
+}